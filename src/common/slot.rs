@@ -1,4 +1,4 @@
-#[derive(Copy, Clone, PartialEq, Eq, Ord, PartialOrd, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Ord, PartialOrd, Hash, Debug)]
 pub enum Slot {
     Init(usize),
     UnInit,
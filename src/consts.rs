@@ -1,4 +1,9 @@
 pub(crate) const INTEGER_BYTES: usize = 4;
+pub(crate) const LONG_BYTES: usize = 8;
+/// Bytes at the end of every data block holding the LSN of the last logged change of the block
+pub(crate) const PAGE_LSN_BYTES: usize = LONG_BYTES;
+/// Name of the write-ahead log file in the database directory
+pub(crate) const LOG_FILE: &str = "wal.log";
 
 /// The message hopefully you will never see
 pub(crate) const GULAG_MSG: &str = "This message will be displayed in the gulag";
@@ -2,12 +2,50 @@
 pub enum DbError {
     #[error("Failed to acquire lock, Lock timeout exceeded")]
     LockAborted,
+    #[error("Transaction {0} was aborted to prevent a deadlock")]
+    DeadlockAborted(u64),
+    #[error("Transaction {0} cannot change a record changed by a concurrent transaction")]
+    WriteConflict(u64),
+    #[error("Transaction {0} was aborted")]
+    TransactionAborted(u64),
+    #[error("Transaction {0} is not running")]
+    TransactionNotFound(u64),
+    #[error("Transaction {0} is read only and cannot change the database")]
+    ReadOnlyTransaction(u64),
+    #[error("Transaction {0} is prepared, it can only be committed or rolled back")]
+    TransactionPrepared(u64),
+    #[error("Prepared transaction not found: {0}")]
+    PreparedTransactionNotFound(String),
+    #[error("Global transaction id already in use: {0}")]
+    DuplicateGid(String),
     #[error("IO error occurred {0:?}")]
     IoError(#[from] std::io::Error),
     #[error("Value from Option was invalid")]
     InvalidValue,
     #[error("Cannot find the field in the schema")]
     SchemaFieldNotFound,
+    #[error("Unknown field type: {0}")]
+    UnknownFieldType(u32),
+    #[error("Value does not fit the decimal field {0}")]
+    DecimalOverflow(String),
+    #[error("Unknown record format: {0}")]
+    UnknownRecordFormat(u32),
+    #[error("Record does not fit in its block")]
+    RecordDoesNotFit,
+    #[error("Field {0} cannot be NULL")]
+    FieldNotNullable(String),
+    #[error("Value of field {0} is too large to be stored")]
+    ValueTooLarge(String),
+    #[error("Field {0} is NULL")]
+    NullValue(String),
+    #[error("Field {0} does not hold a value of the requested type")]
+    FieldTypeMismatch(String),
+    #[error("Field {0} is not a TEXT or BLOB field")]
+    NotOverflowField(String),
+    #[error("Field {0} does not hold valid UTF-8 text")]
+    InvalidText(String),
+    #[error("Overflow chain in {0} ends before its value")]
+    BrokenOverflowChain(String),
     #[error("Offsets were available")]
     OffsetNotFound,
     #[error("Not slot were available")]
@@ -18,8 +56,26 @@ pub enum DbError {
     TableNotFound { table_name: String },
     #[error("View not found: {0}")]
     ViewNotFound(String),
+    #[error("Savepoint not found: {0}")]
+    SavepointNotFound(String),
+    #[error("Unknown log operation: {0}")]
+    UnknownLogOperation(i32),
+    #[error("Log record version {0} is not supported")]
+    UnsupportedLogVersion(i32),
+    #[error("Log record of {0} bytes is corrupt")]
+    CorruptLogRecord(usize),
+    #[error("Recovery target is before the end of the backup at LSN {0}")]
+    RecoveryTargetBeforeBackup(u64),
     #[error("Unexpected Error")]
     Unexpected,
 }
 
+impl DbError {
+    /// Whether the transaction failed only because of concurrent transactions, running it
+    /// again might succeed
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Self::LockAborted | Self::DeadlockAborted(_))
+    }
+}
+
 pub type DbResult<T> = Result<T, DbError>;
@@ -1,31 +1,84 @@
+#[cfg(test)]
+use crate::storage::disk::fault::FaultInjector;
 use crate::{
-    error::DbResult, storage::bufferpool::pool::BufferPoolManager, storage::disk::manager::Manager,
-    storage::log::manager::LogManager, storage::tx::Transactions,
+    consts::LOG_FILE,
+    error::DbResult,
+    storage::backup::{self, BackupLabel, RecoveryTarget},
+    storage::bufferpool::pool::BufferPoolManager,
+    storage::disk::manager::Manager,
+    storage::log::manager::LogManager,
+    storage::tx::DeadlockPolicy,
+    storage::tx::IsolationLevel,
+    storage::tx::{
+        Checkpointer, Transactions, TxGuard, TxInfo, TxManager, DEFAULT_TRANSACTION_RETRIES,
+        RETRY_BACKOFF_MS,
+    },
+    utils::safe_lock::SafeLock,
+};
+use rand::Rng;
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
 };
-use std::sync::{Arc, Mutex};
 
+/// Nothing is printed by the server, it reports what it does through `tracing`. Transactions,
+/// buffer pins, lock waits, log flushes and recovery get their own spans, the application
+/// decides what's kept by installing a subscriber
 pub struct DBServer {
     pub file_manager: Arc<Manager>,
     pub log_manager: Arc<Mutex<LogManager>>,
     pub buffer_manager: Arc<Mutex<BufferPoolManager>>,
+    tx_manager: Arc<TxManager>,
+    /// Takes the periodic checkpoints, if configured
+    checkpointer: Option<Checkpointer>,
+    deadlock_policy: DeadlockPolicy,
+    lock_timeout: Option<Duration>,
+    lock_escalation_threshold: Option<usize>,
+    transaction_retries: usize,
 }
 
 impl DBServer {
     pub fn new_with_params(options: DBServerOptions) -> std::io::Result<Self> {
-        let fm = Arc::new(Manager::new(options.directory, options.block_size));
+        let fm = Manager::new(options.directory, options.block_size);
+        #[cfg(test)]
+        let fm = fm.with_faults(options.faults);
+        let fm = Arc::new(fm);
         let log_manager = Arc::new(Mutex::new(LogManager::new(
             fm.clone(),
-            "wal.log".to_string(),
+            LOG_FILE.to_string(),
         )?));
 
+        let buffer_manager = Arc::new(Mutex::new(BufferPoolManager::new(
+            fm.clone(),
+            log_manager.clone(),
+            options.pool_size as u32,
+        )));
+        let tx_manager = Arc::new(TxManager::recover(log_manager.clone()));
+        let checkpointer = options
+            .checkpoint_interval
+            .map(|interval| {
+                Checkpointer::start(
+                    interval,
+                    log_manager.clone(),
+                    buffer_manager.clone(),
+                    tx_manager.clone(),
+                )
+            })
+            .transpose()?;
+
         Ok(Self {
             file_manager: fm.clone(),
             log_manager: log_manager.clone(),
-            buffer_manager: Arc::new(Mutex::new(BufferPoolManager::new(
-                fm.clone(),
-                log_manager.clone(),
-                options.pool_size as u32,
-            ))),
+            buffer_manager,
+            tx_manager,
+            checkpointer,
+            deadlock_policy: options.deadlock_policy,
+            lock_timeout: options.lock_timeout,
+            lock_escalation_threshold: options.lock_escalation_threshold,
+            transaction_retries: options
+                .transaction_retries
+                .unwrap_or(DEFAULT_TRANSACTION_RETRIES),
         })
     }
     pub fn log_manager(&self) -> Arc<Mutex<LogManager>> {
@@ -40,13 +93,185 @@ impl DBServer {
         self.file_manager.clone()
     }
 
+    /// Locks and other state shared by the transactions of this server
+    pub fn tx_manager(&self) -> Arc<TxManager> {
+        self.tx_manager.clone()
+    }
+
     pub fn new_tx(&self) -> DbResult<Transactions> {
-        Transactions::new(
+        self.new_tx_with(TxOptions::default())
+    }
+
+    /// Starts a transaction with its own settings, e.g a lower isolation level for a report
+    /// which does not need a consistent view of the data
+    pub fn new_tx_with(&self, options: TxOptions) -> DbResult<Transactions> {
+        let new = if options.read_only {
+            Transactions::new_read_only
+        } else {
+            Transactions::new
+        };
+        let mut tx = new(
             self.file_manager.clone(),
             self.buffer_manager.clone(),
             self.log_manager.clone(),
+            self.tx_manager.clone(),
+            self.deadlock_policy,
+        )?;
+
+        if let Some(timeout) = self.lock_timeout {
+            tx.set_lock_timeout(timeout);
+        }
+
+        if let Some(threshold) = self.lock_escalation_threshold {
+            tx.set_lock_escalation_threshold(threshold);
+        }
+
+        tx.set_isolation_level(options.isolation_level);
+        if options.snapshot {
+            tx.begin_snapshot();
+        }
+
+        Ok(tx)
+    }
+
+    /// Starts a transaction which is rolled back unless it's committed before the returned
+    /// guard is dropped
+    pub fn begin(&self) -> DbResult<TxGuard> {
+        self.begin_with(TxOptions::default())
+    }
+
+    pub fn begin_with(&self, options: TxOptions) -> DbResult<TxGuard> {
+        self.new_tx_with(options).map(TxGuard::new)
+    }
+
+    /// Runs `f` in a new transaction, committing it if `f` succeeds and rolling it back
+    /// otherwise. A transaction which failed because of a lock timeout or a deadlock is run
+    /// again from the start after a short random pause, up to the configured number of
+    /// retries. Every attempt keeps the age of the first one for the deadlock policy
+    pub fn transaction<T>(
+        &self,
+        mut f: impl FnMut(&mut Transactions) -> DbResult<T>,
+    ) -> DbResult<T> {
+        let mut retries = 0;
+        let mut age = None;
+        loop {
+            let mut tx = self.begin()?;
+            match age {
+                Some(age) => tx.set_age(age),
+                None => age = Some(tx.txnum()),
+            }
+
+            match f(&mut tx) {
+                Ok(val) => {
+                    tx.commit()?;
+                    return Ok(val);
+                }
+                Err(e) if e.is_retryable() && retries < self.transaction_retries => {
+                    retries += 1;
+                    tx.rollback()?;
+
+                    // Transactions which failed together don't all run into each other again
+                    let backoff = rand::rng().random_range(0..=RETRY_BACKOFF_MS * retries as u64);
+                    thread::sleep(Duration::from_millis(backoff));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Lists the transactions running on the server along with the locks and buffers they
+    /// hold, oldest first
+    pub fn transactions(&self) -> Vec<TxInfo> {
+        self.tx_manager.transactions()
+    }
+
+    /// Rolls back a running transaction and releases its locks, see `TxManager::abort`
+    pub fn abort_tx(&self, txnum: u64) -> DbResult<()> {
+        self.tx_manager.abort(txnum)
+    }
+
+    /// Global ids of the prepared transactions waiting for their outcome
+    pub fn prepared(&self) -> Vec<String> {
+        self.tx_manager.prepared()
+    }
+
+    pub fn commit_prepared(&self, gid: &str) -> DbResult<()> {
+        self.tx_manager.commit_prepared(gid)
+    }
+
+    pub fn rollback_prepared(&self, gid: &str) -> DbResult<()> {
+        self.tx_manager.rollback_prepared(gid)
+    }
+
+    /// Logs a checkpoint right away, the running transactions go on meanwhile
+    pub fn checkpoint(&self) -> DbResult<()> {
+        self.tx_manager
+            .checkpoint(self.log_manager.clone(), &self.buffer_manager)?;
+        Ok(())
+    }
+
+    /// Copies the data files and the log to the directory `path` while the transactions keep
+    /// running, see `backup::backup`. The copy is brought to a consistent state by `restore`
+    pub fn backup_to(&self, path: &str) -> DbResult<BackupLabel> {
+        let start_lsn = self
+            .tx_manager
+            .checkpoint(self.log_manager.clone(), &self.buffer_manager)?;
+        backup::backup(
+            &self.file_manager,
+            &self.log_manager,
+            &self.buffer_manager,
+            start_lsn,
+            path,
         )
     }
+
+    /// Creates the database in the directory of `options` from the backup at `backup`. The
+    /// changes the copied blocks miss are redone and the transactions which were running at
+    /// the end of the backup are rolled back
+    pub fn restore(backup: &str, options: DBServerOptions) -> DbResult<Self> {
+        let label = backup::restore(backup, &options.directory)?;
+        Self::recover_restored(label, options)
+    }
+
+    /// Copies the log to the directory `path`, see `backup::archive_log`
+    pub fn archive_log_to(&self, path: &str) -> DbResult<u64> {
+        backup::archive_log(&self.file_manager, &self.log_manager, path)
+    }
+
+    /// Like `restore` but replays the log archived at `archive` up to `target`, bringing the
+    /// database back to how it was at that point, e.g right before a bad batch job ran
+    pub fn restore_to(
+        backup: &str,
+        archive: &str,
+        target: RecoveryTarget,
+        options: DBServerOptions,
+    ) -> DbResult<Self> {
+        let label = backup::restore_to(
+            backup,
+            archive,
+            target,
+            &options.directory,
+            options.block_size,
+        )?;
+        Self::recover_restored(label, options)
+    }
+
+    fn recover_restored(label: BackupLabel, options: DBServerOptions) -> DbResult<Self> {
+        let db = Self::new_with_params(options)?;
+        db.new_tx()?.recover_backup(label.start_lsn)?;
+        Ok(db)
+    }
+}
+
+/// A clean shutdown writes back the blocks left in the buffer pool, only after a crash are the
+/// committed changes brought back by `Transactions::recover`
+impl Drop for DBServer {
+    fn drop(&mut self) {
+        self.checkpointer.take();
+        if let Err(e) = self.buffer_manager.safe_lock().flush_dirty() {
+            tracing::error!(error = %e, "failed to flush the buffer pool on shutdown");
+        }
+    }
 }
 
 #[derive(Default)]
@@ -54,6 +279,13 @@ pub struct DBServerOptions {
     directory: String,
     block_size: u64,
     pool_size: usize,
+    deadlock_policy: DeadlockPolicy,
+    lock_timeout: Option<Duration>,
+    lock_escalation_threshold: Option<usize>,
+    transaction_retries: Option<usize>,
+    checkpoint_interval: Option<Duration>,
+    #[cfg(test)]
+    faults: Option<Arc<FaultInjector>>,
 }
 
 impl DBServerOptions {
@@ -71,4 +303,74 @@ impl DBServerOptions {
         self.pool_size = pool_size;
         self
     }
+
+    /// Scheme used to resolve conflicting lock requests, defaults to waiting with a timeout
+    pub fn deadlock_policy(mut self, policy: DeadlockPolicy) -> Self {
+        self.deadlock_policy = policy;
+        self
+    }
+
+    /// Time a transaction waits for a lock before it's aborted, defaults to 10 seconds.
+    /// It can be changed for a single transaction with `Transactions::set_lock_timeout`
+    pub fn lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = Some(timeout);
+        self
+    }
+
+    /// Number of block locks a transaction can hold on a single file before they are
+    /// escalated to a lock on the whole file, defaults to 1000
+    pub fn lock_escalation_threshold(mut self, threshold: usize) -> Self {
+        self.lock_escalation_threshold = Some(threshold);
+        self
+    }
+
+    /// Number of times `DBServer::transaction` runs a transaction again after it failed on a
+    /// lock, defaults to 3
+    pub fn transaction_retries(mut self, retries: usize) -> Self {
+        self.transaction_retries = Some(retries);
+        self
+    }
+
+    /// Takes a checkpoint this often on a background thread, by default checkpoints are only
+    /// taken by recovery and `DBServer::checkpoint`
+    pub fn checkpoint_interval(mut self, interval: Duration) -> Self {
+        self.checkpoint_interval = Some(interval);
+        self
+    }
+
+    /// Simulates a crash at some point of the disk writes, see `FaultInjector`
+    #[cfg(test)]
+    pub(crate) fn fault_injector(mut self, faults: Arc<FaultInjector>) -> Self {
+        self.faults = Some(faults);
+        self
+    }
+}
+
+/// Settings of a single transaction, see `DBServer::new_tx_with`
+#[derive(Default, Clone)]
+pub struct TxOptions {
+    isolation_level: IsolationLevel,
+    snapshot: bool,
+    read_only: bool,
+}
+
+impl TxOptions {
+    /// How long the transaction holds its read locks, defaults to serializable
+    pub fn isolation_level(mut self, level: IsolationLevel) -> Self {
+        self.isolation_level = level;
+        self
+    }
+
+    /// Reads under snapshot isolation without taking read locks, see
+    /// `Transactions::begin_snapshot`
+    pub fn snapshot(mut self) -> Self {
+        self.snapshot = true;
+        self
+    }
+
+    /// Starts a read only transaction, see `Transactions::new_read_only`
+    pub fn read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
 }
@@ -0,0 +1,232 @@
+use std::{
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use crate::{
+    consts::LOG_FILE,
+    error::{DbError, DbResult},
+    storage::bufferpool::pool::BufferPoolManager,
+    storage::disk::{block::Block, manager::Manager, page::Page},
+    storage::log::manager::{LogManager, LogManagerForwardIterator},
+    storage::tx::{create_log_record, LogOperation},
+    utils::safe_lock::SafeLock,
+};
+
+mod test;
+
+/// File of a backup holding its `BackupLabel`
+pub const BACKUP_LABEL: &str = "backup_label";
+
+/// Range of the log a backup needs to be restored
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BackupLabel {
+    /// LSN of the checkpoint taken when the backup started, recovery of the restored
+    /// database starts from it
+    pub start_lsn: u64,
+    /// LSN of the last record in the copied log, every change in the copied blocks is logged
+    /// up to there
+    pub end_lsn: u64,
+}
+
+impl BackupLabel {
+    pub fn read(dir: &str) -> DbResult<Self> {
+        let label = fs::read_to_string(Path::new(dir).join(BACKUP_LABEL))?;
+        let lsn = |name: &str| {
+            label
+                .lines()
+                .find_map(|line| line.strip_prefix(name)?.trim().parse().ok())
+                .ok_or(DbError::InvalidValue)
+        };
+
+        Ok(Self {
+            start_lsn: lsn("start_lsn")?,
+            end_lsn: lsn("end_lsn")?,
+        })
+    }
+
+    fn write(&self, dir: &str) -> std::io::Result<()> {
+        fs::write(
+            Path::new(dir).join(BACKUP_LABEL),
+            format!("start_lsn {}\nend_lsn {}\n", self.start_lsn, self.end_lsn),
+        )
+    }
+}
+
+/// Copies the files of the database to `path` while the transactions keep running
+///
+/// The blocks are copied one at a time with the buffer pool locked, so no block is copied while
+/// it's written back. The copies might be from any time after the checkpoint at `start_lsn`,
+/// the log is copied last and has every change they could be missing or have too many
+pub fn backup(
+    fm: &Manager,
+    lm: &Mutex<LogManager>,
+    bm: &Mutex<BufferPoolManager>,
+    start_lsn: u64,
+    path: &str,
+) -> DbResult<BackupLabel> {
+    let target = Manager::new(path.to_string(), fm.blocksize());
+    let mut page = Page::new(fm.blocksize());
+
+    for entry in fs::read_dir(fm.directory())? {
+        let entry = entry?;
+        let file = entry.file_name().to_string_lossy().to_string();
+        if !entry.file_type()?.is_file() || file == LOG_FILE {
+            continue;
+        }
+
+        copy_file(fm, &target, &file, &mut page, || bm.safe_lock())?;
+    }
+
+    let end_lsn = archive_log(fm, lm, path)?;
+    let label = BackupLabel { start_lsn, end_lsn };
+    label.write(path)?;
+    tracing::info!(path, start_lsn, end_lsn, "backup finished");
+    Ok(label)
+}
+
+/// Copies the log as it is now to the directory `path` and returns the LSN of its last record.
+/// The log is never truncated, so an archived log holds every record of the backups taken
+/// before it and can replay them forward with `restore_to`
+pub fn archive_log(fm: &Manager, lm: &Mutex<LogManager>, path: &str) -> DbResult<u64> {
+    let target = Manager::new(path.to_string(), fm.blocksize());
+    let mut page = Page::new(fm.blocksize());
+
+    let mut lm = lm.safe_lock();
+    let end_lsn = lm.latest_lsn();
+    lm.flush(end_lsn)?;
+    // Nothing is appended while the log manager is locked
+    copy_file(fm, &target, LOG_FILE, &mut page, || ())?;
+    Ok(end_lsn)
+}
+
+/// Point of the archived log a point-in-time recovery stops at
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RecoveryTarget {
+    /// Replays the records up to and including the one at the LSN
+    Lsn(u64),
+    /// Replays the records up to and including the commit or rollback of the transaction
+    Transaction(u64),
+    /// Replays the records up to the first transaction which committed after the time
+    Time(SystemTime),
+}
+
+/// Copies the files of the backup at `backup` to the empty directory `dir`, the database there
+/// still has to be recovered with `Transactions::recover_backup`
+pub fn restore(backup: &str, dir: &str) -> DbResult<BackupLabel> {
+    let label = BackupLabel::read(backup)?;
+    copy_data_files(backup, dir, &[BACKUP_LABEL])?;
+    Ok(label)
+}
+
+/// Like `restore` but with the log of `archive` cut at `target` instead of the log of the
+/// backup. Recovery rolls back whatever didn't commit before the target, so the database ends
+/// up as it was right then. The target can't be earlier than the end of the backup, the blocks
+/// copied might already have changes up to there
+pub fn restore_to(
+    backup: &str,
+    archive: &str,
+    target: RecoveryTarget,
+    dir: &str,
+    block_size: u64,
+) -> DbResult<BackupLabel> {
+    let label = BackupLabel::read(backup)?;
+    if !Path::new(archive).join(LOG_FILE).is_file() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("No archived log in {archive}"),
+        )
+        .into());
+    }
+
+    let archive = Arc::new(Manager::new(archive.to_string(), block_size));
+    let records = || LogManagerForwardIterator::new(archive.clone(), LOG_FILE.to_string());
+
+    // Finds where the target is first, the records are only read again once it's known the
+    // log can be cut there
+    let mut end_lsn = None;
+    for (lsn, bytes) in records()? {
+        let rec = create_log_record(bytes)?;
+
+        match target {
+            RecoveryTarget::Lsn(target) if lsn > target => break,
+            RecoveryTarget::Time(time) if rec.commit_time().is_some_and(|at| at > time) => break,
+            RecoveryTarget::Transaction(txnum)
+                if rec.tx_number() == txnum as i64
+                    && (rec.op() == LogOperation::Commit || rec.op() == LogOperation::Rollback) =>
+            {
+                end_lsn = Some(lsn);
+                break;
+            }
+            RecoveryTarget::Transaction(_) => {}
+            _ => end_lsn = Some(lsn),
+        }
+    }
+    let end_lsn = match target {
+        RecoveryTarget::Transaction(txnum) => end_lsn.ok_or(DbError::TransactionNotFound(txnum))?,
+        _ => end_lsn.unwrap_or(0),
+    };
+    if end_lsn < label.end_lsn {
+        return Err(DbError::RecoveryTargetBeforeBackup(label.end_lsn));
+    }
+
+    copy_data_files(backup, dir, &[BACKUP_LABEL, LOG_FILE])?;
+    let fm = Arc::new(Manager::new(dir.to_string(), block_size));
+    let mut lm = LogManager::new(fm, LOG_FILE.to_string())?;
+    // The transactions after the target were logged too, their numbers aren't handed out again
+    lm.advance_txnum(LogManager::new(archive.clone(), LOG_FILE.to_string())?.next_txnum());
+    for (_, bytes) in records()?.take(end_lsn as usize) {
+        lm.append(&bytes)?;
+    }
+    lm.flush(end_lsn)?;
+
+    tracing::info!(
+        dir,
+        ?target,
+        end_lsn,
+        "log replayed up to the recovery target"
+    );
+    Ok(label)
+}
+
+/// Copies the files of `from` to the empty directory `to`, except the ones named in `skip`
+fn copy_data_files(from: &str, to: &str, skip: &[&str]) -> DbResult<()> {
+    if fs::read_dir(to).is_ok_and(|mut files| files.next().is_some()) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("Cannot restore into {to}, it's not empty"),
+        )
+        .into());
+    }
+
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if entry.file_type()?.is_file() && !skip.iter().any(|skip| name == *skip) {
+            fs::copy(entry.path(), Path::new(to).join(name))?;
+        }
+    }
+    Ok(())
+}
+
+/// Copies the file a block at a time, reading each block while holding the guard `lock` returns
+fn copy_file<G>(
+    fm: &Manager,
+    target: &Manager,
+    file: &str,
+    page: &mut Page,
+    lock: impl Fn() -> G,
+) -> DbResult<()> {
+    for num in 0..fm.size(file)? {
+        let block = Block::new(file.to_string(), num);
+        {
+            let _guard = lock();
+            fm.read(&block, page)?;
+        }
+        target.write(&block, page)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,223 @@
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        sync::{
+            atomic::{AtomicBool, AtomicI32, Ordering},
+            Arc,
+        },
+        thread,
+        time::{Duration, SystemTime},
+    };
+
+    use crate::{
+        error::DbError,
+        server::{DBServer, DBServerOptions},
+        storage::backup::{BackupLabel, RecoveryTarget},
+        storage::disk::block::Block,
+    };
+
+    const SLOTS: u32 = 5;
+
+    #[test]
+    fn backuptest() {
+        let options = |dir: &str| {
+            DBServerOptions::default()
+                .block_size(400)
+                .directory(dir.to_string())
+                .pool_size(8)
+        };
+        let blocks = (0..2)
+            .map(|num| Block::new("testfile".to_string(), num))
+            .collect::<Vec<_>>();
+
+        // Slot 0 of every block counts the transfers between the other slots of the block,
+        // which always sum up to the same amount
+        let db = Arc::new(
+            DBServer::new_with_params(
+                options("backupdb").checkpoint_interval(Duration::from_millis(5)),
+            )
+            .unwrap(),
+        );
+        db.transaction(|tx| {
+            // Copying the blocks after the ones changed takes a while
+            let last = Block::new("testfile".to_string(), 2000);
+            tx.pin(&last)?;
+            tx.set_int(&last, 0, 1, true)?;
+            tx.unpin(&last)?;
+            for blk in &blocks {
+                tx.pin(blk)?;
+                for slot in 1..=SLOTS {
+                    tx.set_int(blk, slot * 4, 100, true)?;
+                }
+                tx.unpin(blk)?;
+            }
+            Ok(())
+        })
+        .unwrap();
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let workers = blocks
+            .iter()
+            .map(|blk| {
+                let (db, stop, blk) = (db.clone(), stop.clone(), blk.clone());
+                let committed = Arc::new(AtomicI32::new(0));
+                let count = committed.clone();
+                let handle = thread::spawn(move || {
+                    let mut i = 0;
+                    while !stop.load(Ordering::SeqCst) {
+                        i += 1;
+                        let (from, to) = (i % SLOTS + 1, (i + 1) % SLOTS + 1);
+                        db.transaction(|tx| {
+                            tx.pin(&blk)?;
+                            let transfers = tx.get_int(&blk, 0)?;
+                            let val = tx.get_int(&blk, from * 4)?;
+                            tx.set_int(&blk, from * 4, val - 1, true)?;
+                            let val = tx.get_int(&blk, to * 4)?;
+                            tx.set_int(&blk, to * 4, val + 1, true)?;
+                            tx.set_int(&blk, 0, transfers + 1, true)?;
+                            tx.unpin(&blk)
+                        })
+                        .unwrap();
+                        count.fetch_add(1, Ordering::SeqCst);
+
+                        // The blocks on disk keep changing while they are copied and the
+                        // checkpoints logged meanwhile don't need the changes made before
+                        db.buffer_manager().lock().unwrap().flush_dirty().unwrap();
+                        db.checkpoint().unwrap();
+                    }
+                });
+                (handle, committed)
+            })
+            .collect::<Vec<_>>();
+
+        thread::sleep(Duration::from_millis(50));
+        let before = workers
+            .iter()
+            .map(|(_, committed)| committed.load(Ordering::SeqCst))
+            .collect::<Vec<_>>();
+        let label = db.backup_to("backupcopy").unwrap();
+        thread::sleep(Duration::from_millis(50));
+        stop.store(true, Ordering::SeqCst);
+        let after = workers
+            .into_iter()
+            .map(|(handle, committed)| {
+                handle.join().unwrap();
+                committed.load(Ordering::SeqCst)
+            })
+            .collect::<Vec<_>>();
+        drop(db);
+
+        assert!(label.start_lsn <= label.end_lsn);
+        assert_eq!(BackupLabel::read("backupcopy").unwrap(), label);
+
+        let db = DBServer::restore("backupcopy", options("backuprestored")).unwrap();
+        let mut tx = db.new_tx().unwrap();
+        for (i, blk) in blocks.iter().enumerate() {
+            tx.pin(blk).unwrap();
+            let transfers = tx.get_int(blk, 0).unwrap();
+            assert!((before[i]..=after[i]).contains(&transfers));
+
+            let total = (1..=SLOTS)
+                .map(|slot| tx.get_int(blk, slot * 4).unwrap())
+                .sum::<i32>();
+            assert_eq!(total, 100 * SLOTS as i32);
+            tx.unpin(blk).unwrap();
+        }
+        tx.commit().unwrap();
+        drop(db);
+
+        // A backup is only restored into an empty directory
+        assert!(DBServer::restore("backupcopy", options("backuprestored")).is_err());
+
+        fs::remove_dir_all("backupdb").unwrap();
+        fs::remove_dir_all("backupcopy").unwrap();
+        fs::remove_dir_all("backuprestored").unwrap();
+    }
+
+    #[test]
+    fn pitrtest() {
+        let options = |dir: &str| {
+            DBServerOptions::default()
+                .block_size(400)
+                .directory(dir.to_string())
+                .pool_size(8)
+        };
+        let blk = Block::new("testfile".to_string(), 0);
+        let set = |db: &DBServer, offset: u32, val: i32| {
+            let mut tx = db.new_tx().unwrap();
+            tx.pin(&blk).unwrap();
+            tx.set_int(&blk, offset, val, true).unwrap();
+            tx.commit().unwrap();
+            tx.txnum()
+        };
+
+        let db = DBServer::new_with_params(options("pitrdb")).unwrap();
+        set(&db, 0, 1);
+        let label = db.backup_to("pitrbase").unwrap();
+
+        let good = set(&db, 0, 2);
+        let good_lsn = db.log_manager().lock().unwrap().latest_lsn();
+        thread::sleep(Duration::from_millis(10));
+        let before_batch = SystemTime::now();
+        thread::sleep(Duration::from_millis(10));
+
+        // The bad batch job, and a transaction still running when the log is archived
+        set(&db, 0, 666);
+        set(&db, 4, 666);
+        let mut running = db.new_tx().unwrap();
+        running.pin(&blk).unwrap();
+        running.set_int(&blk, 8, 5, true).unwrap();
+        db.archive_log_to("pitrarchive").unwrap();
+        running.rollback().unwrap();
+        drop(db);
+
+        let restored = |dir: &str, target: RecoveryTarget| {
+            let db = DBServer::restore_to("pitrbase", "pitrarchive", target, options(dir)).unwrap();
+            let mut tx = db.new_tx().unwrap();
+            tx.pin(&blk).unwrap();
+            let vals = [0, 4, 8].map(|offset| tx.get_int(&blk, offset).unwrap());
+            tx.commit().unwrap();
+            vals
+        };
+        assert_eq!(
+            restored("pitrtime", RecoveryTarget::Time(before_batch)),
+            [2, 0, 0]
+        );
+        assert_eq!(
+            restored("pitrtx", RecoveryTarget::Transaction(good)),
+            [2, 0, 0]
+        );
+        assert_eq!(
+            restored("pitrlsn", RecoveryTarget::Lsn(good_lsn)),
+            [2, 0, 0]
+        );
+        assert_eq!(
+            restored("pitrend", RecoveryTarget::Lsn(u64::MAX)),
+            [666, 666, 0]
+        );
+
+        // The blocks of the backup might already have changes up to its end
+        assert!(matches!(
+            DBServer::restore_to(
+                "pitrbase",
+                "pitrarchive",
+                RecoveryTarget::Lsn(label.end_lsn - 1),
+                options("pitrearly"),
+            ),
+            Err(DbError::RecoveryTargetBeforeBackup(lsn)) if lsn == label.end_lsn
+        ));
+
+        for dir in [
+            "pitrdb",
+            "pitrbase",
+            "pitrarchive",
+            "pitrtime",
+            "pitrtx",
+            "pitrlsn",
+            "pitrend",
+        ] {
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+}
@@ -1,11 +1,19 @@
 use crate::{
+    consts::PAGE_LSN_BYTES,
     storage::disk::{block::Block, manager::Manager, page::Page},
     storage::log::manager::LogManager,
     utils::safe_lock::SafeLock,
 };
-use std::sync::{Arc, Mutex};
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
 
 /// Contents of a single buffer in a buffer pool
+///
+/// The last bytes of the page hold the page LSN, the LSN of the last logged change of the
+/// block. It's written to disk along with the block, so recovery can tell which changes the
+/// block on disk already has
 pub struct Buffer {
     file_manager: Arc<Manager>,
     log_manager: Arc<Mutex<LogManager>>,
@@ -16,11 +24,12 @@ pub struct Buffer {
     /// Number of times this buffer has been pinned,
     /// This will help to determine how frequently it's been used
     pins: u64,
-    /// Unique id for a single transaction that happens on the disk
-    txnum: i32,
-    /// LSN of the log that has been written for a particular
-    /// transaction that happens on the buffer
-    lsn: i32,
+    /// Transactions which modified the buffer since it was last flushed. With record level
+    /// locking several transactions can modify different records of the same block
+    modified_by: HashSet<i64>,
+    /// LSN of the first logged change since the block was last written, redo has to start
+    /// from there at the latest
+    rec_lsn: Option<u64>,
 }
 
 /// Operate function f over the block, if the block is some execute
@@ -46,8 +55,8 @@ impl Buffer {
             contents: Page::new(file_manager.blocksize()),
             block: None,
             pins: 0,
-            txnum: -1,
-            lsn: -1,
+            modified_by: HashSet::new(),
+            rec_lsn: None,
         }
     }
 
@@ -59,22 +68,43 @@ impl Buffer {
         self.block.as_ref()
     }
 
-    /// Sets the txnum to the supplied one, and if the LSN is less than zero that means
-    /// logs were pushed for the buffer modification, so keep it unchanged
-    pub fn set_modified(&mut self, txnum: i32, lsn: i32) {
-        self.txnum = txnum;
+    /// Marks the buffer as modified by the txnum, and if the LSN is less than zero that means
+    /// no logs were pushed for the buffer modification, so the page LSN is kept unchanged
+    pub fn set_modified(&mut self, txnum: i64, lsn: i64) {
+        self.modified_by.insert(txnum);
 
         if lsn >= 0 {
-            self.lsn = lsn;
+            let pos = self.page_lsn_pos();
+            self.contents.set_long(pos, lsn);
+            self.rec_lsn.get_or_insert(lsn as u64);
         }
     }
 
+    /// LSN of the first logged change the block on disk doesn't have yet
+    pub fn rec_lsn(&self) -> Option<u64> {
+        self.rec_lsn
+    }
+
+    /// LSN of the last logged change of the block, 0 if no logged change ever reached it
+    pub fn page_lsn(&mut self) -> u64 {
+        let pos = self.page_lsn_pos();
+        self.contents.get_long(pos) as u64
+    }
+
+    fn page_lsn_pos(&self) -> usize {
+        self.file_manager.blocksize() as usize - PAGE_LSN_BYTES
+    }
+
+    pub fn is_modified(&self) -> bool {
+        !self.modified_by.is_empty()
+    }
+
     pub fn is_pinned(&self) -> bool {
         self.pins > 0
     }
 
-    pub fn modifying_tx(&self) -> i32 {
-        self.txnum
+    pub fn is_modified_by(&self, txnum: i64) -> bool {
+        self.modified_by.contains(&txnum)
     }
 
     pub fn assign_to_block(&mut self, block: Block) -> std::io::Result<()> {
@@ -92,11 +122,15 @@ impl Buffer {
     }
 
     /// Flush only if there are any modifications in the buffer.
-    /// i.e Checks if the txnum is not negative and if it's not flushes the
-    /// Page into disk and resets the txnum to -1
+    /// i.e Checks if any transaction modified the buffer and if so flushes the
+    /// Page into disk and forgets the modifying transactions
     pub fn flush(&mut self) -> std::io::Result<()> {
-        if self.txnum >= 0 {
-            self.log_manager.safe_lock().flush(self.lsn as u32)?;
+        if !self.modified_by.is_empty() {
+            // The log has to reach the disk before the changes it describes
+            let lsn = self.page_lsn();
+            if lsn > 0 {
+                self.log_manager.safe_lock().flush(lsn)?;
+            }
 
             let block_clone = self.block.clone();
 
@@ -104,7 +138,8 @@ impl Buffer {
                 self.file_manager.write(&b.clone(), &mut self.contents)
             })?;
 
-            self.txnum = -1;
+            self.modified_by.clear();
+            self.rec_lsn = None;
         }
         Ok(())
     }
@@ -47,15 +47,31 @@ impl BufferPoolManager {
     }
 
     /// Flush all the buffers that is changed by the current txn
-    pub fn flush_all(&mut self, txnum: i32) -> std::io::Result<()> {
+    pub fn flush_all(&mut self, txnum: i64) -> std::io::Result<()> {
         for buf in &mut self.bufferpool {
-            if buf.modifying_tx() == txnum {
+            if buf.is_modified_by(txnum) {
                 buf.flush()?;
             }
         }
         Ok(())
     }
 
+    /// Flush every modified buffer, whoever changed it
+    pub fn flush_dirty(&mut self) -> std::io::Result<()> {
+        for buf in &mut self.bufferpool {
+            if buf.is_modified() {
+                buf.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Smallest LSN any block in the pool still needs redone from, None if every logged
+    /// change already reached the disk. The dirty page table of a checkpoint boils down to it
+    pub fn oldest_rec_lsn(&self) -> Option<u64> {
+        self.bufferpool.iter().filter_map(Buffer::rec_lsn).min()
+    }
+
     ///
     /// Marks the buffer as unpinned, If the buffer is unpinned then,
     /// It unparks the current thread and allows the waiting clients to use the buffer
@@ -79,11 +95,14 @@ impl BufferPoolManager {
     ///
     pub fn pin(&mut self, block: Block) -> std::io::Result<usize> {
         let current = std::time::Instant::now();
-        let mut buffer = self.try_pin(block.clone());
+        let mut buffer = self.try_pin(block.clone())?;
 
+        if buffer.is_none() {
+            tracing::debug!(block = %block, "waiting for a buffer to become available");
+        }
         while buffer.is_none() && !self.waited_too_long(current) {
             thread::park_timeout(std::time::Duration::from_millis(Self::MAX_TIME));
-            buffer = self.try_pin(block.clone());
+            buffer = self.try_pin(block.clone())?;
         }
 
         buffer.ok_or(std::io::Error::other("Buffer timed out"))
@@ -95,14 +114,20 @@ impl BufferPoolManager {
 
     ///
     /// Tries to find the existing Buffer which has this block assigned,
-    /// Or else finds the buffer which is not pinned yet.
+    /// Or else finds the buffer which is not pinned yet. Failing to write back the block the
+    /// buffer held is reported rather than waited out
     ///
-    fn try_pin(&mut self, block: Block) -> Option<usize> {
-        let buffer = self.find_existing(&block).or_else(|| {
-            let buf = self.find_unpinned()?;
-            self.bufferpool[buf].assign_to_block(block.clone()).ok()?;
-            Some(buf)
-        })?;
+    fn try_pin(&mut self, block: Block) -> std::io::Result<Option<usize>> {
+        let buffer = match self.find_existing(&block) {
+            Some(buf) => buf,
+            None => {
+                let Some(buf) = self.find_unpinned() else {
+                    return Ok(None);
+                };
+                self.bufferpool[buf].assign_to_block(block)?;
+                buf
+            }
+        };
 
         if !self.bufferpool[buffer].is_pinned() {
             self.available -= 1;
@@ -110,7 +135,7 @@ impl BufferPoolManager {
 
         self.bufferpool[buffer].pin();
 
-        Some(buffer)
+        Ok(Some(buffer))
     }
 
     pub fn waited_too_long(&self, started_at: std::time::Instant) -> bool {
@@ -1,4 +1,4 @@
-#[derive(Eq, PartialEq, Hash, Clone)]
+#[derive(Eq, PartialEq, Hash, Clone, Debug)]
 pub struct Block {
     filename: String,
     num: u64,
@@ -1,4 +1,4 @@
-use crate::consts::INTEGER_BYTES;
+use crate::consts::{INTEGER_BYTES, LONG_BYTES};
 
 pub(super) struct SimpleBytesCursor {
     pos: usize,
@@ -34,6 +34,24 @@ impl SimpleBytesCursor {
         self.pos += INTEGER_BYTES;
     }
 
+    pub(super) fn get_i64(&mut self) -> i64 {
+        let mut buf: [u8; LONG_BYTES] = [0; LONG_BYTES];
+        buf.copy_from_slice(&self.data[self.pos..self.pos + LONG_BYTES]);
+
+        self.pos += LONG_BYTES;
+
+        i64::from_be_bytes(buf)
+    }
+
+    pub(super) fn set_i64(&mut self, val: i64) {
+        let bytes = val.to_be_bytes();
+
+        let buf = &mut self.data[self.pos..self.pos + LONG_BYTES];
+
+        buf.copy_from_slice(&bytes);
+        self.pos += LONG_BYTES;
+    }
+
     pub(super) fn set_slice(&mut self, data: &[u8]) {
         let dest = &mut self.data[self.pos..self.pos + data.len()];
 
@@ -0,0 +1,93 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::consts::LOG_FILE;
+
+use super::block::Block;
+
+/// Write after which the simulated crash happens
+#[derive(Clone, Copy, Debug)]
+pub enum CrashPoint {
+    /// The nth write of a data block is the last one reaching the disk
+    BlockWrite(u64),
+    /// The nth write of a log block is the last one reaching the disk
+    LogFlush(u64),
+}
+
+/// What happens to a single write
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum WriteFate {
+    /// The whole block reaches the disk
+    Full,
+    /// Only the first `n` bytes reach the disk, then the database crashes
+    Torn(usize),
+    /// Nothing reaches the disk, the database already crashed
+    Lost,
+}
+
+/// Fault injecting hook of the file manager, used to test recovery
+///
+/// The writes are counted until the crash point, from then on every write fails as if the
+/// process died, so whatever wasn't written stays lost. The database is expected to be dropped
+/// without cleanup, e.g with `std::mem::forget`, reopened and recovered. A torn crash writes
+/// only the first half of the last data block, leaving the rest of the old block on disk. Log
+/// blocks are never torn, recovery can't detect a torn log block yet
+pub struct FaultInjector {
+    crash_point: CrashPoint,
+    torn: bool,
+    block_writes: AtomicU64,
+    log_flushes: AtomicU64,
+    crashed: AtomicBool,
+}
+
+impl FaultInjector {
+    pub fn new(crash_point: CrashPoint) -> Self {
+        Self {
+            crash_point,
+            torn: false,
+            block_writes: AtomicU64::new(0),
+            log_flushes: AtomicU64::new(0),
+            crashed: AtomicBool::new(false),
+        }
+    }
+
+    /// Tears the data block written when crashing
+    pub fn torn(mut self) -> Self {
+        self.torn = true;
+        self
+    }
+
+    /// Whether the crash point was reached
+    pub fn crashed(&self) -> bool {
+        self.crashed.load(Ordering::SeqCst)
+    }
+
+    /// Counts a write of `len` bytes to the block and decides how much of it is kept
+    pub(crate) fn on_write(&self, block: &Block, len: usize) -> WriteFate {
+        if self.crashed() {
+            return WriteFate::Lost;
+        }
+
+        let is_log = block.filename() == LOG_FILE;
+        let (count, crash_at) = match self.crash_point {
+            CrashPoint::BlockWrite(n) if !is_log => (&self.block_writes, n),
+            CrashPoint::LogFlush(n) if is_log => (&self.log_flushes, n),
+            _ => return WriteFate::Full,
+        };
+
+        if count.fetch_add(1, Ordering::SeqCst) + 1 < crash_at {
+            return WriteFate::Full;
+        }
+
+        self.crashed.store(true, Ordering::SeqCst);
+        if self.torn && !is_log {
+            WriteFate::Torn(len / 2)
+        } else {
+            WriteFate::Full
+        }
+    }
+
+    /// Error returned by every write failing because of the crash
+    pub(crate) fn crash_error() -> std::io::Error {
+        std::io::Error::other("simulated crash")
+    }
+}
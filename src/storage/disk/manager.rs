@@ -4,6 +4,8 @@ use std::{
     sync::{atomic::AtomicU64, Arc},
 };
 
+#[cfg(test)]
+use super::fault::{FaultInjector, WriteFate};
 use super::{block::Block, page::Page};
 
 pub struct Manager {
@@ -11,6 +13,9 @@ pub struct Manager {
     blocksize: u64,
     is_new: bool,
     stats: ManagerStats,
+    /// Simulates a crash in the middle of the writes, only set by tests
+    #[cfg(test)]
+    faults: Option<Arc<FaultInjector>>,
 }
 
 impl Manager {
@@ -29,9 +34,17 @@ impl Manager {
                 blocks_read: Arc::new(0.into()),
                 blocks_writes: Arc::new(0.into()),
             },
+            #[cfg(test)]
+            faults: None,
         }
     }
 
+    #[cfg(test)]
+    pub(crate) fn with_faults(mut self, faults: Option<Arc<FaultInjector>>) -> Self {
+        self.faults = faults;
+        self
+    }
+
     fn get_file(&self, name: &str) -> std::io::Result<fs::File> {
         let filepath = std::path::Path::new(&self.directory).join(name);
 
@@ -49,7 +62,10 @@ impl Manager {
 
         file.seek(SeekFrom::Start(location))?;
         self.stats.increment_blocks_read();
-        file.read(p.contents()).map(|_| ())
+        let read = file.read(p.contents())?;
+        // A block past the end of the file was never written, it reads as zeros
+        p.contents()[read..].fill(0);
+        Ok(())
     }
 
     pub fn write(&self, block: &Block, p: &mut Page) -> std::io::Result<()> {
@@ -57,9 +73,23 @@ impl Manager {
 
         let location = block.num() * self.blocksize;
 
+        #[cfg(test)]
+        let fate = self.faults.as_ref().map_or(WriteFate::Full, |faults| {
+            faults.on_write(block, p.contents().len())
+        });
+        #[cfg(test)]
+        if fate == WriteFate::Lost {
+            return Err(FaultInjector::crash_error());
+        }
+
         file.seek(SeekFrom::Start(location))?;
         self.stats.increment_blocks_writes();
 
+        #[cfg(test)]
+        if let WriteFate::Torn(len) = fate {
+            file.write_all(&p.contents()[..len])?;
+            return Err(FaultInjector::crash_error());
+        }
         file.write_all(p.contents()).map(|_| ())
     }
 
@@ -71,6 +101,11 @@ impl Manager {
     }
 
     pub fn append(&self, file: &str) -> std::io::Result<Block> {
+        #[cfg(test)]
+        if self.faults.as_ref().is_some_and(|faults| faults.crashed()) {
+            return Err(FaultInjector::crash_error());
+        }
+
         let blknum = self.size(file)?;
 
         let block = Block::new(file.to_string(), blknum);
@@ -84,6 +119,10 @@ impl Manager {
         file.write(&data).map(|_| block)
     }
 
+    pub fn directory(&self) -> &str {
+        &self.directory
+    }
+
     pub fn is_new(&self) -> bool {
         self.is_new
     }
@@ -1,5 +1,7 @@
 pub(crate) mod block;
 mod cursor;
+#[cfg(test)]
+pub(crate) mod fault;
 pub(crate) mod manager;
 pub(crate) mod page;
 mod test;
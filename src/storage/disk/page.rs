@@ -1,5 +1,5 @@
 use super::cursor::SimpleBytesCursor;
-use crate::consts::INTEGER_BYTES;
+use crate::consts::{INTEGER_BYTES, LONG_BYTES};
 
 const MAX_BYTES_PER_CHAR: usize = 1;
 
@@ -37,6 +37,19 @@ impl Page {
         self.size += INTEGER_BYTES;
     }
 
+    pub fn get_long(&mut self, offset: usize) -> i64 {
+        self.blob.set_position(offset);
+
+        self.blob.get_i64()
+    }
+
+    pub fn set_long(&mut self, offset: usize, val: i64) {
+        self.blob.set_position(offset);
+
+        self.blob.set_i64(val);
+        self.size += LONG_BYTES;
+    }
+
     pub fn get_bytes(&mut self, offset: usize) -> &[u8] {
         self.blob.set_position(offset);
 
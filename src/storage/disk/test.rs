@@ -25,6 +25,8 @@ mod tests {
         let size = Page::max_len("abcdefghijklm".len());
         let pos2 = pos1 + size;
         p1.set_int(pos2, 345);
+        let pos3 = pos2 + 4;
+        p1.set_long(pos3, 1 << 40);
         fm.write(&blk, &mut p1).unwrap();
 
         let mut p2 = Page::new(fm.blocksize());
@@ -32,6 +34,7 @@ mod tests {
 
         assert_eq!(105, pos2);
         assert_eq!(345, p2.get_int(pos2));
+        assert_eq!(1 << 40, p2.get_long(pos3));
         assert_eq!(88, pos1);
         assert_eq!("abcdefghijklm", p2.get_string(pos1));
 
@@ -1,9 +1,24 @@
 use crate::{
-    consts::INTEGER_BYTES,
+    consts::{INTEGER_BYTES, LONG_BYTES},
     storage::disk::{block::Block, manager::Manager, page::Page},
 };
 use std::sync::Arc;
 
+/// Every block of the log starts with a header, the records fill the block from its end
+///
+/// [BOUNDARY] [LSN] [NEXT TRANSACTION NUM]
+///
+/// - Boundary: Offset of the last record written to the block
+/// - LSN: LSN of the last record logged when the block was written, the numbering continues
+///   after it
+/// - Next transaction num: Number following the highest transaction number logged so far
+///
+/// The header is rewritten each time the block is, so after a restart the numbering continues
+/// from the header of the last block without reading the records
+const LSN_POS: usize = INTEGER_BYTES;
+const TXNUM_POS: usize = LSN_POS + LONG_BYTES;
+pub const HEADER_BYTES: usize = TXNUM_POS + LONG_BYTES;
+
 pub struct LogManager {
     // File manager to read/write to file
     fm: Arc<Manager>,
@@ -12,8 +27,11 @@ pub struct LogManager {
     // Block which points to last ever written block of data to Logfile
     block: Block,
     logfile: String,
-    last_saved_seq: u32,
-    latest_seq: u32,
+    /// Log sequence numbers count the records from the start of the log, so they keep growing
+    /// across restarts and the first record gets 1
+    last_saved_seq: u64,
+    latest_seq: u64,
+    next_txnum: u64,
 }
 
 impl LogManager {
@@ -31,16 +49,29 @@ impl LogManager {
         } else {
             let block = Block::new(logfile.clone(), size - 1);
             fm.read(&block, &mut page)?;
+            // The block was appended right before a crash and never written, the block before
+            // it was written before appending it
+            if page.get_int(0) == 0 {
+                if size > 1 {
+                    fm.read(&Block::new(logfile.clone(), size - 2), &mut page)?;
+                }
+                page.set_int(0, fm.blocksize() as i32);
+            }
             block
         };
 
+        // Everything in the file is already saved, the numbering continues after it
+        let saved = page.get_long(LSN_POS) as u64;
+        let next_txnum = page.get_long(TXNUM_POS) as u64;
+
         Ok(Self {
             fm,
             block,
             log_page: page,
             logfile,
-            last_saved_seq: 0,
-            latest_seq: 0,
+            last_saved_seq: saved,
+            latest_seq: saved,
+            next_txnum,
         })
     }
 
@@ -56,14 +87,14 @@ impl LogManager {
 
     /// Appends the data to the current Page,if there is room otherwise,
     /// Flushes the current Page to disk and allocates a new block to the Page
-    pub fn append(&mut self, data: &[u8]) -> std::io::Result<u32> {
+    pub fn append(&mut self, data: &[u8]) -> std::io::Result<u64> {
         let mut plen = self.log_page.get_int(0);
         let data_size = data.len();
 
-        // 4 bytes to store the length of the page and rest for the data
+        // 4 bytes to store the length of the record and rest for the data
         let total_bytes_needed = (data_size + INTEGER_BYTES) as i32;
 
-        if plen - total_bytes_needed < INTEGER_BYTES as i32 {
+        if plen - total_bytes_needed < HEADER_BYTES as i32 {
             self.flush_impl(self.latest_seq)?;
             self.block = Self::append_new_block(&mut self.log_page, &self.fm, &self.logfile)?;
             plen = self.log_page.get_int(0);
@@ -80,7 +111,7 @@ impl LogManager {
 
     /// Compare the provided SEQ with the last saved SEQ if it's smaller then write the `logpage to
     /// disk
-    pub fn flush(&mut self, log_seq_no: u32) -> std::io::Result<()> {
+    pub fn flush(&mut self, log_seq_no: u64) -> std::io::Result<()> {
         if log_seq_no >= self.last_saved_seq {
             self.flush_impl(log_seq_no)?;
         }
@@ -88,10 +119,30 @@ impl LogManager {
     }
 
     pub fn iter(&mut self) -> std::io::Result<LogManagerIterator> {
-        self.flush_impl(self.last_saved_seq)?;
-        LogManagerIterator::new(self.fm.clone(), self.block.clone())
+        self.flush_impl(self.latest_seq)?;
+        LogManagerIterator::new(self.fm.clone(), self.block.clone(), self.latest_seq)
     }
-    pub fn flush_impl(&mut self, log_seq_no: u32) -> std::io::Result<()> {
+
+    /// LSN of the last record appended
+    pub fn latest_lsn(&self) -> u64 {
+        self.latest_seq
+    }
+
+    /// Number following the highest transaction number logged, the ones handed out after a
+    /// restart start there
+    pub fn next_txnum(&self) -> u64 {
+        self.next_txnum
+    }
+
+    /// Keeps the transaction numbering at `next` or past it, called before a record of the
+    /// transaction numbered `next - 1` is appended
+    pub fn advance_txnum(&mut self, next: u64) {
+        self.next_txnum = self.next_txnum.max(next);
+    }
+    #[tracing::instrument(level = "trace", name = "log_flush", skip(self), fields(block = %self.block))]
+    pub fn flush_impl(&mut self, log_seq_no: u64) -> std::io::Result<()> {
+        self.log_page.set_long(LSN_POS, self.latest_seq as i64);
+        self.log_page.set_long(TXNUM_POS, self.next_txnum as i64);
         self.fm.write(&self.block, &mut self.log_page)?;
 
         self.last_saved_seq = log_seq_no;
@@ -106,6 +157,8 @@ pub struct LogManagerIterator {
     boundary: usize,
     block: Block,
     page: Page,
+    /// LSN of the record returned next
+    lsn: u64,
 }
 
 impl LogManagerIterator {
@@ -113,22 +166,36 @@ impl LogManagerIterator {
         self.fm.blocksize()
     }
 
-    pub fn new(fm: Arc<Manager>, block: Block) -> std::io::Result<Self> {
+    /// Iterates from the end of the `block`, `lsn` being the LSN of the record found there
+    pub fn new(fm: Arc<Manager>, block: Block, lsn: u64) -> std::io::Result<Self> {
         let mut this = Self {
             fm: fm.clone(),
             block: block.clone(),
             page: Page::new_with_data(vec![0; fm.blocksize() as usize]),
             boundary: 0,
             current_pos: 0,
+            lsn,
         };
 
         this.move_to_block(&block)?;
         Ok(this)
     }
 
+    /// Pairs every record with its LSN
+    pub fn with_lsn(mut self) -> impl Iterator<Item = (u64, Vec<u8>)> {
+        std::iter::from_fn(move || {
+            let lsn = self.lsn;
+            self.next().map(|bytes| (lsn, bytes))
+        })
+    }
+
     fn move_to_block(&mut self, block: &Block) -> std::io::Result<()> {
         self.fm.read(block, &mut self.page)?;
-        self.boundary = self.page.get_int(0) as usize;
+        // A block appended right before a crash holds no records
+        self.boundary = match self.page.get_int(0) {
+            0 => self.fm.blocksize() as usize,
+            boundary => boundary as usize,
+        };
         self.current_pos = self.boundary;
         Ok(())
     }
@@ -148,7 +215,71 @@ impl Iterator for LogManagerIterator {
         }
         let data = self.page.get_bytes(self.current_pos);
         self.current_pos += data.len() + INTEGER_BYTES;
+        self.lsn = self.lsn.saturating_sub(1);
 
         Some(data.to_vec())
     }
 }
+
+/// Reads a log from its first record to its last, holding the records of a single block at a
+/// time. The records are paired with their LSN, which counts them from the start of the log
+pub struct LogManagerForwardIterator {
+    fm: Arc<Manager>,
+    file: String,
+    page: Page,
+    /// Number of the block read next and the number of blocks of the log
+    next_block: u64,
+    blocks: u64,
+    /// Records of the current block which were not returned yet, the next one is the last
+    records: Vec<Vec<u8>>,
+    lsn: u64,
+}
+
+impl LogManagerForwardIterator {
+    pub fn new(fm: Arc<Manager>, file: String) -> std::io::Result<Self> {
+        Ok(Self {
+            page: Page::new_with_data(vec![0; fm.blocksize() as usize]),
+            blocks: fm.size(&file)?,
+            fm,
+            file,
+            next_block: 0,
+            records: Vec::new(),
+            lsn: 0,
+        })
+    }
+
+    fn read_block(&mut self) -> std::io::Result<()> {
+        let block = Block::new(self.file.clone(), self.next_block);
+        self.next_block += 1;
+        self.fm.read(&block, &mut self.page)?;
+
+        // The records follow the boundary newest first, a block appended right before a
+        // crash holds none
+        let mut pos = match self.page.get_int(0) {
+            0 => self.fm.blocksize() as usize,
+            boundary => boundary as usize,
+        };
+        while pos < self.fm.blocksize() as usize {
+            let data = self.page.get_bytes(pos).to_vec();
+            pos += data.len() + INTEGER_BYTES;
+            self.records.push(data);
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for LogManagerForwardIterator {
+    type Item = (u64, Vec<u8>);
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(bytes) = self.records.pop() {
+                self.lsn += 1;
+                return Some((self.lsn, bytes));
+            }
+            if self.next_block >= self.blocks {
+                return None;
+            }
+            self.read_block().ok()?;
+        }
+    }
+}
@@ -7,9 +7,10 @@ mod tests {
     };
 
     use crate::{
+        consts::LOG_FILE,
         server::{DBServer, DBServerOptions},
         storage::disk::page::Page,
-        storage::log::manager::LogManager,
+        storage::log::manager::{LogManager, LogManagerForwardIterator},
     };
 
     #[test]
@@ -28,6 +29,18 @@ mod tests {
         lm.lock().unwrap().flush(65).unwrap();
         assert_log_records(lm, (1..=70).rev().collect());
 
+        // Read from the start the records come oldest first, numbered by their LSN
+        let forward =
+            LogManagerForwardIterator::new(db.file_manager.clone(), LOG_FILE.to_string()).unwrap();
+        let lsns = forward
+            .map(|(lsn, rec)| {
+                let mut p = Page::new_with_data(rec);
+                assert_eq!(format!("record{lsn}"), p.get_string(0));
+                lsn
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(lsns, (1..=70).collect::<Vec<_>>());
+
         fs::remove_dir_all("logtest").unwrap();
     }
 
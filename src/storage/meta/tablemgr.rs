@@ -2,11 +2,11 @@ use crate::{
     consts::GULAG_MSG,
     error::{DbError, DbResult},
     storage::record::{
-        layout::Layout,
+        layout::{Layout, RecordFormat},
         scan::{Scan, TableScan, UpdateScan},
         schema::{self, Schema},
     },
-    storage::tx::Transactions,
+    storage::tx::{LockMode, LockTarget, Transactions},
 };
 
 use std::{collections::HashMap, num::NonZeroUsize};
@@ -23,6 +23,7 @@ impl TableManager {
         let mut tcat_schema = Schema::new();
         tcat_schema.add_string_field("table_name".to_string(), MAX_NAME_LEN);
         tcat_schema.add_int_field("slotsize".to_string());
+        tcat_schema.add_int_field("format".to_string());
 
         let mut fcat_schema = Schema::new();
         fcat_schema.add_string_field("table_name".to_string(), MAX_NAME_LEN);
@@ -30,6 +31,7 @@ impl TableManager {
         fcat_schema.add_int_field("field_type".to_string());
         fcat_schema.add_int_field("field_length".to_string());
         fcat_schema.add_int_field("field_offset".to_string());
+        fcat_schema.add_int_field("field_nullable".to_string());
 
         let table_cat = Layout::new(tcat_schema.clone())?;
         let field_cat = Layout::new(fcat_schema.clone())?;
@@ -53,16 +55,23 @@ impl TableManager {
         &self,
         table_name: String,
         layout: Layout,
-        tx: Transactions,
+        mut tx: Transactions,
     ) -> DbResult<()> {
-        let mut tcat = TableScan::new(tx.clone(), "table_cat".to_string(), layout.clone())?;
+        // Nobody should be using the table while it's being defined
+        tx.lock(
+            &LockTarget::File(format!("{table_name}.tbl")),
+            LockMode::Exclusive,
+        )?;
+
+        let mut tcat = TableScan::new(tx.clone(), "table_cat".to_string(), self.table_cat.clone())?;
         tcat.insert()?;
 
         tcat.set_string("table_name", table_name.clone())?;
         tcat.set_int("slotsize", layout.slot_size().get() as i32)?;
+        tcat.set_int("format", layout.format() as i32)?;
         tcat.close()?;
 
-        let mut field_cat = TableScan::new(tx, "field_cat".to_string(), layout.clone())?;
+        let mut field_cat = TableScan::new(tx, "field_cat".to_string(), self.field_cat.clone())?;
 
         for field_name in layout.schema().fields() {
             field_cat.insert()?;
@@ -71,6 +80,10 @@ impl TableManager {
             field_cat.set_int("field_type", layout.schema().typ(field_name)? as i32)?;
             field_cat.set_int("field_length", layout.schema().length(field_name)? as i32)?;
             field_cat.set_int("field_offset", layout.offset(field_name)? as i32)?;
+            field_cat.set_int(
+                "field_nullable",
+                layout.schema().is_nullable(field_name)? as i32,
+            )?;
         }
         field_cat.close()?;
 
@@ -81,6 +94,7 @@ impl TableManager {
     /// tables to fetch the information about the schema
     pub fn get_layout(&self, table_name: &str, tx: Transactions) -> DbResult<Layout> {
         let mut size = -1;
+        let mut format = 0;
 
         let mut tcat = TableScan::new(tx.clone(), "table_cat".to_string(), self.table_cat.clone())?;
 
@@ -89,6 +103,7 @@ impl TableManager {
         while tcat.next()? {
             if tcat.get_string("table_name")?.eq(table_name) {
                 size = tcat.get_int("slotsize")?;
+                format = tcat.get_int("format")? as u32;
                 break;
             }
         }
@@ -113,17 +128,24 @@ impl TableManager {
                 let field_type = fcat.get_int("field_type")? as u32;
                 let field_length = fcat.get_int("field_length")? as usize;
                 let field_offset = fcat.get_int("field_offset")? as usize;
+                let nullable = fcat.get_int("field_nullable")? != 0;
 
                 offsets.insert(field_name.clone(), field_offset);
-                schema.add_field(
-                    field_name,
-                    schema::FieldType::from(field_type),
-                    field_length,
-                );
+                let field_type = schema::FieldType::try_from(field_type)?;
+                if nullable {
+                    schema.add_nullable_field(field_name, field_type, field_length);
+                } else {
+                    schema.add_field(field_name, field_type, field_length);
+                }
             }
         }
         fcat.close()?;
 
-        Ok(Layout::new_with_data(schema, offsets, size))
+        Ok(Layout::new_with_data(
+            schema,
+            offsets,
+            size,
+            RecordFormat::try_from(format)?,
+        ))
     }
 }
@@ -1,3 +1,4 @@
+pub mod backup;
 pub mod bufferpool;
 pub mod disk;
 pub mod log;
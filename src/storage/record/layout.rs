@@ -3,16 +3,46 @@ use std::num::NonZeroUsize;
 use std::sync::Arc;
 
 use crate::{
-    consts::INTEGER_BYTES,
+    consts::{INTEGER_BYTES, LONG_BYTES},
     error::{DbError, DbResult},
     storage::disk::page::Page,
+    storage::record::record_page::SLOT_HEADER_BYTES,
 };
 
 use std::collections::HashMap;
 
+/// Fields a single word of the null bitmap has bits for
+pub(crate) const NULL_BITS_PER_WORD: usize = INTEGER_BYTES * 8;
+
+/// How the records of a table are stored in its blocks, the value is how the format is stored
+/// in the catalog
+#[repr(u32)]
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub(crate) enum RecordFormat {
+    /// Every record takes a slot of `Layout::slot_size` bytes, the fields at fixed offsets
+    Fixed,
+    /// Every record takes as many bytes as its values, see `SlottedPage`
+    Slotted,
+}
+
+impl TryFrom<u32> for RecordFormat {
+    type Error = DbError;
+
+    fn try_from(value: u32) -> DbResult<Self> {
+        match value {
+            0 => Ok(RecordFormat::Fixed),
+            1 => Ok(RecordFormat::Slotted),
+            _ => Err(DbError::UnknownRecordFormat(value)),
+        }
+    }
+}
+
 struct LayoutInner {
     schema: Schema,
+    format: RecordFormat,
     offsets: HashMap<String, usize>,
+    /// Bit of every nullable field in the null bitmap
+    null_bits: HashMap<String, usize>,
     slot_size: NonZeroUsize,
 }
 
@@ -23,7 +53,19 @@ pub struct Layout {
 
 impl Layout {
     pub fn new(schema: Schema) -> DbResult<Self> {
-        let mut pos = INTEGER_BYTES;
+        Self::new_with_format(schema, RecordFormat::Fixed)
+    }
+
+    /// Layout of a table stored in slotted pages. The offsets and the slot size are the ones of
+    /// a record with every string as long as it can be, the fields of the records themselves
+    /// follow each other without gaps
+    pub fn new_slotted(schema: Schema) -> DbResult<Self> {
+        Self::new_with_format(schema, RecordFormat::Slotted)
+    }
+
+    fn new_with_format(schema: Schema, format: RecordFormat) -> DbResult<Self> {
+        let null_bits = Self::null_bits(&schema);
+        let mut pos = SLOT_HEADER_BYTES + Self::bitmap_bytes(null_bits.len());
         let offsets = schema
             .fields()
             .iter()
@@ -40,6 +82,8 @@ impl Layout {
             inner: Arc::new(LayoutInner {
                 offsets,
                 schema,
+                format,
+                null_bits,
                 slot_size: NonZeroUsize::new(pos).expect("This will never be executed"),
             }),
         })
@@ -49,11 +93,14 @@ impl Layout {
         schema: Schema,
         offsets: HashMap<String, usize>,
         slot_size: NonZeroUsize,
+        format: RecordFormat,
     ) -> Self {
         Self {
             inner: Arc::new(LayoutInner {
+                null_bits: Self::null_bits(&schema),
                 offsets,
                 schema,
+                format,
                 slot_size,
             }),
         }
@@ -63,6 +110,10 @@ impl Layout {
         &self.inner.schema
     }
 
+    pub fn format(&self) -> RecordFormat {
+        self.inner.format
+    }
+
     pub fn offset(&self, field_name: &str) -> DbResult<usize> {
         self.inner
             .offsets
@@ -75,13 +126,55 @@ impl Layout {
         self.inner.slot_size
     }
 
-    pub fn length_in_bytes(schema: &Schema, field_name: &String) -> DbResult<usize> {
-        let field_type = schema.typ(field_name)?;
+    /// Bit of the field in the null bitmap, `None` if the field can't be NULL
+    pub fn null_bit(&self, field_name: &str) -> DbResult<Option<usize>> {
+        self.offset(field_name)?;
+        Ok(self.inner.null_bits.get(field_name).copied())
+    }
+
+    /// Size of the null bitmap ending the header of every slot, layouts without nullable fields
+    /// have none
+    pub fn null_bitmap_bytes(&self) -> usize {
+        Self::bitmap_bytes(self.inner.null_bits.len())
+    }
+
+    /// Words of the null bitmap of a record with every nullable field NULL
+    pub fn null_bitmap(&self) -> Vec<i32> {
+        let mut words = vec![0; self.null_bitmap_bytes() / INTEGER_BYTES];
+        for &bit in self.inner.null_bits.values() {
+            words[bit / NULL_BITS_PER_WORD] |= 1 << (bit % NULL_BITS_PER_WORD);
+        }
+        words
+    }
 
-        Ok(if field_type.eq(&FieldType::Integer) {
-            INTEGER_BYTES
-        } else {
-            Page::max_len(schema.length(field_name)?)
+    /// Numbers the nullable fields in the order of the schema
+    fn null_bits(schema: &Schema) -> HashMap<String, usize> {
+        let mut null_bits = HashMap::new();
+        for field_name in schema.fields() {
+            if schema
+                .is_nullable(field_name)
+                .is_ok_and(|nullable| nullable)
+            {
+                null_bits.insert(field_name.clone(), null_bits.len());
+            }
+        }
+        null_bits
+    }
+
+    /// The bitmap is made of whole int words
+    fn bitmap_bytes(nullable: usize) -> usize {
+        nullable.div_ceil(NULL_BITS_PER_WORD) * INTEGER_BYTES
+    }
+
+    pub fn length_in_bytes(schema: &Schema, field_name: &String) -> DbResult<usize> {
+        Ok(match schema.typ(field_name)? {
+            FieldType::Integer | FieldType::Boolean | FieldType::Date => INTEGER_BYTES,
+            FieldType::BigInt | FieldType::Double | FieldType::Timestamp | FieldType::Decimal => {
+                LONG_BYTES
+            }
+            // The record only holds the reference to the value, see `OverflowRef`
+            FieldType::Text | FieldType::Blob => LONG_BYTES,
+            FieldType::Varchar => Page::max_len(schema.length(field_name)?),
         })
     }
 }
@@ -1,6 +1,8 @@
 pub(crate) mod layout;
+pub(crate) mod overflow;
 mod record_page;
 pub(super) mod rowid;
 pub(crate) mod scan;
 pub(crate) mod schema;
+pub(crate) mod slotted_page;
 mod test;
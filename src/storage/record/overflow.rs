@@ -0,0 +1,353 @@
+use crate::{
+    consts::{INTEGER_BYTES, PAGE_LSN_BYTES},
+    error::{DbError, DbResult},
+    storage::disk::{block::Block, page::Page},
+    storage::tx::Transactions,
+};
+use std::io;
+
+/// First block of an overflow file, it holds the head of the list of free blocks
+const META_BLOCK: u64 = 0;
+/// Number of the first free block plus one, 0 if there are none. A block appended but not
+/// written yet is all zeroes, so it's an empty list
+const FREE_HEAD_OFFSET: usize = 0;
+
+/// Block of the chain following this one, -1 ends the chain. Free blocks link to the next free
+/// block the same way
+const NEXT_OFFSET: usize = 0;
+const DATA_OFFSET: usize = INTEGER_BYTES;
+
+/// Value stored out of line in a chain of overflow blocks, the record holds this reference in
+/// place of the value
+///
+/// A chain is never changed once it's written. Setting the value again writes a new chain, so
+/// the versions of the record other transactions still read keep theirs, and undoing the change
+/// of the record is all a rollback has to do.
+///
+/// The blocks of a chain no version refers to anymore go to the free list of the file, new
+/// chains take their blocks from there first. The free list is changed without locks and its
+/// changes are never undone, so a chain is only freed once nothing can refer to it whatever
+/// happens to the transaction freeing it, see `PendingChains`
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub(crate) struct OverflowRef {
+    len: u32,
+    /// First block of the chain, meaningless for an empty value which has no blocks
+    first: u32,
+}
+
+impl OverflowRef {
+    /// The reference as a record stores it, the length in the high half
+    pub(crate) fn to_long(self) -> i64 {
+        ((self.len as u64) << 32 | self.first as u64) as i64
+    }
+
+    pub(crate) fn from_long(val: i64) -> Self {
+        let val = val as u64;
+        Self {
+            len: (val >> 32) as u32,
+            first: val as u32,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len as usize
+    }
+}
+
+/// Chains a transaction wrote or stopped referring to, freed once the transaction finishes
+#[derive(Default)]
+pub(crate) struct PendingChains {
+    /// Freed if the transaction rolls back, no record refers to them then
+    written: Vec<(String, OverflowRef)>,
+    /// Freed once the transaction committed
+    garbage: Vec<(String, OverflowRef)>,
+}
+
+impl PendingChains {
+    pub(crate) fn written(&mut self, file: &str, reference: OverflowRef) {
+        self.written.push((file.to_string(), reference));
+    }
+
+    /// A record stopped referring to the chain. A chain the transaction wrote itself is not
+    /// referred to by any other version, so it's garbage once the transaction commits. The
+    /// chains of other transactions are kept by the version the change replaced, vacuum frees
+    /// them along with that version
+    pub(crate) fn replaced(&mut self, file: &str, reference: OverflowRef) {
+        let entry = (file.to_string(), reference);
+        if self.written.contains(&entry) {
+            self.garbage.push(entry);
+        }
+    }
+
+    /// The chain belonged to a version which was thrown away
+    pub(crate) fn released(&mut self, file: &str, reference: OverflowRef) {
+        self.garbage.push((file.to_string(), reference));
+    }
+
+    /// The chains to free once the transaction committed or rolled back
+    pub(crate) fn take(&mut self, committed: bool) -> Vec<(String, OverflowRef)> {
+        let written = std::mem::take(&mut self.written);
+        let garbage = std::mem::take(&mut self.garbage);
+        if committed {
+            garbage
+        } else {
+            written
+        }
+    }
+}
+
+/// Bytes of a value an overflow block holds
+fn capacity(tx: &Transactions) -> usize {
+    tx.blocksize() as usize - DATA_OFFSET - PAGE_LSN_BYTES
+}
+
+/// Writes a value to a new chain of overflow blocks of `file`, the value is streamed through
+/// one block at a time
+pub(crate) struct OverflowWriter {
+    tx: Transactions,
+    file: String,
+    /// Bytes of the value which didn't fill a block yet
+    pending: Vec<u8>,
+    /// Block the pending bytes go to, appended once the first byte arrives
+    block: Option<Block>,
+    first: Option<Block>,
+    len: u64,
+}
+
+impl OverflowWriter {
+    pub(crate) fn new(tx: Transactions, file: String) -> Self {
+        Self {
+            tx,
+            file,
+            pending: Vec::new(),
+            block: None,
+            first: None,
+            len: 0,
+        }
+    }
+
+    /// Adds the bytes to the value, every block filled up is written out
+    pub(crate) fn append(&mut self, mut bytes: &[u8]) -> DbResult<()> {
+        let capacity = capacity(&self.tx);
+        while !bytes.is_empty() {
+            if self.pending.len() == capacity {
+                // The next block is only known once there is more to write
+                let next = allocate(&mut self.tx, &self.file)?;
+                self.flush(next.num() as i32)?;
+                self.block = Some(next);
+            }
+            if self.block.is_none() {
+                let block = allocate(&mut self.tx, &self.file)?;
+                self.first = Some(block.clone());
+                self.block = Some(block);
+            }
+
+            let n = bytes.len().min(capacity - self.pending.len());
+            self.pending.extend_from_slice(&bytes[..n]);
+            self.len += n as u64;
+            bytes = &bytes[n..];
+        }
+        Ok(())
+    }
+
+    /// Writes the last block of the chain and returns the reference to it
+    pub(crate) fn finish(mut self, field_name: &str) -> DbResult<OverflowRef> {
+        let len =
+            u32::try_from(self.len).map_err(|_| DbError::ValueTooLarge(field_name.to_string()))?;
+        if self.block.is_some() {
+            self.flush(-1)?;
+        }
+
+        let reference = OverflowRef {
+            len,
+            first: self.first.map_or(0, |block| block.num() as u32),
+        };
+        self.tx.chain_written(&self.file, reference);
+        Ok(reference)
+    }
+
+    /// Writes the pending bytes to the current block along with the block following it
+    fn flush(&mut self, next: i32) -> DbResult<()> {
+        let Some(block) = self.block.take() else {
+            return Ok(());
+        };
+
+        let mut bytes = int_bytes(next);
+        bytes.append(&mut self.pending);
+
+        self.tx.pin(&block)?;
+        {
+            let _latch = self.tx.latch(&block);
+            self.tx
+                .write_overflow_latched(&block, NEXT_OFFSET, &bytes)?;
+        }
+        self.tx.unpin(&block)
+    }
+}
+
+/// Reads a value stored in a chain of overflow blocks of `file`, one block at a time
+pub struct OverflowReader {
+    tx: Transactions,
+    file: String,
+    next: Option<u64>,
+    /// Bytes of the value not read from the blocks yet
+    remaining: usize,
+    /// Bytes of the current block not handed out yet
+    buffered: Vec<u8>,
+    pos: usize,
+}
+
+impl OverflowReader {
+    pub(crate) fn new(tx: Transactions, file: String, reference: OverflowRef) -> Self {
+        Self {
+            tx,
+            file,
+            next: (reference.len > 0).then_some(reference.first as u64),
+            remaining: reference.len(),
+            buffered: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    /// Bytes of the value left to read
+    pub fn remaining(&self) -> usize {
+        self.remaining + self.buffered.len() - self.pos
+    }
+
+    /// Reads the rest of the value
+    pub(crate) fn read_all(mut self) -> DbResult<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(self.remaining());
+        while self.fill()? {
+            bytes.extend_from_slice(&self.buffered[self.pos..]);
+            self.pos = self.buffered.len();
+        }
+        Ok(bytes)
+    }
+
+    /// Reads the next block of the chain once the current one is used up, false at the end of
+    /// the value
+    fn fill(&mut self) -> DbResult<bool> {
+        if self.pos < self.buffered.len() {
+            return Ok(true);
+        }
+        if self.remaining == 0 {
+            return Ok(false);
+        }
+        let num = self
+            .next
+            .ok_or_else(|| DbError::BrokenOverflowChain(self.file.clone()))?;
+
+        let block = Block::new(self.file.clone(), num);
+        let len = self.remaining.min(capacity(&self.tx));
+        self.tx.pin(&block)?;
+        let (next, bytes) = {
+            let _latch = self.tx.latch(&block);
+            let next = self.tx.get_int_latched(&block, NEXT_OFFSET as u32)?;
+            (next, self.tx.get_bytes_latched(&block, DATA_OFFSET, len)?)
+        };
+        self.tx.unpin(&block)?;
+
+        self.next = (next >= 0).then_some(next as u64);
+        self.remaining -= len;
+        self.buffered = bytes;
+        self.pos = 0;
+        Ok(true)
+    }
+}
+
+impl io::Read for OverflowReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.fill().map_err(io::Error::other)? {
+            return Ok(0);
+        }
+
+        let n = buf.len().min(self.buffered.len() - self.pos);
+        buf[..n].copy_from_slice(&self.buffered[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+fn int_bytes(val: i32) -> Vec<u8> {
+    let mut page = Page::new(INTEGER_BYTES as u64);
+    page.set_int(0, val);
+    page.contents()[..INTEGER_BYTES].to_vec()
+}
+
+/// Block for a new chain, a free block if there is one. The meta block is appended along with
+/// the first block of the file
+fn allocate(tx: &mut Transactions, file: &str) -> DbResult<Block> {
+    if let Some(block) = pop_free(tx, file)? {
+        return Ok(block);
+    }
+
+    let block = tx.append(file.to_string())?;
+    if block.num() == META_BLOCK {
+        return tx.append(file.to_string());
+    }
+    Ok(block)
+}
+
+/// Takes the first block off the free list of the file
+fn pop_free(tx: &mut Transactions, file: &str) -> DbResult<Option<Block>> {
+    if tx.size_unlocked(file.to_string())? == 0 {
+        return Ok(None);
+    }
+
+    let meta = Block::new(file.to_string(), META_BLOCK);
+    tx.pin(&meta)?;
+    let popped = {
+        let _latch = tx.latch(&meta);
+        match tx.get_int_latched(&meta, FREE_HEAD_OFFSET as u32)? {
+            0 => None,
+            head => {
+                let block = Block::new(file.to_string(), head as u64 - 1);
+                tx.pin(&block)?;
+                let next = {
+                    let _latch = tx.latch(&block);
+                    tx.get_int_latched(&block, NEXT_OFFSET as u32)?
+                };
+                tx.unpin(&block)?;
+
+                tx.write_overflow_latched(&meta, FREE_HEAD_OFFSET, &int_bytes(next + 1))?;
+                Some(block)
+            }
+        }
+    };
+    tx.unpin(&meta)?;
+
+    Ok(popped)
+}
+
+/// Puts the blocks of the chain on the free list of the file, nothing may refer to the chain
+/// anymore
+pub(crate) fn free_chain(
+    tx: &mut Transactions,
+    file: &str,
+    reference: OverflowRef,
+) -> DbResult<()> {
+    let meta = Block::new(file.to_string(), META_BLOCK);
+    let mut next = (reference.len > 0).then_some(reference.first as i32);
+    // The length tells where the chain ends, the last block links to the free list afterwards
+    let blocks = reference.len().div_ceil(capacity(tx));
+
+    tx.pin(&meta)?;
+    for _ in 0..blocks {
+        let num = next.ok_or_else(|| DbError::BrokenOverflowChain(file.to_string()))?;
+        let block = Block::new(file.to_string(), num as u64);
+
+        tx.pin(&block)?;
+        {
+            let _meta_latch = tx.latch(&meta);
+            let _latch = tx.latch(&block);
+            let following = tx.get_int_latched(&block, NEXT_OFFSET as u32)?;
+            let head = tx.get_int_latched(&meta, FREE_HEAD_OFFSET as u32)?;
+
+            tx.write_overflow_latched(&block, NEXT_OFFSET, &int_bytes(head - 1))?;
+            tx.write_overflow_latched(&meta, FREE_HEAD_OFFSET, &int_bytes(num + 1))?;
+            next = (following >= 0).then_some(following);
+        }
+        tx.unpin(&block)?;
+    }
+    tx.unpin(&meta)
+}
@@ -1,15 +1,119 @@
 use super::schema::FieldType;
 use crate::{
-    common::slot::Slot, error::DbResult, storage::disk::block::Block,
-    storage::record::layout::Layout, storage::tx::Transactions,
+    common::slot::Slot,
+    consts::{INTEGER_BYTES, LONG_BYTES, PAGE_LSN_BYTES},
+    error::{DbError, DbResult},
+    storage::disk::{block::Block, page::Page},
+    storage::record::{
+        layout::{Layout, RecordFormat, NULL_BITS_PER_WORD},
+        rowid::RowId,
+        slotted_page::{self, SlottedPage},
+    },
+    storage::tx::{LockMode, LockTarget, RowChange, RowOp, Transactions, NO_TX},
 };
 use std::mem::MaybeUninit;
 
+/// Size of the header every slot starts with. The null bitmap of the layout, if it has nullable
+/// fields, ends the header and the fields of the record follow it
+pub(crate) const SLOT_HEADER_BYTES: usize = 3 * INTEGER_BYTES + 2 * LONG_BYTES;
+
+const FLAG_OFFSET: usize = 0;
+const XMIN_OFFSET: usize = INTEGER_BYTES;
+const XMAX_OFFSET: usize = XMIN_OFFSET + LONG_BYTES;
+const PREV_BLOCK_OFFSET: usize = XMAX_OFFSET + LONG_BYTES;
+const PREV_SLOT_OFFSET: usize = PREV_BLOCK_OFFSET + INTEGER_BYTES;
+
+/// An empty slot is all zeroes, so a block which was appended but not formatted yet has no
+/// records in it
 #[repr(i32)]
 #[derive(Copy, Clone)]
 pub(crate) enum RecordFlags {
-    Used,
     Empty,
+    /// Holds the newest version of a record
+    Used,
+    /// Holds an older version of a record, the newest one is kept in another slot
+    Version,
+}
+
+/// Header of a slot, every slot holds a single version of a record
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(crate) struct SlotHeader {
+    pub(crate) flag: i32,
+    /// Transaction which created the version
+    pub(crate) xmin: i64,
+    /// Transaction which deleted the version or replaced it with a newer one, `NO_TX` if the
+    /// version is still alive
+    pub(crate) xmax: i64,
+    /// Slot holding the version the transaction `xmin` replaced
+    pub(crate) prev: Option<RowId>,
+}
+
+impl SlotHeader {
+    /// Header of a record newly inserted by the transaction
+    pub(crate) fn inserted(txnum: u64) -> Self {
+        Self {
+            flag: RecordFlags::Used as i32,
+            xmin: txnum as i64,
+            xmax: NO_TX,
+            prev: None,
+        }
+    }
+
+    fn empty() -> Self {
+        Self {
+            flag: RecordFlags::Empty as i32,
+            xmin: NO_TX,
+            xmax: NO_TX,
+            prev: None,
+        }
+    }
+
+    pub(crate) fn is(&self, flag: RecordFlags) -> bool {
+        self.flag == flag as i32
+    }
+
+    /// The header the way it's stored at the start of the slot
+    fn to_bytes(&self) -> Vec<u8> {
+        let (prev_block, prev_slot) = self.prev.as_ref().map_or((-1, -1), |rid| {
+            (rid.blk_num() as i32, rid.slot().inner() as i32)
+        });
+
+        encode(SLOT_HEADER_BYTES, |page| {
+            page.set_int(FLAG_OFFSET, self.flag);
+            page.set_long(XMIN_OFFSET, self.xmin);
+            page.set_long(XMAX_OFFSET, self.xmax);
+            page.set_int(PREV_BLOCK_OFFSET, prev_block);
+            page.set_int(PREV_SLOT_OFFSET, prev_slot);
+        })
+    }
+}
+
+/// A version vacuum threw away along with the values of the fields it was asked for, `None` for
+/// the NULL ones
+pub(crate) struct Vacuumed {
+    pub(crate) slot: Slot,
+    pub(crate) header: SlotHeader,
+    pub(crate) values: Vec<Option<i64>>,
+}
+
+/// Bytes of a value the way it's stored in a block, `len` bytes long
+fn encode(len: usize, f: impl FnOnce(&mut Page)) -> Vec<u8> {
+    let mut page = Page::new(len as u64);
+    f(&mut page);
+    page.contents()[..len].to_vec()
+}
+
+/// Writes `bytes` at `pos` of the image of a record, growing it if they run past its end
+fn overwrite(row: &mut Vec<u8>, pos: usize, bytes: &[u8]) {
+    let end = (pos + bytes.len()).min(row.len());
+    row.splice(pos..end, bytes.iter().copied());
+}
+
+/// Reads the int at `pos` of the image of a record
+fn int_at(row: &[u8], pos: usize) -> i32 {
+    let mut bytes = [0; INTEGER_BYTES];
+    bytes.copy_from_slice(&row[pos..pos + INTEGER_BYTES]);
+    i32::from_be_bytes(bytes)
 }
 
 /// Stores a record in a given location in a block
@@ -17,6 +121,15 @@ pub(crate) enum RecordFlags {
 /// Every record page maintains an array of layout which contains the information about the fields
 /// in the page. This is very important for a record to be non homogeneous (variable in size). We
 /// need to track what's the size of individual fields and offset
+///
+/// Records are versioned, a slot flagged `Used` holds the newest version of a record and links to
+/// the slot holding the version it replaced. Transactions reading under a snapshot follow these
+/// links till they find the version visible to them, without locking anything. Transactions
+/// without a snapshot lock the record and only ever read the newest version.
+///
+/// Blocks of a slotted layout keep each record as a tuple of the size of its values, found
+/// through the directory of the block, see `SlottedPage`. A string changing size moves the
+/// fields after it, so the change rewrites the rest of the tuple.
 pub(crate) struct RecordPage {
     block: Block,
     tx: Transactions,
@@ -24,90 +137,243 @@ pub(crate) struct RecordPage {
 }
 
 impl RecordPage {
-    /// Creates a new Object of RecordPage
-    pub(crate) fn new(transaction: Transactions, block: Block, layout: Layout) -> Self {
+    /// Creates a new Object of RecordPage, the block stays pinned till the transaction unpins
+    /// it
+    pub(crate) fn new(transaction: Transactions, block: Block, layout: Layout) -> DbResult<Self> {
         let mut rc = Self {
             tx: transaction,
             block,
             layout,
         };
 
-        assert!(rc.tx.pin(&rc.block).is_ok());
+        rc.tx.pin(&rc.block)?;
 
-        rc
+        Ok(rc)
     }
 
     /// Gets the postion of the field and gets the data from it
     pub(crate) fn get_int(&mut self, slot: Slot, field_name: &str) -> DbResult<i32> {
-        let field_pos = self.offset(slot) + self.layout.offset(field_name)?;
-        self.tx.get_int(&self.block, field_pos.inner() as u32)
+        self.read_field(slot, field_name, Transactions::get_int_latched)
+    }
+
+    /// Gets the postion of the field and gets the data from it
+    pub(crate) fn get_long(&mut self, slot: Slot, field_name: &str) -> DbResult<i64> {
+        self.read_field(slot, field_name, Transactions::get_long_latched)
     }
 
     /// Gets the postion of the field and get the data from it
     pub(crate) fn get_string(&mut self, slot: Slot, field_name: &str) -> DbResult<String> {
-        let field_pos = self.offset(slot) + self.layout.offset(field_name)?;
-        self.tx.get_string(&self.block, field_pos.inner() as u32)
+        self.read_field(slot, field_name, Transactions::get_string_latched)
+    }
+
+    /// Reads the field of the record visible to the transaction with `get`, which is given the
+    /// position of the field in the block
+    fn read_field<T>(
+        &mut self,
+        slot: Slot,
+        field_name: &str,
+        get: impl Fn(&mut Transactions, &Block, u32) -> DbResult<T>,
+    ) -> DbResult<T> {
+        self.layout.offset(field_name)?;
+        let layout = self.layout.clone();
+        self.read(slot, |tx, block, slot_pos| {
+            let field_pos = Self::field_offset(tx, &layout, block, slot_pos, field_name)?;
+            get(tx, block, (slot_pos + field_pos) as u32)
+        })
+    }
+
+    /// Whether the field of the record is NULL, fields which can't be NULL never are
+    pub(crate) fn is_null(&mut self, slot: Slot, field_name: &str) -> DbResult<bool> {
+        let Some(bit) = self.layout.null_bit(field_name)? else {
+            return Ok(false);
+        };
+
+        let (word_pos, mask) = Self::null_word(bit);
+        self.read(slot, |tx, block, slot_pos| {
+            Ok(tx.get_int_latched(block, (slot_pos + word_pos) as u32)? & mask != 0)
+        })
+    }
+
+    /// Sets the field to NULL, the value stored for it stays until the field is set again.
+    /// Fails with `FieldNotNullable` if the schema doesn't allow NULL for the field, see
+    /// `set_int` for versioning
+    pub(crate) fn set_null(&mut self, slot: Slot, field_name: &str) -> DbResult<()> {
+        let bit = self
+            .layout
+            .null_bit(field_name)?
+            .ok_or_else(|| DbError::FieldNotNullable(field_name.to_string()))?;
+        self.tx
+            .lock_record(&self.block, slot, LockMode::Exclusive)?;
+
+        let _latch = self.tx.latch(&self.block);
+        self.change(RowOp::Update, slot, |row| {
+            Self::set_null_bit(row, bit, true)
+        })
     }
 
     /// Sets the int value in a layout
+    ///
+    /// The value is changed in place, keeping the version other transactions might still read
+    /// is up to the caller
     pub(crate) fn set_int(&mut self, slot: Slot, field_name: &str, val: i32) -> DbResult<()> {
-        let field_pos = self.offset(slot) + self.layout.offset(field_name)?;
-        self.tx
-            .set_int(&self.block, field_pos.inner() as u32, val, true)
+        let value = encode(INTEGER_BYTES, |page| page.set_int(0, val));
+        self.set_field(slot, field_name, value)
     }
 
-    /// Sets the string value in a layout
+    /// Sets the long value in a layout, see `set_int`
+    pub(crate) fn set_long(&mut self, slot: Slot, field_name: &str, val: i64) -> DbResult<()> {
+        let value = encode(LONG_BYTES, |page| page.set_long(0, val));
+        self.set_field(slot, field_name, value)
+    }
+
+    /// Sets the string value in a layout, see `set_int`
     pub(crate) fn set_string(&mut self, slot: Slot, field_name: &str, val: String) -> DbResult<()> {
-        let field_pos = self.offset(slot) + self.layout.offset(field_name)?;
+        let value = encode(Page::max_len(val.len()), |page| page.set_string(0, val));
+        self.set_field(slot, field_name, value)
+    }
 
+    /// Writes the stored bytes of a value to the field and marks the field as set, logged as a
+    /// single change of the record
+    fn set_field(&mut self, slot: Slot, field_name: &str, value: Vec<u8>) -> DbResult<()> {
+        self.layout.offset(field_name)?;
         self.tx
-            .set_string(&self.block, field_pos.inner() as u32, val, true)
+            .lock_record(&self.block, slot, LockMode::Exclusive)?;
+
+        let _latch = self.tx.latch(&self.block);
+        let field_pos = self.field_offset_latched(slot, field_name)?;
+        let null_bit = self.layout.null_bit(field_name)?;
+        // A string of a tuple changing size moves the fields after it
+        let resizes = self.layout.format() == RecordFormat::Slotted
+            && self.layout.schema().typ(field_name)? == FieldType::Varchar;
+
+        self.change(RowOp::Update, slot, |row| {
+            let old_len = match resizes {
+                true => Page::max_len(int_at(row, field_pos) as usize),
+                false => value.len(),
+            };
+            let end = (field_pos + old_len).min(row.len());
+            row.splice(field_pos..end, value);
+            if let Some(bit) = null_bit {
+                Self::set_null_bit(row, bit, false);
+            }
+        })
     }
 
-    /// Sets the record to empty
-    pub(crate) fn delete(&mut self, slot: Slot) -> DbResult<()> {
-        self.set_flag(slot, RecordFlags::Empty)
+    /// Sets or clears the bit of the null bitmap in the image of a record
+    fn set_null_bit(row: &mut Vec<u8>, bit: usize, null: bool) {
+        let (word_pos, mask) = Self::null_word(bit);
+        let word = int_at(row, word_pos);
+        let word = if null { word | mask } else { word & !mask };
+        overwrite(row, word_pos, &word.to_be_bytes());
     }
 
-    pub(crate) fn set_flag(&mut self, slot: Slot, flag: RecordFlags) -> DbResult<()> {
-        self.tx.set_int(
-            &self.block,
-            self.offset(slot).inner() as u32,
-            flag as i32,
-            true,
+    /// Offset in the slot of the word of the null bitmap holding the bit, and the mask of the
+    /// bit in the word
+    fn null_word(bit: usize) -> (usize, i32) {
+        (
+            SLOT_HEADER_BYTES + bit / NULL_BITS_PER_WORD * INTEGER_BYTES,
+            1 << (bit % NULL_BITS_PER_WORD),
         )
     }
 
+    /// Marks the record deleted by the transaction, the slot is reused once no transaction
+    /// can see the record anymore
+    pub(crate) fn delete(&mut self, slot: Slot) -> DbResult<()> {
+        self.lock_for_write(slot)?;
+
+        let _latch = self.tx.latch(&self.block);
+        let txnum = self.tx.txnum() as i64;
+        self.change(RowOp::Delete, slot, |row| {
+            overwrite(row, XMAX_OFFSET, &txnum.to_be_bytes())
+        })
+    }
+
+    /// Locks the record exclusively and returns its header. The transaction has to see the
+    /// newest version of the record, otherwise it was changed by a transaction the snapshot
+    /// doesn't see and `WriteConflict` is returned
+    pub(crate) fn lock_for_write(&mut self, slot: Slot) -> DbResult<SlotHeader> {
+        self.tx
+            .lock_record(&self.block, slot, LockMode::Exclusive)?;
+
+        let header = self.header(slot)?;
+
+        if !header.is(RecordFlags::Used)
+            || !self.tx.is_visible(header.xmin, header.xmax)
+            || !self.tx.is_current(header.xmin, header.xmax)
+        {
+            return Err(DbError::WriteConflict(self.tx.txnum()));
+        }
+
+        Ok(header)
+    }
+
+    /// Reads the header of the slot
+    pub(crate) fn header(&mut self, slot: Slot) -> DbResult<SlotHeader> {
+        let _latch = self.tx.latch(&self.block);
+        let block = self.block.clone();
+        self.read_header(&block, slot)
+    }
+
+    /// Overwrites the header of the slot at once, the caller has to hold an exclusive lock on
+    /// the record
+    pub(crate) fn set_header(&mut self, slot: Slot, header: &SlotHeader) -> DbResult<()> {
+        let _latch = self.tx.latch(&self.block);
+        self.write_header(slot, header)
+    }
+
     /// Formats the record before adding a new block.
     ///
     /// Format does the garbage collection of the record pages. And aligns them properly in disk
     /// i.e if the record value was deleted before. This process will properly align all of the
     /// blocks properly in the disk
     pub(crate) fn format(&mut self) -> DbResult<()> {
+        if self.layout.format() == RecordFormat::Slotted {
+            // A block of zeroes is an empty slotted page, the directory grows as records are
+            // added
+            return self
+                .tx
+                .set_int(&self.block, slotted_page::COUNT_OFFSET as u32, 0, false);
+        }
+
         let mut slot = Slot::new(0_usize);
 
-        while self.is_valid_slot(slot) {
-            self.tx.set_int(
-                &self.block,
-                self.offset(slot).inner() as u32,
-                RecordFlags::Empty as i32,
-                false,
-            )?;
+        while self.is_valid_slot(slot)? {
+            let empty = SlotHeader::empty();
+            let pos = self.offset(slot).inner();
+            let header = [
+                (FLAG_OFFSET, empty.flag),
+                (PREV_BLOCK_OFFSET, -1),
+                (PREV_SLOT_OFFSET, -1),
+            ];
+            for (offset, val) in header {
+                self.tx
+                    .set_int(&self.block, (pos + offset) as u32, val, false)?;
+            }
+            for (offset, val) in [(XMIN_OFFSET, empty.xmin), (XMAX_OFFSET, empty.xmax)] {
+                self.tx
+                    .set_long(&self.block, (pos + offset) as u32, val, false)?;
+            }
+
+            // Every nullable field of an unused slot is NULL
+            for (i, word) in self.layout.null_bitmap().into_iter().enumerate() {
+                let word_pos = pos + SLOT_HEADER_BYTES + i * INTEGER_BYTES;
+                self.tx.set_int(&self.block, word_pos as u32, word, false)?;
+            }
 
             let schema = self.layout.schema();
             for field_name in schema.fields() {
-                let field_pos = self.offset(slot) + self.layout.offset(field_name)?;
-
-                if schema.typ(field_name)?.eq(&FieldType::Integer) {
-                    self.tx
-                        .set_int(&self.block, field_pos.inner() as u32, 0, false)?;
-                } else {
-                    self.tx.set_string(
-                        &self.block,
-                        field_pos.inner() as u32,
-                        String::default(),
-                        false,
-                    )?;
+                let field_pos =
+                    (self.offset(slot) + self.layout.offset(field_name)?).inner() as u32;
+
+                match schema.typ(field_name)? {
+                    FieldType::Varchar => {
+                        self.tx
+                            .set_string(&self.block, field_pos, String::default(), false)?;
+                    }
+                    _ if Layout::length_in_bytes(schema, field_name)? == LONG_BYTES => {
+                        self.tx.set_long(&self.block, field_pos, 0, false)?;
+                    }
+                    _ => self.tx.set_int(&self.block, field_pos, 0, false)?,
                 }
             }
 
@@ -117,42 +383,562 @@ impl RecordPage {
         Ok(())
     }
 
-    /// Returns the next slot which is used after the given slot
-    pub fn next_after(&mut self, slot: Slot) -> DbResult<Slot> {
-        self.search_after(slot, RecordFlags::Used)
+    /// Returns the next slot after the given slot holding a record visible to the transaction
+    ///
+    /// Without a snapshot every record is read under the lock the isolation level of the
+    /// transaction asks for, waiting for the transaction changing it to finish.
+    pub fn next_after(&mut self, mut slot: Slot) -> DbResult<Slot> {
+        self.tx.lock_scan(&self.block)?;
+        let block = self.block.clone();
+
+        slot += 1;
+        while self.is_valid_slot(slot)? {
+            if self.header(slot)?.is(RecordFlags::Used) {
+                let visible = if self.tx.has_snapshot() {
+                    self.read_version(slot, |_, _, _| Ok(()))?.is_some()
+                } else {
+                    let layout = self.layout.clone();
+                    let header = self.tx.read_record(&block, slot, |tx| {
+                        let _latch = tx.latch(&block);
+                        let pos = Self::slot_pos(tx, &layout, &block, slot)?;
+                        Self::header_at(tx, &block, pos)
+                    })?;
+                    header.is(RecordFlags::Used) && self.tx.is_visible(header.xmin, header.xmax)
+                };
+
+                if visible {
+                    return Ok(slot);
+                }
+            }
+            slot += 1;
+        }
+
+        Ok(Slot::UnInit)
     }
 
     /// Inserts a new slot after the given slot
     pub fn insert_after(&mut self, slot: Slot) -> DbResult<Slot> {
-        let new_slot = self.search_after(slot, RecordFlags::Empty)?;
+        let header = SlotHeader::inserted(self.tx.txnum());
+        self.claim_after(slot, &header, None)
+    }
 
-        if new_slot.is_init() {
-            self.set_flag(new_slot, RecordFlags::Used)?;
+    /// Claims a free slot after the given slot, locking it exclusively and writing the header
+    /// to it along with the `fields` of the record if they are known already, which start with
+    /// the null bitmap. A new record starts with every nullable field NULL. Slots of versions
+    /// no transaction can see anymore are reused, unless the version might refer to overflow
+    /// chains which only vacuum frees. A slotted page adds a slot to its directory once none of
+    /// them has room for the record.
+    ///
+    /// The block is latched while looking for a free slot so two transactions never claim
+    /// the same one.
+    pub(crate) fn claim_after(
+        &mut self,
+        mut slot: Slot,
+        header: &SlotHeader,
+        fields: Option<&[u8]>,
+    ) -> DbResult<Slot> {
+        // Waiting for the block lock while holding the latch could block the holder of the
+        // lock, so it's taken before latching
+        self.tx.lock(
+            &LockTarget::Block(self.block.clone()),
+            LockMode::IntentionExclusive,
+        )?;
+        let _latch = self.tx.latch(&self.block);
+        let block = self.block.clone();
+
+        let mut new = header.to_bytes();
+        match fields {
+            Some(fields) => new.extend_from_slice(fields),
+            None => new.extend(self.new_fields()?),
         }
 
-        Ok(new_slot)
-    }
-
-    /// Searches for the next slot after the given slot which has the given flag
-    fn search_after(&mut self, mut slot: Slot, flag: RecordFlags) -> DbResult<Slot> {
         slot += 1;
-        while self.is_valid_slot(slot) {
-            if self
-                .tx
-                .get_int(&self.block, self.offset(slot).inner() as u32)?
-                .eq(&(flag as i32))
+        while self.is_valid_slot(slot)? {
+            let current = self.read_header(&block, slot)?;
+            if self.is_reusable(&current)
+                && self.has_room(slot, new.len(), true)?
+                && self
+                    .tx
+                    .try_lock_record(&self.block, slot, LockMode::Exclusive)?
             {
+                self.change(RowOp::Insert, slot, |row| overwrite(row, 0, &new))?;
                 return Ok(slot);
             }
             slot += 1;
         }
 
+        // The directory gets a new slot at its end
+        let slot = match self.layout.format() {
+            RecordFormat::Fixed => return Ok(Slot::UnInit),
+            RecordFormat::Slotted => Slot::new(self.slot_count()?),
+        };
+        if self.has_room(slot, new.len(), true)?
+            && self
+                .tx
+                .try_lock_record(&self.block, slot, LockMode::Exclusive)?
+        {
+            self.change(RowOp::Insert, slot, |row| overwrite(row, 0, &new))?;
+            return Ok(slot);
+        }
+
         Ok(Slot::UnInit)
     }
 
-    /// If the slot does not exceed the current block then it's a valid slot otherwise it's full
-    fn is_valid_slot(&self, slot: Slot) -> bool {
-        self.offset(slot + 1).inner() as u64 <= self.tx.blocksize()
+    /// Null bitmap and fields of a new record, the fields of a fixed size slot are already
+    /// formatted
+    fn new_fields(&self) -> DbResult<Vec<u8>> {
+        let mut fields = Vec::new();
+        for word in self.layout.null_bitmap() {
+            fields.extend(encode(INTEGER_BYTES, |page| page.set_int(0, word)));
+        }
+        if self.layout.format() == RecordFormat::Fixed {
+            return Ok(fields);
+        }
+
+        // Zeroes are a 0 of every number and an empty string
+        let schema = self.layout.schema();
+        for field in schema.fields() {
+            let len = match schema.typ(field)? {
+                FieldType::Varchar => Page::max_len(0),
+                _ => Layout::length_in_bytes(schema, field)?,
+            };
+            fields.resize(fields.len() + len, 0);
+        }
+        Ok(fields)
+    }
+
+    /// Whether `len` bytes of the record fit the slot, slots of fixed size always have room
+    /// for a record. See `SlottedPage::fits`, the caller has to hold the latch of the block
+    fn has_room(&mut self, slot: Slot, len: usize, new_record: bool) -> DbResult<bool> {
+        if self.layout.format() == RecordFormat::Fixed {
+            return Ok(true);
+        }
+        if self.is_valid_slot(slot)? {
+            let capacity = slotted_page::entry_pos(slot.inner()) + INTEGER_BYTES;
+            if self.tx.get_int_latched(&self.block, capacity as u32)? as usize >= len {
+                return Ok(true);
+            }
+        }
+
+        let size = self.tx.blocksize() as usize;
+        let mut page = Page::new_with_data(self.tx.get_bytes_latched(&self.block, 0, size)?);
+        Ok(SlottedPage::new(&mut page).fits(slot.inner(), len, new_record))
+    }
+
+    /// Frees the slots of the versions no transaction can see anymore, returns them along with
+    /// the values of `fields` they held
+    pub(crate) fn vacuum(&mut self, fields: &[String]) -> DbResult<Vec<Vacuumed>> {
+        self.tx.lock(
+            &LockTarget::Block(self.block.clone()),
+            LockMode::IntentionExclusive,
+        )?;
+        let _latch = self.tx.latch(&self.block);
+        let block = self.block.clone();
+
+        let mut freed = Vec::new();
+        let mut slot = Slot::new(0);
+        while self.is_valid_slot(slot)? {
+            let header = self.read_header(&block, slot)?;
+
+            if !header.is(RecordFlags::Empty)
+                && self.is_free(&header)
+                && self
+                    .tx
+                    .try_lock_record(&self.block, slot, LockMode::Exclusive)?
+            {
+                let values = self.values_latched(slot, fields)?;
+                self.write_header(slot, &SlotHeader::empty())?;
+                freed.push(Vacuumed {
+                    slot,
+                    header,
+                    values,
+                });
+            }
+            slot += 1;
+        }
+
+        Ok(freed)
+    }
+
+    /// The versions of the block which replaced an older one, as the slot of the older version
+    /// along with the values of `fields` the newer version holds
+    pub(crate) fn replacements(
+        &mut self,
+        fields: &[String],
+    ) -> DbResult<Vec<(RowId, Vec<Option<i64>>)>> {
+        let _latch = self.tx.latch(&self.block);
+        let block = self.block.clone();
+
+        let mut replacements = Vec::new();
+        let mut slot = Slot::new(0);
+        while self.is_valid_slot(slot)? {
+            let header = self.read_header(&block, slot)?;
+            if let (false, Some(prev)) = (header.is(RecordFlags::Empty), header.prev) {
+                replacements.push((prev, self.values_latched(slot, fields)?));
+            }
+            slot += 1;
+        }
+
+        Ok(replacements)
+    }
+
+    /// Values of 8 byte fields of the version in the slot whoever can see it, `None` for the
+    /// NULL ones. The caller has to hold the latch of the block
+    fn values_latched(&mut self, slot: Slot, fields: &[String]) -> DbResult<Vec<Option<i64>>> {
+        let block = self.block.clone();
+        let slot_pos = self.slot_pos_latched(slot)?;
+
+        let mut values = Vec::new();
+        for field_name in fields {
+            if let Some(bit) = self.layout.null_bit(field_name)? {
+                let (word_pos, mask) = Self::null_word(bit);
+                let word = self
+                    .tx
+                    .get_int_latched(&block, (slot_pos + word_pos) as u32)?;
+                if word & mask != 0 {
+                    values.push(None);
+                    continue;
+                }
+            }
+
+            let field_pos =
+                Self::field_offset(&mut self.tx, &self.layout, &block, slot_pos, field_name)?;
+            let pos = (slot_pos + field_pos) as u32;
+            values.push(Some(self.tx.get_long_latched(&block, pos)?));
+        }
+        Ok(values)
+    }
+
+    /// Reads a value of the record visible to the transaction. If the record is not visible at
+    /// all, e.g the transaction deleted it, the newest version is read
+    fn read<T>(
+        &mut self,
+        slot: Slot,
+        mut f: impl FnMut(&mut Transactions, &Block, usize) -> DbResult<T>,
+    ) -> DbResult<T> {
+        let block = self.block.clone();
+        let layout = self.layout.clone();
+
+        if self.tx.has_snapshot() {
+            if let Some(val) = self.read_version(slot, &mut f)? {
+                return Ok(val);
+            }
+
+            let _latch = self.tx.latch(&block);
+            let slot_pos = Self::slot_pos(&mut self.tx, &layout, &block, slot)?;
+            return f(&mut self.tx, &block, slot_pos);
+        }
+
+        self.tx.read_record(&block, slot, |tx| {
+            let _latch = tx.latch(&block);
+            let slot_pos = Self::slot_pos(tx, &layout, &block, slot)?;
+            f(tx, &block, slot_pos)
+        })
+    }
+
+    /// Finds the version of the record the snapshot of the transaction sees and reads from it,
+    /// `None` if the record does not exist in the snapshot.
+    ///
+    /// The newest version can be replaced while the older ones are read, in that case the
+    /// search starts over. The older versions themselves never change while a snapshot which
+    /// might see them is alive.
+    fn read_version<T>(
+        &mut self,
+        slot: Slot,
+        mut f: impl FnMut(&mut Transactions, &Block, usize) -> DbResult<T>,
+    ) -> DbResult<Option<T>> {
+        let block = self.block.clone();
+        let layout = self.layout.clone();
+
+        loop {
+            let (head, val) = {
+                let _latch = self.tx.latch(&block);
+                let slot_pos = Self::slot_pos(&mut self.tx, &layout, &block, slot)?;
+                let head = Self::header_at(&mut self.tx, &block, slot_pos)?;
+                let val = f(&mut self.tx, &block, slot_pos)?;
+                (head, val)
+            };
+
+            if !head.is(RecordFlags::Used) {
+                return Ok(None);
+            }
+            if self.tx.is_visible(head.xmin, NO_TX) {
+                return Ok(self.tx.is_visible(head.xmin, head.xmax).then_some(val));
+            }
+
+            let mut prev = head.prev.clone();
+            let mut found = None;
+            let mut stale = false;
+
+            while let Some(rid) = prev {
+                let version = Block::new(block.filename().to_string(), rid.blk_num());
+                self.tx.pin(&version)?;
+
+                let header = {
+                    let _latch = self.tx.latch(&version);
+                    let version_pos = Self::slot_pos(&mut self.tx, &layout, &version, rid.slot())?;
+                    let header = Self::header_at(&mut self.tx, &version, version_pos)?;
+
+                    if header.is(RecordFlags::Version) && self.tx.is_visible(header.xmin, NO_TX) {
+                        found = Some(f(&mut self.tx, &version, version_pos)?);
+                    }
+                    header
+                };
+                self.tx.unpin(&version)?;
+
+                // The version was thrown away, the newer version had to change since
+                if !header.is(RecordFlags::Version) {
+                    stale = true;
+                    break;
+                }
+                if found.is_some() {
+                    break;
+                }
+                prev = header.prev;
+            }
+
+            if !stale && self.header(slot)? == head {
+                return Ok(found);
+            }
+        }
+    }
+
+    /// Whether the slot can be vacuumed, i.e it's empty or holds a version no transaction can
+    /// see anymore
+    fn is_free(&self, header: &SlotHeader) -> bool {
+        header.is(RecordFlags::Empty) || self.tx.is_dead(header.xmax)
+    }
+
+    /// Whether the slot can be claimed without vacuuming it first, the versions of records with
+    /// TEXT or BLOB fields are left to vacuum so their chains are freed
+    fn is_reusable(&self, header: &SlotHeader) -> bool {
+        header.is(RecordFlags::Empty)
+            || (self.layout.schema().overflow_fields().is_empty() && self.is_free(header))
+    }
+
+    /// Reads the header of a slot of the block, the caller has to hold the latch of the block
+    fn read_header(&mut self, block: &Block, slot: Slot) -> DbResult<SlotHeader> {
+        let pos = Self::slot_pos(&mut self.tx, &self.layout, block, slot)?;
+        Self::header_at(&mut self.tx, block, pos)
+    }
+
+    /// Reads the header of the slot starting at `pos`
+    fn header_at(tx: &mut Transactions, block: &Block, pos: usize) -> DbResult<SlotHeader> {
+        let offset = |offset: usize| (pos + offset) as u32;
+
+        let flag = tx.get_int_latched(block, offset(FLAG_OFFSET))?;
+        let xmin = tx.get_long_latched(block, offset(XMIN_OFFSET))?;
+        let xmax = tx.get_long_latched(block, offset(XMAX_OFFSET))?;
+        let prev_block = tx.get_int_latched(block, offset(PREV_BLOCK_OFFSET))?;
+        let prev_slot = tx.get_int_latched(block, offset(PREV_SLOT_OFFSET))?;
+
+        Ok(SlotHeader {
+            flag,
+            xmin,
+            xmax,
+            prev: (prev_slot >= 0)
+                .then(|| RowId::new(prev_block as u64, Slot::new(prev_slot as usize))),
+        })
+    }
+
+    /// Writes the header of a slot of this block, the caller has to hold the latch of the block
+    /// and an exclusive lock on the record
+    fn write_header(&mut self, slot: Slot, header: &SlotHeader) -> DbResult<()> {
+        let header = header.to_bytes();
+        self.change(RowOp::Update, slot, |row| {
+            overwrite(row, FLAG_OFFSET, &header)
+        })
+    }
+
+    /// Raw bytes of the null bitmap and the fields of the record, the caller has to hold the
+    /// latch of the block
+    pub(crate) fn fields_bytes(&mut self, slot: Slot) -> DbResult<Vec<u8>> {
+        let mut row = self.row_latched(slot)?;
+        Ok(row.split_off(SLOT_HEADER_BYTES))
+    }
+
+    /// Image of the record in the slot, its header followed by the null bitmap and the fields.
+    /// A slot about to be added to the directory of a slotted page has none. The caller has to
+    /// hold the latch of the block
+    fn row_latched(&mut self, slot: Slot) -> DbResult<Vec<u8>> {
+        if !self.is_valid_slot(slot)? {
+            return Ok(Vec::new());
+        }
+
+        let block = self.block.clone();
+        let slot_pos = self.slot_pos_latched(slot)?;
+        let len = match self.layout.format() {
+            RecordFormat::Fixed => self.layout.slot_size().get(),
+            RecordFormat::Slotted => Self::tuple_len(&mut self.tx, &self.layout, &block, slot_pos)?,
+        };
+        self.tx.get_bytes_latched(&block, slot_pos, len)
+    }
+
+    /// Applies `edit` to the image of the record and writes it back, logged as a single row
+    /// change holding the bytes of the image which changed. Undoing an insert only has to free
+    /// the slot again, so only the flag is kept of the record it replaced. Nothing is logged if
+    /// the record stays the same. The caller has to hold the latch of the block and an
+    /// exclusive lock on the record
+    fn change(&mut self, op: RowOp, slot: Slot, edit: impl FnOnce(&mut Vec<u8>)) -> DbResult<()> {
+        let old = self.row_latched(slot)?;
+        let mut new = old.clone();
+        edit(&mut new);
+        if new == old {
+            return Ok(());
+        }
+
+        let slot_size = match self.layout.format() {
+            RecordFormat::Fixed => self.layout.slot_size().get(),
+            RecordFormat::Slotted if !self.has_room(slot, new.len(), false)? => {
+                return Err(DbError::RecordDoesNotFit);
+            }
+            RecordFormat::Slotted => 0,
+        };
+
+        // A slot about to be added to the directory is empty
+        let old = match old.is_empty() {
+            true => vec![0; new.len()],
+            false => old,
+        };
+        // An insert logs the whole record, a slot added to the directory gets exactly its size
+        let (start, old_end, new_end) = match op {
+            RowOp::Insert => (FLAG_OFFSET, FLAG_OFFSET + INTEGER_BYTES, new.len()),
+            _ => Self::changed(&old, &new),
+        };
+
+        self.tx.change_row_latched(
+            &self.block,
+            &RowChange {
+                op,
+                file: self.block.filename().to_string(),
+                rid: RowId::new(self.block.num(), slot),
+                slot_size,
+                offset: start,
+                old: old[start..old_end].to_vec(),
+                new: new[start..new_end].to_vec(),
+            },
+        )
+    }
+
+    /// Span of the image of a record which changed, as its start and its end in the old and the
+    /// new image. A string changing size moves the rest of the tuple
+    fn changed(old: &[u8], new: &[u8]) -> (usize, usize, usize) {
+        let differs = |(old, new): (&u8, &u8)| old != new;
+        let start = old.iter().zip(new).position(differs).unwrap_or(0);
+        if old.len() != new.len() {
+            return (start, old.len(), new.len());
+        }
+
+        let end = old
+            .iter()
+            .zip(new)
+            .rposition(differs)
+            .map_or(start, |i| i + 1);
+        (start, end, end)
+    }
+
+    /// If the slot does not exceed the current block then it's a valid slot otherwise it's full.
+    /// The page LSN at the end of the block is not available for slots. A slotted page has the
+    /// slots of its directory
+    fn is_valid_slot(&mut self, slot: Slot) -> DbResult<bool> {
+        match self.layout.format() {
+            RecordFormat::Fixed => {
+                Ok(self.offset(slot + 1).inner() as u64
+                    <= self.tx.blocksize() - PAGE_LSN_BYTES as u64)
+            }
+            RecordFormat::Slotted => Ok(slot.inner() < self.slot_count()?),
+        }
+    }
+
+    /// Number of slots in the directory of a slotted page
+    fn slot_count(&mut self) -> DbResult<usize> {
+        let count = self
+            .tx
+            .get_int_latched(&self.block, slotted_page::COUNT_OFFSET as u32)?;
+        Ok(count as usize)
+    }
+
+    /// Position of the slot in this block, the caller has to hold the latch of the block
+    fn slot_pos_latched(&mut self, slot: Slot) -> DbResult<usize> {
+        let block = self.block.clone();
+        Self::slot_pos(&mut self.tx, &self.layout, &block, slot)
+    }
+
+    /// Offset of the field from the start of the slot in this block, the caller has to hold the
+    /// latch of the block
+    fn field_offset_latched(&mut self, slot: Slot, field_name: &str) -> DbResult<usize> {
+        let block = self.block.clone();
+        let slot_pos = self.slot_pos_latched(slot)?;
+        Self::field_offset(&mut self.tx, &self.layout, &block, slot_pos, field_name)
+    }
+
+    /// Position of the slot in the block, the caller has to hold the latch of the block
+    fn slot_pos(
+        tx: &mut Transactions,
+        layout: &Layout,
+        block: &Block,
+        slot: Slot,
+    ) -> DbResult<usize> {
+        match layout.format() {
+            RecordFormat::Fixed => Ok(slot.inner() * layout.slot_size().get()),
+            RecordFormat::Slotted => {
+                let entry = slotted_page::entry_pos(slot.inner());
+                Ok(tx.get_int_latched(block, entry as u32)? as usize)
+            }
+        }
+    }
+
+    /// Offset of the field from the start of the slot at `slot_pos`. The fields of a tuple
+    /// follow each other, so the strings before the field are read to find it
+    fn field_offset(
+        tx: &mut Transactions,
+        layout: &Layout,
+        block: &Block,
+        slot_pos: usize,
+        field_name: &str,
+    ) -> DbResult<usize> {
+        if layout.format() == RecordFormat::Fixed {
+            return layout.offset(field_name);
+        }
+
+        let mut offset = SLOT_HEADER_BYTES + layout.null_bitmap_bytes();
+        for field in layout.schema().fields() {
+            if field == field_name {
+                return Ok(offset);
+            }
+            offset += Self::stored_len(tx, layout, block, slot_pos + offset, field)?;
+        }
+        Err(DbError::OffsetNotFound)
+    }
+
+    /// Bytes the tuple at `slot_pos` of a slotted page takes
+    fn tuple_len(
+        tx: &mut Transactions,
+        layout: &Layout,
+        block: &Block,
+        slot_pos: usize,
+    ) -> DbResult<usize> {
+        let mut len = SLOT_HEADER_BYTES + layout.null_bitmap_bytes();
+        for field in layout.schema().fields() {
+            len += Self::stored_len(tx, layout, block, slot_pos + len, field)?;
+        }
+        Ok(len)
+    }
+
+    /// Bytes the value of the field at `pos` takes in a tuple
+    fn stored_len(
+        tx: &mut Transactions,
+        layout: &Layout,
+        block: &Block,
+        pos: usize,
+        field_name: &String,
+    ) -> DbResult<usize> {
+        match layout.schema().typ(field_name)? {
+            FieldType::Varchar => Ok(Page::max_len(
+                tx.get_int_latched(block, pos as u32)? as usize
+            )),
+            _ => Layout::length_in_bytes(layout.schema(), field_name),
+        }
     }
 
     /// Returns the offset of the slot in the block
@@ -1,5 +1,6 @@
 use crate::common::slot::Slot;
 
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub struct RowId {
     blk_num: u64,
     slot: Slot,
@@ -1,20 +1,63 @@
-use std::fmt;
+use std::{
+    cmp::Ordering,
+    fmt,
+    hash::{Hash, Hasher},
+};
 
-#[derive(Clone, Eq, Hash, PartialEq, PartialOrd)]
+const DAY_MICROS: i64 = 24 * 60 * 60 * 1_000_000;
+
+/// A value of a field
+///
+/// The derived comparisons treat `Null` as a value like any other, e.g to group or sort by it.
+/// `equals` and `compare` follow SQL instead, where comparing with NULL is unknown
+#[derive(Clone, Debug, Eq, Hash, PartialEq, PartialOrd)]
 pub enum Constant {
     Int(i32),
     String(String),
+    BigInt(i64),
+    Double(Double),
+    Bool(bool),
+    /// Days since 1970-01-01
+    Date(i32),
+    /// Microseconds since 1970-01-01 00:00:00 UTC
+    Timestamp(i64),
+    Decimal(Decimal),
+    Bytes(Vec<u8>),
+    Null,
 }
 
 impl fmt::Display for Constant {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if let Self::Int(ival) = self {
-            return write!(f, "{ival}");
-        }
-        if let Self::Int(sval) = &self {
-            return write!(f, "{sval}");
+        match self {
+            Self::Int(ival) => write!(f, "{ival}"),
+            Self::String(sval) => write!(f, "{sval}"),
+            Self::BigInt(lval) => write!(f, "{lval}"),
+            Self::Double(dval) => write!(f, "{}", dval.0),
+            Self::Bool(bval) => write!(f, "{bval}"),
+            Self::Date(days) => write_date(f, *days as i64),
+            Self::Timestamp(micros) => {
+                write_date(f, micros.div_euclid(DAY_MICROS))?;
+                let micros = micros.rem_euclid(DAY_MICROS);
+                let secs = micros / 1_000_000;
+                write!(
+                    f,
+                    " {:02}:{:02}:{:02}",
+                    secs / 3600,
+                    secs / 60 % 60,
+                    secs % 60
+                )?;
+                match micros % 1_000_000 {
+                    0 => Ok(()),
+                    fraction => write!(f, ".{fraction:06}"),
+                }
+            }
+            Self::Decimal(decimal) => write!(f, "{decimal}"),
+            Self::Bytes(bytes) => {
+                write!(f, "\\x")?;
+                bytes.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+            }
+            Self::Null => write!(f, "NULL"),
         }
-        write!(f, "")
     }
 }
 
@@ -27,6 +70,80 @@ impl Constant {
         Self::String(sval)
     }
 
+    pub fn with_long(lval: i64) -> Self {
+        Self::BigInt(lval)
+    }
+
+    pub fn with_double(dval: f64) -> Self {
+        Self::Double(Double(dval))
+    }
+
+    pub fn with_bool(bval: bool) -> Self {
+        Self::Bool(bval)
+    }
+
+    /// Date of the day of the proleptic Gregorian calendar, `month` and `day` start at 1
+    pub fn with_date(year: i32, month: u32, day: u32) -> Self {
+        Self::Date(days_from_civil(year, month, day))
+    }
+
+    /// Timestamp `micros` microseconds after 1970-01-01 00:00:00 UTC
+    pub fn with_timestamp(micros: i64) -> Self {
+        Self::Timestamp(micros)
+    }
+
+    pub fn with_decimal(decimal: Decimal) -> Self {
+        Self::Decimal(decimal)
+    }
+
+    pub fn with_bytes(bytes: Vec<u8>) -> Self {
+        Self::Bytes(bytes)
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Self::Null)
+    }
+
+    /// Whether the values are equal, `None` (unknown) if either of them is NULL
+    pub fn equals(&self, other: &Self) -> Option<bool> {
+        self.compare(other).map(Ordering::is_eq)
+    }
+
+    /// Orders the values, `None` (unknown) if either of them is NULL or they can't be compared.
+    /// Numbers of different types are compared by their value
+    pub fn compare(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Self::Null, _) | (_, Self::Null) => None,
+            (Self::String(a), Self::String(b)) => Some(a.cmp(b)),
+            (Self::Bool(a), Self::Bool(b)) => Some(a.cmp(b)),
+            (Self::Date(a), Self::Date(b)) => Some(a.cmp(b)),
+            (Self::Timestamp(a), Self::Timestamp(b)) => Some(a.cmp(b)),
+            (Self::Bytes(a), Self::Bytes(b)) => Some(a.cmp(b)),
+            (Self::Double(_), _) | (_, Self::Double(_)) => {
+                self.as_f64()?.partial_cmp(&other.as_f64()?)
+            }
+            _ => Some(self.as_decimal()?.compare(&other.as_decimal()?)),
+        }
+    }
+
+    /// The value of an integer or decimal
+    pub fn as_decimal(&self) -> Option<Decimal> {
+        match self {
+            Self::Int(v) => Some(Decimal::new(*v as i64, 0)),
+            Self::BigInt(v) => Some(Decimal::new(*v, 0)),
+            Self::Decimal(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// The value of any number
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Double(v) => Some(v.0),
+            _ => self.as_decimal().map(Decimal::to_f64),
+        }
+    }
+
     fn is_int(&self) -> bool {
         matches!(self, Self::Int(_))
     }
@@ -52,4 +169,195 @@ impl Constant {
             _ => unreachable!("This wont be executed"),
         }
     }
+
+    /// Value of a `BigInt` or an `Int`
+    pub fn into_long(self) -> i64 {
+        match self {
+            Self::BigInt(v) => v,
+            Self::Int(v) => v as i64,
+            _ => panic!("Invalid value being queried"),
+        }
+    }
+
+    /// Value of any number
+    pub fn into_double(self) -> f64 {
+        self.as_f64().expect("Invalid value being queried")
+    }
+
+    pub fn into_bool(self) -> bool {
+        match self {
+            Self::Bool(v) => v,
+            _ => panic!("Invalid value being queried"),
+        }
+    }
+
+    /// Days since 1970-01-01 of a `Date`
+    pub fn into_date(self) -> i32 {
+        match self {
+            Self::Date(v) => v,
+            _ => panic!("Invalid value being queried"),
+        }
+    }
+
+    /// Microseconds since 1970-01-01 00:00:00 UTC of a `Timestamp`
+    pub fn into_timestamp(self) -> i64 {
+        match self {
+            Self::Timestamp(v) => v,
+            _ => panic!("Invalid value being queried"),
+        }
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        match self {
+            Self::Bytes(v) => v,
+            _ => panic!("Invalid value being queried"),
+        }
+    }
+
+    /// Value of a `Decimal` or an integer
+    pub fn into_decimal(self) -> Decimal {
+        self.as_decimal().expect("Invalid value being queried")
+    }
+}
+
+/// A double compared with `f64::total_cmp`, so it can be hashed and sorted like the other
+/// values
+#[derive(Clone, Copy, Debug)]
+pub struct Double(pub f64);
+
+impl PartialEq for Double {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Double {}
+
+impl PartialOrd for Double {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Double {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl Hash for Double {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state);
+    }
+}
+
+/// Fixed point number, `unscaled` divided by 10 to the power of `scale`
+///
+/// The derived comparisons tell apart the same value with different scales, `compare` compares
+/// the values
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd)]
+pub struct Decimal {
+    unscaled: i64,
+    scale: u32,
+}
+
+impl Decimal {
+    pub fn new(unscaled: i64, scale: u32) -> Self {
+        Self { unscaled, scale }
+    }
+
+    pub fn unscaled(&self) -> i64 {
+        self.unscaled
+    }
+
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    /// The same value with `scale` digits after the decimal point, digits dropped are rounded
+    /// half away from zero. `None` if the value doesn't fit anymore
+    pub fn rescale(&self, scale: u32) -> Option<Self> {
+        let unscaled = if scale >= self.scale {
+            self.unscaled
+                .checked_mul(10_i64.checked_pow(scale - self.scale)?)?
+        } else {
+            let factor = 10_i64.checked_pow(self.scale - scale)?;
+            let (quot, rem) = (self.unscaled / factor, self.unscaled % factor);
+            if rem.abs() * 2 >= factor {
+                quot + self.unscaled.signum()
+            } else {
+                quot
+            }
+        };
+
+        Some(Self::new(unscaled, scale))
+    }
+
+    /// Compares the values regardless of the scales
+    pub fn compare(&self, other: &Self) -> Ordering {
+        if self.scale > other.scale {
+            return other.compare(self).reverse();
+        }
+
+        let widened = 10_i128
+            .checked_pow(other.scale - self.scale)
+            .and_then(|factor| (self.unscaled as i128).checked_mul(factor));
+        match widened {
+            Some(widened) => widened.cmp(&(other.unscaled as i128)),
+            // Widened, a value other than 0 would be further from 0 than any unscaled value
+            None if self.unscaled == 0 => 0.cmp(&other.unscaled),
+            None => self.unscaled.cmp(&0),
+        }
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.unscaled as f64 / 10_f64.powi(self.scale as i32)
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = self.unscaled.unsigned_abs().to_string();
+        let sign = if self.unscaled < 0 { "-" } else { "" };
+        let scale = self.scale as usize;
+        if scale == 0 {
+            return write!(f, "{sign}{digits}");
+        }
+
+        let digits = format!("{digits:0>width$}", width = scale + 1);
+        let (int, fraction) = digits.split_at(digits.len() - scale);
+        write!(f, "{sign}{int}.{fraction}")
+    }
+}
+
+/// Writes the date `days` after 1970-01-01 as YYYY-MM-DD
+fn write_date(f: &mut fmt::Formatter<'_>, days: i64) -> fmt::Result {
+    let (year, month, day) = civil_from_days(days);
+    write!(f, "{year:04}-{month:02}-{day:02}")
+}
+
+/// Days since 1970-01-01 of the date, see http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i32, month: u32, day: u32) -> i32 {
+    let year = if month <= 2 { year - 1 } else { year } as i64;
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    (era * 146097 + day_of_era - 719468) as i32
+}
+
+/// Date `days` after 1970-01-01, the inverse of `days_from_civil`
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
 }
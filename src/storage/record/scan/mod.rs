@@ -7,20 +7,36 @@ mod table_scan;
 
 pub(crate) use table_scan::TableScan;
 
+/// Dates are read with `get_int` as days since 1970-01-01, timestamps with `get_long` as
+/// microseconds since 1970-01-01 00:00:00 UTC and decimals with `get_long` unscaled, `get_val`
+/// reads any field as its typed `Constant`. TEXT fields are read with `get_string` and BLOB
+/// fields with `get_bytes`, either of them reads the bytes of a TEXT field
 pub trait Scan: RowImpl {
     fn get_int(&mut self, field_name: &str) -> DbResult<i32>;
+    fn get_long(&mut self, field_name: &str) -> DbResult<i64>;
+    fn get_double(&mut self, field_name: &str) -> DbResult<f64>;
+    fn get_bool(&mut self, field_name: &str) -> DbResult<bool>;
     fn get_string(&mut self, field_name: &str) -> DbResult<String>;
+    fn get_bytes(&mut self, field_name: &str) -> DbResult<Vec<u8>>;
+    /// Returns `Constant::Null` if the field is NULL
     fn get_val(&mut self, field_name: &str) -> DbResult<Constant>;
+    fn is_null(&mut self, field_name: &str) -> DbResult<bool>;
     fn before_first(&mut self) -> DbResult<()>;
     fn has_field(&self, field_name: &str) -> bool;
     fn close(&mut self) -> DbResult<()>;
     fn next(&mut self) -> DbResult<bool>;
 }
 
+/// Every field is written in the form `Scan` reads it
 pub trait UpdateScan: Scan {
     fn set_int(&mut self, field_name: &str, val: i32) -> DbResult<()>;
+    fn set_long(&mut self, field_name: &str, val: i64) -> DbResult<()>;
+    fn set_double(&mut self, field_name: &str, val: f64) -> DbResult<()>;
+    fn set_bool(&mut self, field_name: &str, val: bool) -> DbResult<()>;
     fn set_string(&mut self, field_name: &str, val: String) -> DbResult<()>;
+    fn set_bytes(&mut self, field_name: &str, val: Vec<u8>) -> DbResult<()>;
     fn set_val(&mut self, field_name: &str, val: Constant) -> DbResult<()>;
+    fn set_null(&mut self, field_name: &str) -> DbResult<()>;
     fn insert(&mut self) -> DbResult<()>;
     fn delete(&mut self) -> DbResult<()>;
 }
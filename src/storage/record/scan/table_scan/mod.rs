@@ -2,40 +2,65 @@ mod test;
 
 use crate::{
     common::{maybeinit::MaybeInit, slot::Slot},
-    error::DbResult,
+    consts::PAGE_LSN_BYTES,
+    error::{DbError, DbResult},
     storage::disk::block::Block,
     storage::record::{
-        layout::Layout,
-        record_page::RecordPage,
+        layout::{Layout, RecordFormat},
+        overflow::{OverflowReader, OverflowRef, OverflowWriter},
+        record_page::{RecordFlags, RecordPage, SlotHeader},
         rowid::RowId,
-        scan::{constant::Constant, RowImpl, Scan, UpdateScan},
+        scan::{
+            constant::{Constant, Decimal},
+            RowImpl, Scan, UpdateScan,
+        },
         schema::FieldType,
     },
-    storage::tx::Transactions,
+    storage::tx::{Transactions, NO_TX},
+};
+use std::{
+    collections::HashMap,
+    io::{self, Read},
 };
 
 /// TableScan is to store and scan through the whole table
+///
+/// Updating a record keeps the version it replaces in another slot for the transactions which
+/// still see it, the record itself stays in its slot so its RowId does not change.
+///
+/// TEXT and BLOB values are kept out of line in chains of blocks of the overflow file of the
+/// table, the record only refers to the chain, see `OverflowRef`.
 pub struct TableScan {
     tx: Transactions,
     layout: Layout,
     rp: MaybeInit<RecordPage>,
     file_name: String,
+    overflow_file: String,
     current_slot: Slot,
 }
 
 impl TableScan {
-    /// New object of tablescan takes Transactions, Table Name, Layout as parameters
-    pub fn new(mut tx: Transactions, table: String, layout: Layout) -> DbResult<Self> {
+    /// New object of tablescan takes Transactions, Table Name, Layout as parameters. Fails
+    /// with `RecordDoesNotFit` if a slot of a fixed size layout is larger than a block, large
+    /// values belong in TEXT or BLOB fields
+    pub fn new(tx: Transactions, table: String, layout: Layout) -> DbResult<Self> {
+        if layout.format() == RecordFormat::Fixed
+            && layout.slot_size().get() as u64 > tx.blocksize() - PAGE_LSN_BYTES as u64
+        {
+            return Err(DbError::RecordDoesNotFit);
+        }
+
         let file_name = format!("{table}.tbl");
         let mut obj = Self {
             tx: tx.clone(),
             layout,
             rp: MaybeInit::new(),
             file_name: file_name.clone(),
+            overflow_file: format!("{table}.ovf"),
             current_slot: Slot::UnInit,
         };
 
-        if tx.size(file_name)? == 0 {
+        if obj.size()? == 0 {
             obj.move_to_new_block()?;
         } else {
             obj.move_to_block(0)?;
@@ -48,10 +73,7 @@ impl TableScan {
     fn move_to_new_block(&mut self) -> DbResult<()> {
         self.close()?;
 
-        let blk = self.tx.append(self.file_name.clone())?;
-        let mut rp = RecordPage::new(self.tx.clone(), blk, self.layout.clone());
-        rp.format()?;
-
+        let rp = self.new_block()?;
         self.rp.overwrite(rp);
 
         self.current_slot = Slot::UnInit;
@@ -59,11 +81,20 @@ impl TableScan {
         Ok(())
     }
 
+    /// Appends a new formatted block to the file
+    fn new_block(&mut self) -> DbResult<RecordPage> {
+        let blk = self.tx.append(self.file_name.clone())?;
+        let mut rp = RecordPage::new(self.tx.clone(), blk, self.layout.clone())?;
+        rp.format()?;
+
+        Ok(rp)
+    }
+
     ///Moves the record page to a block number provided
     fn move_to_block(&mut self, block_num: u64) -> DbResult<()> {
         self.close()?;
         let blk = Block::new(self.file_name.clone(), block_num);
-        let rp = RecordPage::new(self.tx.clone(), blk, self.layout.clone());
+        let rp = RecordPage::new(self.tx.clone(), blk, self.layout.clone())?;
         self.rp.overwrite(rp);
 
         self.current_slot = Slot::UnInit;
@@ -73,7 +104,224 @@ impl TableScan {
 
     /// Checks if the current record page is at the last block of the file
     fn at_last_block(&mut self) -> DbResult<bool> {
-        Ok(self.rp.block().num() == self.tx.size(self.file_name.clone())? - 1)
+        Ok(self.rp.block().num() == self.size()? - 1)
+    }
+
+    /// Number of blocks of the table, transactions reading under a snapshot do not lock the
+    /// end of the file so they never block the transactions appending to it
+    fn size(&mut self) -> DbResult<u64> {
+        if self.tx.has_snapshot() {
+            self.tx.size_unlocked(self.file_name.clone())
+        } else {
+            self.tx.size(self.file_name.clone())
+        }
+    }
+
+    /// Claims a free slot for a version with the given header and fields, if known. The slots
+    /// after `slot` in the block `block_num` are looked at first, then the following blocks, a
+    /// new block is appended once the table is full
+    fn claim_slot(
+        &mut self,
+        block_num: u64,
+        slot: Slot,
+        header: &SlotHeader,
+        fields: Option<&[u8]>,
+    ) -> DbResult<(RecordPage, Slot)> {
+        let blk = Block::new(self.file_name.clone(), block_num);
+        let mut rp = RecordPage::new(self.tx.clone(), blk, self.layout.clone())?;
+        let mut slot = rp.claim_after(slot, header, fields)?;
+
+        while slot.is_uninit() {
+            let num = rp.block().num();
+            self.tx.unpin(rp.block())?;
+
+            rp = if num == self.tx.size(self.file_name.clone())? - 1 {
+                self.new_block()?
+            } else {
+                let blk = Block::new(self.file_name.clone(), num + 1);
+                RecordPage::new(self.tx.clone(), blk, self.layout.clone())?
+            };
+            slot = rp.claim_after(slot, header, fields)?;
+        }
+
+        Ok((rp, slot))
+    }
+
+    /// Locks the current record for writing. Unless the transaction created the version
+    /// itself, the version is copied to another slot first, the copy is what the transactions
+    /// which don't see this transaction's changes keep reading
+    fn prepare_write(&mut self) -> DbResult<()> {
+        let head = self.rp.lock_for_write(self.current_slot)?;
+        let txnum = self.tx.txnum() as i64;
+
+        if head.xmin == txnum {
+            return Ok(());
+        }
+
+        let version = SlotHeader {
+            flag: RecordFlags::Version as i32,
+            xmin: head.xmin,
+            xmax: txnum,
+            prev: head.prev.clone(),
+        };
+        // The copy is logged as a single insert of the whole record
+        let fields = {
+            let _latch = self.tx.latch(self.rp.block());
+            self.rp.fields_bytes(self.current_slot)?
+        };
+        let (copy, copy_slot) =
+            self.claim_slot(self.rp.block().num(), Slot::UnInit, &version, Some(&fields))?;
+        self.tx.unpin(copy.block())?;
+
+        // The record points to the copy before anyone can see the new version
+        self.rp.set_header(
+            self.current_slot,
+            &SlotHeader {
+                flag: RecordFlags::Used as i32,
+                xmin: txnum,
+                xmax: NO_TX,
+                prev: Some(RowId::new(copy.block().num(), copy_slot)),
+            },
+        )
+    }
+
+    /// Streams the value of a TEXT or BLOB field from its overflow blocks, `None` if the field
+    /// is NULL
+    pub fn read_blob(&mut self, field_name: &str) -> DbResult<Option<OverflowReader>> {
+        self.check_overflow(field_name)?;
+        if self.is_null(field_name)? {
+            return Ok(None);
+        }
+
+        let reference = self.overflow_ref(field_name)?;
+        Ok(Some(OverflowReader::new(
+            self.tx.clone(),
+            self.overflow_file.clone(),
+            reference,
+        )))
+    }
+
+    /// Sets a TEXT or BLOB field to the bytes read from `data`, which are streamed to new
+    /// overflow blocks one block at a time. Returns the length of the value
+    pub fn write_blob(&mut self, field_name: &str, mut data: impl Read) -> DbResult<u64> {
+        self.check_overflow(field_name)?;
+        // A conflicting write fails before anything is written
+        self.prepare_write()?;
+        let old = self.old_overflow_ref(field_name)?;
+
+        let mut writer = OverflowWriter::new(self.tx.clone(), self.overflow_file.clone());
+        let mut buf = vec![0; self.tx.blocksize() as usize];
+        loop {
+            match data.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => writer.append(&buf[..n])?,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        let reference = writer.finish(field_name)?;
+
+        self.rp
+            .set_long(self.current_slot, field_name, reference.to_long())?;
+        if let Some(old) = old {
+            self.tx.chain_replaced(&self.overflow_file, old);
+        }
+        Ok(reference.len() as u64)
+    }
+
+    /// Reference to the chain of a TEXT or BLOB field of the current record, it must not be
+    /// NULL
+    fn overflow_ref(&mut self, field_name: &str) -> DbResult<OverflowRef> {
+        Ok(OverflowRef::from_long(
+            self.rp.get_long(self.current_slot, field_name)?,
+        ))
+    }
+
+    /// Reference to the chain a write to the field of the current record is about to replace,
+    /// `None` if the field is NULL or not a TEXT or BLOB field
+    fn old_overflow_ref(&mut self, field_name: &str) -> DbResult<Option<OverflowRef>> {
+        if !self.is_overflow(field_name)? || self.is_null(field_name)? {
+            return Ok(None);
+        }
+        self.overflow_ref(field_name).map(Some)
+    }
+
+    /// Whether the field is a TEXT or BLOB field, kept in overflow blocks
+    fn is_overflow(&self, field_name: &str) -> DbResult<bool> {
+        Ok(matches!(
+            self.layout.schema().typ(field_name)?,
+            FieldType::Text | FieldType::Blob
+        ))
+    }
+
+    /// Fails with `NotOverflowField` unless the field is kept in overflow blocks
+    fn check_overflow(&self, field_name: &str) -> DbResult<()> {
+        match self.is_overflow(field_name)? {
+            true => Ok(()),
+            false => Err(DbError::NotOverflowField(field_name.to_string())),
+        }
+    }
+
+    /// Fails with `FieldTypeMismatch` unless the field is of one of the types
+    fn check_type(&self, field_name: &str, types: &[FieldType]) -> DbResult<()> {
+        match types.contains(&self.layout.schema().typ(field_name)?) {
+            true => Ok(()),
+            false => Err(DbError::FieldTypeMismatch(field_name.to_string())),
+        }
+    }
+
+    /// Frees the slots of the record versions no transaction can see anymore, returns the
+    /// number of slots freed. Free slots are reused by inserts and updates anyway, vacuuming
+    /// only makes them available sooner, except for tables with TEXT or BLOB fields.
+    ///
+    /// The overflow chains only the thrown away versions refer to are freed once the
+    /// transaction commits. A chain is shared by the versions of a record from the one which
+    /// set the value till the one which replaced it, so the chain of a version is kept if the
+    /// version replacing it still refers to it. A deleted record has no newer version.
+    pub fn vacuum(&mut self) -> DbResult<usize> {
+        let fields = self.layout.schema().overflow_fields();
+        let size = self.tx.size(self.file_name.clone())?;
+
+        let mut replacements = HashMap::new();
+        if !fields.is_empty() {
+            for num in 0..size {
+                let blk = Block::new(self.file_name.clone(), num);
+                let mut rp = RecordPage::new(self.tx.clone(), blk, self.layout.clone())?;
+                replacements.extend(rp.replacements(&fields)?);
+                self.tx.unpin(rp.block())?;
+            }
+        }
+
+        let mut freed = 0;
+        for num in 0..size {
+            let blk = Block::new(self.file_name.clone(), num);
+            let mut rp = RecordPage::new(self.tx.clone(), blk, self.layout.clone())?;
+
+            for vacuumed in rp.vacuum(&fields)? {
+                freed += 1;
+                let newer = if vacuumed.header.is(RecordFlags::Version) {
+                    // A version nobody links to anymore can't be told apart from its newer
+                    // version, its chains are left alone
+                    match replacements.get(&RowId::new(num, vacuumed.slot)) {
+                        Some(newer) => Some(newer),
+                        None => continue,
+                    }
+                } else {
+                    None
+                };
+
+                for (i, value) in vacuumed.values.into_iter().enumerate() {
+                    let kept = newer.is_some_and(|newer| newer[i] == value);
+                    if let (Some(value), false) = (value, kept) {
+                        self.tx
+                            .chain_released(&self.overflow_file, OverflowRef::from_long(value));
+                    }
+                }
+            }
+            self.tx.unpin(rp.block())?;
+        }
+
+        Ok(freed)
     }
 }
 
@@ -86,7 +334,7 @@ impl RowImpl for TableScan {
         self.close()?;
 
         let blk = Block::new(self.file_name.clone(), row_id.blk_num());
-        let rp = RecordPage::new(self.tx.clone(), blk, self.layout.clone());
+        let rp = RecordPage::new(self.tx.clone(), blk, self.layout.clone())?;
 
         self.rp.overwrite(rp);
         self.current_slot = row_id.slot();
@@ -123,11 +371,49 @@ impl Scan for TableScan {
     }
 
     fn get_int(&mut self, field_name: &str) -> DbResult<i32> {
+        // Dates are kept as ints too
+        self.check_type(field_name, &[FieldType::Integer, FieldType::Date])?;
         self.rp.get_int(self.current_slot, field_name)
     }
 
+    fn get_long(&mut self, field_name: &str) -> DbResult<i64> {
+        // Timestamps and decimals are kept as longs too
+        self.check_type(
+            field_name,
+            &[FieldType::BigInt, FieldType::Timestamp, FieldType::Decimal],
+        )?;
+        self.rp.get_long(self.current_slot, field_name)
+    }
+
+    fn get_double(&mut self, field_name: &str) -> DbResult<f64> {
+        self.check_type(field_name, &[FieldType::Double])?;
+        let bits = self.rp.get_long(self.current_slot, field_name)?;
+        Ok(f64::from_bits(bits as u64))
+    }
+
+    fn get_bool(&mut self, field_name: &str) -> DbResult<bool> {
+        self.check_type(field_name, &[FieldType::Boolean])?;
+        Ok(self.rp.get_int(self.current_slot, field_name)? != 0)
+    }
+
     fn get_string(&mut self, field_name: &str) -> DbResult<String> {
-        self.rp.get_string(self.current_slot, field_name)
+        self.check_type(field_name, &[FieldType::Varchar, FieldType::Text])?;
+        if !self.is_overflow(field_name)? {
+            return self.rp.get_string(self.current_slot, field_name);
+        }
+
+        String::from_utf8(self.get_bytes(field_name)?)
+            .map_err(|_| DbError::InvalidText(field_name.to_string()))
+    }
+
+    fn get_bytes(&mut self, field_name: &str) -> DbResult<Vec<u8>> {
+        self.check_overflow(field_name)?;
+        if self.is_null(field_name)? {
+            return Err(DbError::NullValue(field_name.to_string()));
+        }
+
+        let reference = self.overflow_ref(field_name)?;
+        OverflowReader::new(self.tx.clone(), self.overflow_file.clone(), reference).read_all()
     }
 
     fn has_field(&self, field_name: &str) -> bool {
@@ -135,10 +421,30 @@ impl Scan for TableScan {
     }
 
     fn get_val(&mut self, field_name: &str) -> DbResult<Constant> {
-        match self.layout.schema().typ(field_name)? {
-            FieldType::Integer => Ok(Constant::with_int(self.get_int(field_name)?)),
-            FieldType::Varchar => Ok(Constant::with_string(self.get_string(field_name)?)),
+        if self.is_null(field_name)? {
+            return Ok(Constant::Null);
         }
+
+        let schema = self.layout.schema();
+        Ok(match schema.typ(field_name)? {
+            FieldType::Integer => Constant::with_int(self.get_int(field_name)?),
+            FieldType::Varchar => Constant::with_string(self.get_string(field_name)?),
+            FieldType::BigInt => Constant::with_long(self.get_long(field_name)?),
+            FieldType::Double => Constant::with_double(self.get_double(field_name)?),
+            FieldType::Boolean => Constant::with_bool(self.get_bool(field_name)?),
+            FieldType::Date => Constant::Date(self.get_int(field_name)?),
+            FieldType::Timestamp => Constant::with_timestamp(self.get_long(field_name)?),
+            FieldType::Decimal => {
+                let scale = schema.length(&field_name.to_string())? as u32;
+                Constant::with_decimal(Decimal::new(self.get_long(field_name)?, scale))
+            }
+            FieldType::Text => Constant::with_string(self.get_string(field_name)?),
+            FieldType::Blob => Constant::with_bytes(self.get_bytes(field_name)?),
+        })
+    }
+
+    fn is_null(&mut self, field_name: &str) -> DbResult<bool> {
+        self.rp.is_null(self.current_slot, field_name)
     }
 
     fn before_first(&mut self) -> DbResult<()> {
@@ -148,32 +454,102 @@ impl Scan for TableScan {
 
 impl UpdateScan for TableScan {
     fn set_int(&mut self, field_name: &str, val: i32) -> DbResult<()> {
+        self.check_type(field_name, &[FieldType::Integer, FieldType::Date])?;
+        self.prepare_write()?;
         self.rp.set_int(self.current_slot, field_name, val)
     }
 
+    fn set_long(&mut self, field_name: &str, val: i64) -> DbResult<()> {
+        self.check_type(
+            field_name,
+            &[FieldType::BigInt, FieldType::Timestamp, FieldType::Decimal],
+        )?;
+        self.prepare_write()?;
+        self.rp.set_long(self.current_slot, field_name, val)
+    }
+
+    fn set_double(&mut self, field_name: &str, val: f64) -> DbResult<()> {
+        self.check_type(field_name, &[FieldType::Double])?;
+        self.prepare_write()?;
+        self.rp
+            .set_long(self.current_slot, field_name, val.to_bits() as i64)
+    }
+
+    fn set_bool(&mut self, field_name: &str, val: bool) -> DbResult<()> {
+        self.check_type(field_name, &[FieldType::Boolean])?;
+        self.prepare_write()?;
+        self.rp.set_int(self.current_slot, field_name, val as i32)
+    }
+
     fn set_string(&mut self, field_name: &str, val: String) -> DbResult<()> {
+        self.check_type(field_name, &[FieldType::Varchar, FieldType::Text])?;
+        if self.is_overflow(field_name)? {
+            return self.write_blob(field_name, val.as_bytes()).map(|_| ());
+        }
+
+        self.prepare_write()?;
         self.rp.set_string(self.current_slot, field_name, val)
     }
 
+    fn set_bytes(&mut self, field_name: &str, val: Vec<u8>) -> DbResult<()> {
+        self.write_blob(field_name, val.as_slice()).map(|_| ())
+    }
+
     fn set_val(&mut self, field_name: &str, val: Constant) -> DbResult<()> {
-        if self.layout.schema().typ(field_name)? == FieldType::Integer {
-            self.set_int(field_name, val.into_int())
-        } else {
-            self.set_string(field_name, val.into_string())
+        if val.is_null() {
+            return self.set_null(field_name);
         }
-    }
 
-    fn insert(&mut self) -> DbResult<()> {
-        self.current_slot = self.rp.insert_after(self.current_slot)?;
-        while self.current_slot.is_uninit() {
-            if self.at_last_block()? {
-                self.move_to_new_block()?;
-            } else {
-                let num = self.rp.block().num();
-                self.move_to_block(num + 1)?;
+        let schema = self.layout.schema();
+        let mismatch = || DbError::FieldTypeMismatch(field_name.to_string());
+        match (schema.typ(field_name)?, val) {
+            (FieldType::Integer, Constant::Int(v)) => self.set_int(field_name, v),
+            (FieldType::Varchar | FieldType::Text, Constant::String(v)) => {
+                self.set_string(field_name, v)
             }
-            self.current_slot = self.rp.insert_after(self.current_slot)?;
+            (FieldType::BigInt, Constant::BigInt(v)) => self.set_long(field_name, v),
+            (FieldType::BigInt, Constant::Int(v)) => self.set_long(field_name, v as i64),
+            (FieldType::Double, val) => {
+                let v = val.as_f64().ok_or_else(mismatch)?;
+                self.set_double(field_name, v)
+            }
+            (FieldType::Boolean, Constant::Bool(v)) => self.set_bool(field_name, v),
+            (FieldType::Date, Constant::Date(v)) => self.set_int(field_name, v),
+            (FieldType::Timestamp, Constant::Timestamp(v)) => self.set_long(field_name, v),
+            (FieldType::Decimal, val) => {
+                // The value is stored with the scale of the field
+                let scale = schema.length(&field_name.to_string())? as u32;
+                let decimal = val
+                    .as_decimal()
+                    .ok_or_else(mismatch)?
+                    .rescale(scale)
+                    .ok_or_else(|| DbError::DecimalOverflow(field_name.to_string()))?;
+                self.set_long(field_name, decimal.unscaled())
+            }
+            (FieldType::Blob, Constant::Bytes(v)) => self.set_bytes(field_name, v),
+            _ => Err(mismatch()),
         }
+    }
+
+    fn set_null(&mut self, field_name: &str) -> DbResult<()> {
+        self.prepare_write()?;
+        let old = self.old_overflow_ref(field_name)?;
+        self.rp.set_null(self.current_slot, field_name)?;
+
+        if let Some(old) = old {
+            self.tx.chain_replaced(&self.overflow_file, old);
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self) -> DbResult<()> {
+        let header = SlotHeader::inserted(self.tx.txnum());
+        let (rp, slot) =
+            self.claim_slot(self.rp.block().num(), self.current_slot, &header, None)?;
+
+        self.close()?;
+        self.rp.overwrite(rp);
+        self.current_slot = slot;
 
         Ok(())
     }
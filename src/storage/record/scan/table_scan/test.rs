@@ -1,15 +1,26 @@
 #[cfg(test)]
 mod tests {
-    use std::fs;
+    use std::{
+        fs,
+        io::{self, Read},
+        time::Duration,
+    };
 
     use rand::{distr::Uniform, prelude::Distribution};
 
     use crate::{
+        error::DbError,
         server::{DBServer, DBServerOptions},
+        storage::meta::tablemgr::TableManager,
         storage::record::{
-            layout::Layout,
-            scan::{table_scan::TableScan, RowImpl, Scan, UpdateScan},
-            schema::Schema,
+            layout::{Layout, RecordFormat},
+            rowid::RowId,
+            scan::{
+                constant::{Constant, Decimal},
+                table_scan::TableScan,
+                RowImpl, Scan, UpdateScan,
+            },
+            schema::{FieldType, Schema},
         },
     };
 
@@ -29,7 +40,7 @@ mod tests {
         sch.add_string_field("B".to_string(), 9);
         let layout = Layout::new(sch).unwrap();
 
-        let e = [("A", 4), ("B", 8)];
+        let e = [("A", 28), ("B", 32)];
         for (i, fldname) in layout.schema().fields().iter().enumerate() {
             assert_eq!(fldname, e[i].0);
 
@@ -47,8 +58,8 @@ mod tests {
             ts.set_string("B", format!("rec{n}")).unwrap();
 
             let rid = ts.get_row_id();
-            assert_eq!(rid.blk_num(), i / 19);
-            assert_eq!(rid.slot().inner(), i as usize % 19);
+            assert_eq!(rid.blk_num(), i / 8);
+            assert_eq!(rid.slot().inner(), i as usize % 8);
             assert!((0..50).contains(&n));
         }
 
@@ -63,12 +74,12 @@ mod tests {
                 let rid = ts.get_row_id();
 
                 let blknum = rid.blk_num();
-                assert!(blknum <= 2);
+                assert!(blknum <= 6);
 
                 let slot = rid.slot();
-                assert!(slot.inner() < 19);
+                assert!(slot.inner() < 8);
 
-                assert!(blknum * 19 + (slot.inner() as u64) < 50);
+                assert!(blknum * 8 + (slot.inner() as u64) < 50);
 
                 assert!(a < 25);
                 assert_eq!(format!("rec{a}"), b);
@@ -86,12 +97,12 @@ mod tests {
             let rid = ts.get_row_id();
 
             let blknum = rid.blk_num();
-            assert!(blknum <= 2);
+            assert!(blknum <= 6);
 
             let slot = rid.slot();
-            assert!(slot.inner() < 19);
+            assert!(slot.inner() < 8);
 
-            assert!(blknum * 19 + (slot.inner() as u64) < 50);
+            assert!(blknum * 8 + (slot.inner() as u64) < 50);
 
             assert!(a >= 25);
             assert_eq!(format!("rec{a}"), b);
@@ -101,4 +112,819 @@ mod tests {
 
         fs::remove_dir_all("tabletest").unwrap();
     }
+
+    #[test]
+    fn rowlocktest() {
+        let db = DBServer::new_with_params(
+            DBServerOptions::default()
+                .block_size(400)
+                .directory("rowlocktest".to_string())
+                .pool_size(8)
+                .lock_timeout(Duration::from_millis(100)),
+        )
+        .unwrap();
+
+        let mut sch = Schema::new();
+        sch.add_int_field("A".to_string());
+        sch.add_string_field("B".to_string(), 9);
+        let layout = Layout::new(sch).unwrap();
+
+        let mut tx = db.new_tx().unwrap();
+        let mut ts = TableScan::new(tx.clone(), "rowlock".to_string(), layout.clone()).unwrap();
+        let mut rids = Vec::new();
+        for i in 0..3 {
+            ts.insert().unwrap();
+            ts.set_int("A", i).unwrap();
+            ts.set_string("B", format!("rec{i}")).unwrap();
+            rids.push(ts.get_row_id());
+        }
+        ts.close().unwrap();
+        tx.commit().unwrap();
+
+        // Every row lives in the same block
+        assert!(rids.iter().all(|rid| rid.blk_num() == 0));
+
+        // Two transactions update different rows of the block without waiting for each other
+        let mut tx1 = db.new_tx().unwrap();
+        let mut tx2 = db.new_tx().unwrap();
+        let mut ts1 = TableScan::new(tx1.clone(), "rowlock".to_string(), layout.clone()).unwrap();
+        let mut ts2 = TableScan::new(tx2.clone(), "rowlock".to_string(), layout.clone()).unwrap();
+
+        ts1.move_to_row_id(rids[0].clone()).unwrap();
+        ts1.set_int("A", 10).unwrap();
+        ts2.move_to_row_id(rids[1].clone()).unwrap();
+        ts2.set_int("A", 11).unwrap();
+        ts2.set_string("B", "rec11".to_string()).unwrap();
+
+        // A row written by the other transaction stays locked till it commits
+        ts2.move_to_row_id(rids[0].clone()).unwrap();
+        assert!(matches!(ts2.get_int("A"), Err(DbError::LockAborted)));
+
+        // Inserting into the shared block claims a slot nobody else is using
+        ts1.insert().unwrap();
+        ts1.set_int("A", 3).unwrap();
+        let inserted = ts1.get_row_id();
+        assert_eq!(inserted.blk_num(), 0);
+        assert!(!rids.contains(&inserted));
+
+        ts1.close().unwrap();
+        tx1.commit().unwrap();
+        ts2.get_int("A").unwrap();
+        ts2.close().unwrap();
+        tx2.commit().unwrap();
+
+        let mut tx = db.new_tx().unwrap();
+        let mut ts = TableScan::new(tx.clone(), "rowlock".to_string(), layout).unwrap();
+        let mut rows = Vec::new();
+        while ts.next().unwrap() {
+            rows.push((ts.get_int("A").unwrap(), ts.get_string("B").unwrap()));
+        }
+        rows.sort();
+        assert_eq!(
+            rows,
+            vec![
+                (2, "rec2".to_string()),
+                (3, String::new()),
+                (10, "rec0".to_string()),
+                (11, "rec11".to_string()),
+            ]
+        );
+        ts.close().unwrap();
+        tx.commit().unwrap();
+
+        fs::remove_dir_all("rowlocktest").unwrap();
+    }
+
+    #[test]
+    fn snapshottest() {
+        let db = DBServer::new_with_params(
+            DBServerOptions::default()
+                .block_size(400)
+                .directory("snapshottest".to_string())
+                .pool_size(8)
+                .lock_timeout(Duration::from_millis(100)),
+        )
+        .unwrap();
+
+        let mut sch = Schema::new();
+        sch.add_int_field("A".to_string());
+        sch.add_string_field("B".to_string(), 9);
+        let layout = Layout::new(sch).unwrap();
+
+        let read_all = |ts: &mut TableScan| {
+            let mut rows = Vec::new();
+            ts.before_first().unwrap();
+            while ts.next().unwrap() {
+                rows.push((ts.get_int("A").unwrap(), ts.get_string("B").unwrap()));
+            }
+            rows.sort();
+            rows
+        };
+        let original = (0..3).map(|i| (i, format!("rec{i}"))).collect::<Vec<_>>();
+
+        let mut tx = db.new_tx().unwrap();
+        let mut ts = TableScan::new(tx.clone(), "snapshot".to_string(), layout.clone()).unwrap();
+        let mut rids = Vec::new();
+        for (a, b) in &original {
+            ts.insert().unwrap();
+            ts.set_int("A", *a).unwrap();
+            ts.set_string("B", b.clone()).unwrap();
+            rids.push(ts.get_row_id());
+        }
+        ts.close().unwrap();
+        tx.commit().unwrap();
+
+        let mut reader = db.new_tx().unwrap();
+        reader.begin_snapshot();
+        let mut rs =
+            TableScan::new(reader.clone(), "snapshot".to_string(), layout.clone()).unwrap();
+        assert_eq!(read_all(&mut rs), original);
+
+        // The writer changes every record the reader already read
+        let mut writer = db.new_tx().unwrap();
+        let mut ws =
+            TableScan::new(writer.clone(), "snapshot".to_string(), layout.clone()).unwrap();
+        ws.move_to_row_id(rids[0].clone()).unwrap();
+        ws.set_int("A", 10).unwrap();
+        ws.set_string("B", "rec10".to_string()).unwrap();
+        assert_eq!(ws.get_row_id(), rids[0]);
+        ws.move_to_row_id(rids[1].clone()).unwrap();
+        ws.delete().unwrap();
+        ws.insert().unwrap();
+        ws.set_int("A", 3).unwrap();
+        ws.set_string("B", "rec3".to_string()).unwrap();
+
+        // The reader neither waits for the writer nor sees its changes, before or after the
+        // writer commits
+        assert_eq!(read_all(&mut rs), original);
+        ws.close().unwrap();
+        writer.commit().unwrap();
+        assert_eq!(read_all(&mut rs), original);
+
+        // The records were changed after the snapshot, so the reader cannot change them
+        rs.move_to_row_id(rids[0].clone()).unwrap();
+        assert_eq!(rs.get_int("A").unwrap(), 0);
+        assert!(matches!(
+            rs.set_int("A", 20),
+            Err(DbError::WriteConflict(_))
+        ));
+        rs.move_to_row_id(rids[1].clone()).unwrap();
+        assert!(matches!(rs.delete(), Err(DbError::WriteConflict(_))));
+        rs.close().unwrap();
+        reader.rollback().unwrap();
+
+        let changed = vec![
+            (2, "rec2".to_string()),
+            (3, "rec3".to_string()),
+            (10, "rec10".to_string()),
+        ];
+
+        let mut tx = db.new_tx().unwrap();
+        tx.begin_snapshot();
+        let mut ts = TableScan::new(tx.clone(), "snapshot".to_string(), layout.clone()).unwrap();
+        assert_eq!(read_all(&mut ts), changed);
+        ts.close().unwrap();
+        tx.commit().unwrap();
+
+        // Throwing away the old versions leaves the current ones alone
+        let mut tx = db.new_tx().unwrap();
+        let mut ts = TableScan::new(tx.clone(), "snapshot".to_string(), layout).unwrap();
+        ts.vacuum().unwrap();
+        assert_eq!(read_all(&mut ts), changed);
+        ts.close().unwrap();
+        tx.commit().unwrap();
+
+        fs::remove_dir_all("snapshottest").unwrap();
+    }
+
+    #[test]
+    fn nulltest() {
+        let db = DBServer::new_with_params(
+            DBServerOptions::default()
+                .block_size(400)
+                .directory("nulltest".to_string())
+                .pool_size(8),
+        )
+        .unwrap();
+
+        let mut sch = Schema::new();
+        sch.add_int_field("A".to_string());
+        sch.add_nullable_field("B".to_string(), FieldType::Varchar, 9);
+        sch.add_nullable_field("C".to_string(), FieldType::Integer, 0);
+        let layout = Layout::new(sch).unwrap();
+
+        // The null bitmap ends the slot header
+        assert_eq!(layout.null_bitmap_bytes(), 4);
+        assert_eq!(layout.offset("A").unwrap(), 32);
+        assert_eq!(layout.null_bit("A").unwrap(), None);
+        assert_eq!(layout.null_bit("C").unwrap(), Some(1));
+
+        let mut tx = db.new_tx().unwrap();
+        let mut ts = TableScan::new(tx.clone(), "nulls".to_string(), layout.clone()).unwrap();
+        ts.insert().unwrap();
+        let rid = ts.get_row_id();
+        assert!(!ts.is_null("A").unwrap());
+        assert!(ts.is_null("B").unwrap());
+        assert_eq!(ts.get_val("C").unwrap(), Constant::Null);
+
+        ts.set_int("A", 1).unwrap();
+        ts.set_string("B", "one".to_string()).unwrap();
+        ts.set_val("C", Constant::with_int(5)).unwrap();
+        assert!(!ts.is_null("B").unwrap());
+        assert_eq!(ts.get_val("C").unwrap(), Constant::with_int(5));
+        ts.set_val("B", Constant::Null).unwrap();
+        assert_eq!(ts.get_val("B").unwrap(), Constant::Null);
+        assert!(matches!(
+            ts.set_null("A"),
+            Err(DbError::FieldNotNullable(field)) if field == "A"
+        ));
+        ts.close().unwrap();
+        tx.commit().unwrap();
+
+        // A snapshot keeps seeing the NULL the writer replaces, rolling back puts it back
+        let mut reader = db.new_tx().unwrap();
+        reader.begin_snapshot();
+        let mut rs = TableScan::new(reader.clone(), "nulls".to_string(), layout.clone()).unwrap();
+        let mut writer = db.new_tx().unwrap();
+        let mut ws = TableScan::new(writer.clone(), "nulls".to_string(), layout.clone()).unwrap();
+        ws.move_to_row_id(rid.clone()).unwrap();
+        ws.set_string("B", "two".to_string()).unwrap();
+        ws.set_null("C").unwrap();
+        rs.move_to_row_id(rid.clone()).unwrap();
+        assert!(rs.is_null("B").unwrap());
+        assert!(!rs.is_null("C").unwrap());
+        ws.close().unwrap();
+        writer.rollback().unwrap();
+        rs.close().unwrap();
+        reader.commit().unwrap();
+
+        let mut tx = db.new_tx().unwrap();
+        let mut ts = TableScan::new(tx.clone(), "nulls".to_string(), layout.clone()).unwrap();
+        ts.move_to_row_id(rid).unwrap();
+        assert_eq!(ts.get_val("B").unwrap(), Constant::Null);
+        assert_eq!(ts.get_val("C").unwrap(), Constant::with_int(5));
+        ts.close().unwrap();
+
+        // Comparing with NULL is unknown
+        let null = Constant::Null;
+        assert_eq!(null.equals(&Constant::Null), None);
+        assert_eq!(Constant::with_int(5).equals(&null), None);
+        assert_eq!(
+            Constant::with_int(5).equals(&Constant::with_int(5)),
+            Some(true)
+        );
+        assert!(Constant::with_int(1) < Constant::with_int(5));
+        assert_eq!(null.to_string(), "NULL");
+
+        // The catalog keeps which fields are nullable
+        let tm = TableManager::new(true, tx.clone()).unwrap();
+        tm.create_table("nulls".to_string(), layout.clone(), tx.clone())
+            .unwrap();
+        let stored = tm.get_layout("nulls", tx.clone()).unwrap();
+        assert_eq!(stored.offset("C").unwrap(), layout.offset("C").unwrap());
+        assert!(stored.schema().is_nullable("B").unwrap());
+        assert!(!stored.schema().is_nullable("A").unwrap());
+        assert_eq!(stored.null_bit("C").unwrap(), Some(1));
+        tx.commit().unwrap();
+
+        fs::remove_dir_all("nulltest").unwrap();
+    }
+
+    #[test]
+    fn typestest() {
+        let db = DBServer::new_with_params(
+            DBServerOptions::default()
+                .block_size(400)
+                .directory("typestest".to_string())
+                .pool_size(8),
+        )
+        .unwrap();
+
+        let mut sch = Schema::new();
+        sch.add_field("id".to_string(), FieldType::BigInt, 0);
+        sch.add_field("ratio".to_string(), FieldType::Double, 0);
+        sch.add_field("active".to_string(), FieldType::Boolean, 0);
+        sch.add_field("born".to_string(), FieldType::Date, 0);
+        sch.add_field("seen".to_string(), FieldType::Timestamp, 0);
+        sch.add_decimal_field("balance".to_string(), 2);
+        sch.add_nullable_field("closed".to_string(), FieldType::Date, 0);
+        let layout = Layout::new(sch).unwrap();
+        assert_eq!(
+            layout.offset("ratio").unwrap() - layout.offset("id").unwrap(),
+            8
+        );
+        assert_eq!(
+            layout.offset("born").unwrap() - layout.offset("active").unwrap(),
+            4
+        );
+
+        let mut tx = db.new_tx().unwrap();
+        let mut ts = TableScan::new(tx.clone(), "types".to_string(), layout.clone()).unwrap();
+        ts.insert().unwrap();
+        ts.set_long("id", 1 << 40).unwrap();
+        ts.set_double("ratio", 0.25).unwrap();
+        ts.set_bool("active", true).unwrap();
+        ts.set_val("born", Constant::with_date(1969, 12, 31))
+            .unwrap();
+        ts.set_val("seen", Constant::with_timestamp(86_400_000_000 + 1_500))
+            .unwrap();
+        ts.set_val("balance", Constant::with_decimal(Decimal::new(-12345, 3)))
+            .unwrap();
+        let rid = ts.get_row_id();
+
+        // Decimals are kept with the scale of the field
+        assert!(matches!(
+            ts.set_val("balance", Constant::with_long(i64::MAX)),
+            Err(DbError::DecimalOverflow(field)) if field == "balance"
+        ));
+        ts.close().unwrap();
+        tx.commit().unwrap();
+
+        let mut tx = db.new_tx().unwrap();
+        let mut ts = TableScan::new(tx.clone(), "types".to_string(), layout.clone()).unwrap();
+        ts.move_to_row_id(rid).unwrap();
+        assert_eq!(ts.get_long("id").unwrap(), 1 << 40);
+        assert_eq!(ts.get_double("ratio").unwrap(), 0.25);
+        assert!(ts.get_bool("active").unwrap());
+        assert_eq!(ts.get_int("born").unwrap(), -1);
+        // The getters and setters only access fields of their own type
+        for (field, result) in [
+            ("ratio", ts.get_long("ratio").err()),
+            ("id", ts.get_double("id").err()),
+            ("born", ts.get_bool("born").err()),
+            ("active", ts.get_int("active").err()),
+            ("id", ts.get_string("id").err()),
+            ("id", ts.set_int("id", 1).err()),
+            ("born", ts.set_long("born", 1).err()),
+            ("id", ts.set_double("id", 1.0).err()),
+            ("born", ts.set_bool("born", true).err()),
+            ("ratio", ts.set_string("ratio", "a".to_string()).err()),
+            ("active", ts.set_val("active", Constant::with_int(1)).err()),
+            (
+                "born",
+                ts.set_val("born", Constant::with_string("a".to_string()))
+                    .err(),
+            ),
+            (
+                "ratio",
+                ts.set_val("ratio", Constant::with_bool(true)).err(),
+            ),
+            (
+                "balance",
+                ts.set_val("balance", Constant::with_double(1.0)).err(),
+            ),
+        ] {
+            assert!(matches!(result, Some(DbError::FieldTypeMismatch(f)) if f == field));
+        }
+        assert_eq!(
+            ts.get_long("seen").unwrap(),
+            ts.get_val("seen").unwrap().into_timestamp()
+        );
+        let vals = ["born", "seen", "balance", "closed"]
+            .map(|field| ts.get_val(field).unwrap().to_string());
+        assert_eq!(
+            vals,
+            ["1969-12-31", "1970-01-02 00:00:00.001500", "-12.35", "NULL"]
+        );
+        assert_eq!(
+            ts.get_val("balance").unwrap(),
+            Constant::with_decimal(Decimal::new(-1235, 2))
+        );
+        ts.close().unwrap();
+
+        // Numbers of different types compare by their value
+        let decimal = Constant::with_decimal(Decimal::new(1050, 3));
+        assert_eq!(decimal.equals(&Constant::with_double(1.05)), Some(true));
+        assert!(decimal.compare(&Constant::with_int(1)).unwrap().is_gt());
+        assert_eq!(
+            Constant::with_decimal(Decimal::new(10, 1)).equals(&Constant::with_long(1)),
+            Some(true)
+        );
+        assert_eq!(decimal.equals(&Constant::with_bool(true)), None);
+        // Scales too far apart to widen the values still compare
+        let tiny = Decimal::new(1, 60);
+        assert!(Decimal::new(1, 0).compare(&tiny).is_gt());
+        assert!(Decimal::new(-1, 0).compare(&tiny).is_lt());
+        assert!(tiny.compare(&Decimal::new(0, 0)).is_gt());
+        assert!(Decimal::new(0, 0).compare(&Decimal::new(-1, 60)).is_gt());
+
+        // The catalog keeps the types, and rejects the ones it doesn't know
+        let tm = TableManager::new(true, tx.clone()).unwrap();
+        tm.create_table("types".to_string(), layout.clone(), tx.clone())
+            .unwrap();
+        let stored = tm.get_layout("types", tx.clone()).unwrap();
+        for field in layout.schema().fields() {
+            assert_eq!(
+                stored.schema().typ(field).unwrap(),
+                layout.schema().typ(field).unwrap()
+            );
+        }
+        assert_eq!(stored.schema().length(&"balance".to_string()).unwrap(), 2);
+        tx.commit().unwrap();
+        assert!(matches!(
+            FieldType::try_from(99),
+            Err(DbError::UnknownFieldType(99))
+        ));
+
+        fs::remove_dir_all("typestest").unwrap();
+    }
+
+    #[test]
+    fn slottedtest() {
+        let options = || {
+            DBServerOptions::default()
+                .block_size(400)
+                .directory("slottedtest".to_string())
+                .pool_size(8)
+        };
+        let mut sch = Schema::new();
+        sch.add_int_field("A".to_string());
+        sch.add_string_field("B".to_string(), 255);
+        sch.add_nullable_field("C".to_string(), FieldType::Varchar, 255);
+        let layout = Layout::new_slotted(sch.clone()).unwrap();
+
+        // A slot of fixed size wouldn't fit the block at all
+        assert!(Layout::new(sch).unwrap().slot_size().get() > 400);
+
+        let db = DBServer::new_with_params(options()).unwrap();
+        let read = |ts: &mut TableScan| {
+            (
+                ts.get_int("A").unwrap(),
+                ts.get_string("B").unwrap(),
+                ts.get_val("C").unwrap(),
+            )
+        };
+
+        let mut tx = db.new_tx().unwrap();
+        let mut ts = TableScan::new(tx.clone(), "slotted".to_string(), layout.clone()).unwrap();
+        let mut rids = Vec::new();
+        for i in 0..12 {
+            ts.insert().unwrap();
+            ts.set_int("A", i).unwrap();
+            ts.set_string("B", "ok".to_string()).unwrap();
+            rids.push(ts.get_row_id());
+        }
+        assert!(rids[..6].iter().all(|rid| rid.blk_num() == 0));
+        assert!(rids[6..].iter().all(|rid| rid.blk_num() == 1));
+
+        // Growing records move inside their block, which is compacted once it runs out of
+        // space, shrinking keeps them in place
+        let long = "x".repeat(30);
+        ts.move_to_row_id(rids[0].clone()).unwrap();
+        ts.set_string("B", long.clone()).unwrap();
+        ts.set_string("C", "y".repeat(10)).unwrap();
+        ts.move_to_row_id(rids[1].clone()).unwrap();
+        ts.set_string("C", "z".repeat(10)).unwrap();
+        ts.set_string("C", "z".to_string()).unwrap();
+        ts.close().unwrap();
+        tx.commit().unwrap();
+
+        let mut expected = (0..12)
+            .map(|i| (i, "ok".to_string(), Constant::Null))
+            .collect::<Vec<_>>();
+        expected[0] = (0, long, Constant::with_string("y".repeat(10)));
+        expected[1].2 = Constant::with_string("z".to_string());
+
+        let check = |db: &DBServer, rids: &[RowId], expected: &[(i32, String, Constant)]| {
+            let mut tx = db.new_tx().unwrap();
+            let mut ts = TableScan::new(tx.clone(), "slotted".to_string(), layout.clone()).unwrap();
+            for (rid, row) in rids.iter().zip(expected) {
+                ts.move_to_row_id(rid.clone()).unwrap();
+                assert_eq!(&read(&mut ts), row);
+            }
+            let mut count = 0;
+            ts.before_first().unwrap();
+            while ts.next().unwrap() {
+                count += 1;
+            }
+            assert_eq!(count, expected.len());
+            ts.close().unwrap();
+            tx.commit().unwrap();
+        };
+        check(&db, &rids, &expected);
+
+        // A record can't grow past the space of its block, and rolling back shrinks it again
+        let mut tx = db.new_tx().unwrap();
+        let mut ts = TableScan::new(tx.clone(), "slotted".to_string(), layout.clone()).unwrap();
+        ts.move_to_row_id(rids[2].clone()).unwrap();
+        assert!(matches!(
+            ts.set_string("B", "w".repeat(255)),
+            Err(DbError::RecordDoesNotFit)
+        ));
+        ts.set_string("B", "w".repeat(5)).unwrap();
+        ts.close().unwrap();
+        tx.rollback().unwrap();
+        check(&db, &rids, &expected);
+
+        // The catalog keeps the format of the table
+        let mut tx = db.new_tx().unwrap();
+        let tm = TableManager::new(true, tx.clone()).unwrap();
+        tm.create_table("slotted".to_string(), layout.clone(), tx.clone())
+            .unwrap();
+        let stored = tm.get_layout("slotted", tx.clone()).unwrap();
+        assert_eq!(stored.format(), RecordFormat::Slotted);
+        tx.commit().unwrap();
+
+        // Recovery replays the moves of committed records and undoes the others
+        let mut tx1 = db.new_tx().unwrap();
+        let mut ts = TableScan::new(tx1.clone(), "slotted".to_string(), layout.clone()).unwrap();
+        ts.move_to_row_id(rids[8].clone()).unwrap();
+        ts.set_string("B", "v".repeat(30)).unwrap();
+        ts.move_to_row_id(rids[7].clone()).unwrap();
+        ts.delete().unwrap();
+        drop(ts);
+        tx1.commit().unwrap();
+        expected[8].1 = "v".repeat(30);
+        expected.remove(7);
+
+        let tx2 = db.new_tx().unwrap();
+        let mut ts = TableScan::new(tx2.clone(), "slotted".to_string(), layout.clone()).unwrap();
+        ts.move_to_row_id(rids[9].clone()).unwrap();
+        ts.set_string("B", "u".repeat(20)).unwrap();
+        ts.insert().unwrap();
+        ts.set_string("C", "new".to_string()).unwrap();
+        drop(ts);
+
+        std::mem::forget(tx1);
+        std::mem::forget(tx2);
+        std::mem::forget(db);
+
+        let db = DBServer::new_with_params(options()).unwrap();
+        db.new_tx().unwrap().recover().unwrap();
+        rids.remove(7);
+        check(&db, &rids, &expected);
+        drop(db);
+
+        fs::remove_dir_all("slottedtest").unwrap();
+    }
+
+    #[test]
+    fn blobtest() {
+        let options = || {
+            DBServerOptions::default()
+                .block_size(400)
+                .directory("blobtest".to_string())
+                .pool_size(8)
+        };
+        let db = DBServer::new_with_params(options()).unwrap();
+
+        // A string longer than a block can't be part of a record
+        let mut sch = Schema::new();
+        sch.add_string_field("S".to_string(), 500);
+        let tx = db.new_tx().unwrap();
+        assert!(matches!(
+            TableScan::new(tx, "wide".to_string(), Layout::new(sch).unwrap()),
+            Err(DbError::RecordDoesNotFit)
+        ));
+
+        let mut sch = Schema::new();
+        sch.add_int_field("A".to_string());
+        sch.add_nullable_field("T".to_string(), FieldType::Text, 0);
+        sch.add_blob_field("B".to_string());
+        let layout = Layout::new(sch).unwrap();
+
+        let text = (0..3000_usize)
+            .map(|i| (b'a' + (i % 26) as u8) as char)
+            .collect::<String>();
+        let blob = (0..5000).map(|i| (i * 7 % 251) as u8).collect::<Vec<_>>();
+
+        let mut tx = db.new_tx().unwrap();
+        let mut ts = TableScan::new(tx.clone(), "blob".to_string(), layout.clone()).unwrap();
+        let mut rids = Vec::new();
+        for i in 0..3 {
+            ts.insert().unwrap();
+            ts.set_int("A", i).unwrap();
+            rids.push(ts.get_row_id());
+        }
+        ts.move_to_row_id(rids[0].clone()).unwrap();
+        ts.set_string("T", text.clone()).unwrap();
+        ts.set_bytes("B", blob.clone()).unwrap();
+        ts.move_to_row_id(rids[1].clone()).unwrap();
+        ts.set_val("T", Constant::with_string(String::new()))
+            .unwrap();
+        let written = ts.write_blob("B", io::repeat(7).take(2000)).unwrap();
+        assert_eq!(written, 2000);
+        ts.close().unwrap();
+        tx.commit().unwrap();
+
+        let mut tx = db.new_tx().unwrap();
+        let mut ts = TableScan::new(tx.clone(), "blob".to_string(), layout.clone()).unwrap();
+        ts.move_to_row_id(rids[0].clone()).unwrap();
+        assert_eq!(ts.get_string("T").unwrap(), text);
+        assert_eq!(ts.get_bytes("B").unwrap(), blob);
+        assert_eq!(ts.get_val("B").unwrap(), Constant::with_bytes(blob.clone()));
+
+        // Streaming reads hand out the value a piece at a time
+        let mut reader = ts.read_blob("B").unwrap().unwrap();
+        let mut piece = [0; 100];
+        reader.read_exact(&mut piece).unwrap();
+        assert_eq!(piece[..], blob[..100]);
+        assert_eq!(reader.remaining(), 4900);
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, blob[100..]);
+
+        ts.move_to_row_id(rids[1].clone()).unwrap();
+        assert_eq!(
+            ts.get_val("T").unwrap(),
+            Constant::with_string(String::new())
+        );
+        assert_eq!(ts.get_bytes("B").unwrap(), vec![7; 2000]);
+        ts.move_to_row_id(rids[2].clone()).unwrap();
+        assert_eq!(ts.get_val("T").unwrap(), Constant::Null);
+        assert!(ts.read_blob("T").unwrap().is_none());
+        assert!(ts.get_bytes("B").unwrap().is_empty());
+        assert!(matches!(
+            ts.get_bytes("A"),
+            Err(DbError::NotOverflowField(_))
+        ));
+        ts.close().unwrap();
+        tx.commit().unwrap();
+
+        // A snapshot keeps reading the value it saw while the record gets a new one, which is
+        // gone again once its transaction rolls back
+        let mut reader = db.new_tx().unwrap();
+        reader.begin_snapshot();
+        let mut rs = TableScan::new(reader.clone(), "blob".to_string(), layout.clone()).unwrap();
+        let mut writer = db.new_tx().unwrap();
+        let mut ws = TableScan::new(writer.clone(), "blob".to_string(), layout.clone()).unwrap();
+        ws.move_to_row_id(rids[0].clone()).unwrap();
+        ws.set_string("T", "short".to_string()).unwrap();
+        ws.set_bytes("B", Vec::new()).unwrap();
+        assert_eq!(ws.get_string("T").unwrap(), "short");
+        assert!(ws.get_bytes("B").unwrap().is_empty());
+        rs.move_to_row_id(rids[0].clone()).unwrap();
+        assert_eq!(rs.get_string("T").unwrap(), text);
+        assert_eq!(rs.get_bytes("B").unwrap(), blob);
+        ws.close().unwrap();
+        writer.rollback().unwrap();
+        rs.close().unwrap();
+        reader.commit().unwrap();
+
+        // Recovery brings back the chains of committed values and drops the others
+        let other = "z".repeat(1000);
+        let mut tx1 = db.new_tx().unwrap();
+        let mut ts = TableScan::new(tx1.clone(), "blob".to_string(), layout.clone()).unwrap();
+        ts.move_to_row_id(rids[2].clone()).unwrap();
+        ts.set_string("T", other.clone()).unwrap();
+        drop(ts);
+        tx1.commit().unwrap();
+
+        let tx2 = db.new_tx().unwrap();
+        let mut ts = TableScan::new(tx2.clone(), "blob".to_string(), layout.clone()).unwrap();
+        ts.move_to_row_id(rids[0].clone()).unwrap();
+        ts.set_bytes("B", vec![1; 3000]).unwrap();
+        drop(ts);
+
+        std::mem::forget(tx1);
+        std::mem::forget(tx2);
+        std::mem::forget(db);
+
+        let db = DBServer::new_with_params(options()).unwrap();
+        db.new_tx().unwrap().recover().unwrap();
+
+        let mut tx = db.new_tx().unwrap();
+        let mut ts = TableScan::new(tx.clone(), "blob".to_string(), layout).unwrap();
+        ts.move_to_row_id(rids[0].clone()).unwrap();
+        assert_eq!(ts.get_string("T").unwrap(), text);
+        assert_eq!(ts.get_bytes("B").unwrap(), blob);
+        ts.move_to_row_id(rids[2].clone()).unwrap();
+        assert_eq!(ts.get_string("T").unwrap(), other);
+        ts.close().unwrap();
+        tx.commit().unwrap();
+        drop(db);
+
+        fs::remove_dir_all("blobtest").unwrap();
+    }
+
+    #[test]
+    fn overflowfreetest() {
+        let db = DBServer::new_with_params(
+            DBServerOptions::default()
+                .block_size(400)
+                .directory("overflowfreetest".to_string())
+                .pool_size(8),
+        )
+        .unwrap();
+        let fm = db.file_manager();
+        let blocks = || fm.size("chains.ovf").unwrap();
+
+        let mut sch = Schema::new();
+        sch.add_int_field("A".to_string());
+        sch.add_nullable_field("B".to_string(), FieldType::Blob, 0);
+        let layout = Layout::new(sch).unwrap();
+        let scan = |tx: &crate::storage::tx::Transactions| {
+            TableScan::new(tx.clone(), "chains".to_string(), layout.clone()).unwrap()
+        };
+        // A value of 1000 bytes takes 3 blocks
+        let value = |byte: u8| vec![byte; 1000];
+
+        let mut tx = db.new_tx().unwrap();
+        let mut ts = scan(&tx);
+        ts.insert().unwrap();
+        let rid = ts.get_row_id();
+        ts.set_bytes("B", value(1)).unwrap();
+        ts.close().unwrap();
+        tx.commit().unwrap();
+        assert_eq!(blocks(), 4);
+
+        // The value the transaction set first and replaced itself is freed once it commits,
+        // the committed one is kept for the version the update replaced
+        let mut tx = db.new_tx().unwrap();
+        let mut ts = scan(&tx);
+        ts.move_to_row_id(rid.clone()).unwrap();
+        ts.set_bytes("B", value(2)).unwrap();
+        ts.set_bytes("B", value(3)).unwrap();
+        ts.close().unwrap();
+        tx.commit().unwrap();
+        assert_eq!(blocks(), 10);
+
+        let mut tx = db.new_tx().unwrap();
+        let mut ts = scan(&tx);
+        ts.insert().unwrap();
+        let other = ts.get_row_id();
+        ts.set_bytes("B", value(4)).unwrap();
+        ts.close().unwrap();
+        tx.commit().unwrap();
+        assert_eq!(blocks(), 10);
+
+        // A snapshot keeps the old version and its value alive through a vacuum
+        let mut reader = db.new_tx().unwrap();
+        reader.begin_snapshot();
+        let mut rs = scan(&reader);
+        rs.move_to_row_id(rid.clone()).unwrap();
+        assert_eq!(rs.get_bytes("B").unwrap(), value(3));
+
+        let mut tx = db.new_tx().unwrap();
+        let mut ts = scan(&tx);
+        ts.move_to_row_id(rid.clone()).unwrap();
+        ts.set_bytes("B", value(5)).unwrap();
+        ts.close().unwrap();
+        tx.commit().unwrap();
+
+        let mut tx = db.new_tx().unwrap();
+        let mut ts = scan(&tx);
+        assert_eq!(ts.vacuum().unwrap(), 1);
+        ts.close().unwrap();
+        tx.commit().unwrap();
+        assert_eq!(blocks(), 13);
+
+        let mut tx = db.new_tx().unwrap();
+        let mut ts = scan(&tx);
+        ts.move_to_row_id(other.clone()).unwrap();
+        ts.set_bytes("B", value(6)).unwrap();
+        ts.close().unwrap();
+        tx.commit().unwrap();
+        assert_eq!(blocks(), 13);
+        assert_eq!(rs.get_bytes("B").unwrap(), value(3));
+        rs.close().unwrap();
+        reader.commit().unwrap();
+
+        // The values of a transaction which rolls back are freed
+        let mut tx = db.new_tx().unwrap();
+        let mut ts = scan(&tx);
+        ts.move_to_row_id(rid.clone()).unwrap();
+        ts.set_bytes("B", value(7)).unwrap();
+        ts.close().unwrap();
+        tx.rollback().unwrap();
+        assert_eq!(blocks(), 16);
+
+        // Deleted records give back their values too
+        let mut tx = db.new_tx().unwrap();
+        let mut ts = scan(&tx);
+        ts.move_to_row_id(other.clone()).unwrap();
+        ts.delete().unwrap();
+        ts.move_to_row_id(rid.clone()).unwrap();
+        ts.set_null("B").unwrap();
+        assert!(matches!(ts.get_bytes("B"), Err(DbError::NullValue(_))));
+        ts.close().unwrap();
+        tx.commit().unwrap();
+
+        let mut tx = db.new_tx().unwrap();
+        let mut ts = scan(&tx);
+        assert_eq!(ts.vacuum().unwrap(), 4);
+        ts.close().unwrap();
+        tx.commit().unwrap();
+
+        let mut tx = db.new_tx().unwrap();
+        let mut ts = scan(&tx);
+        for byte in 8..13 {
+            ts.insert().unwrap();
+            ts.set_bytes("B", value(byte)).unwrap();
+        }
+        ts.before_first().unwrap();
+        let mut values = Vec::new();
+        while ts.next().unwrap() {
+            if !ts.is_null("B").unwrap() {
+                values.push(ts.get_bytes("B").unwrap()[0]);
+            }
+        }
+        values.sort();
+        assert_eq!(values, vec![8, 9, 10, 11, 12]);
+        ts.close().unwrap();
+        tx.commit().unwrap();
+        assert_eq!(blocks(), 16);
+
+        fs::remove_dir_all("overflowfreetest").unwrap();
+    }
 }
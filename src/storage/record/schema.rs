@@ -1,29 +1,52 @@
 use crate::error::{DbError, DbResult};
 use std::collections::HashMap;
 
+/// Type of a field, the value is how the type is stored in the catalog
 #[repr(u32)]
-#[derive(Clone, Copy, Eq, PartialEq)]
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
 pub(crate) enum FieldType {
     Integer,
     Varchar,
+    /// 64 bit integer
+    BigInt,
+    /// 64 bit floating point number
+    Double,
+    Boolean,
+    /// Days since 1970-01-01
+    Date,
+    /// Microseconds since 1970-01-01 00:00:00 UTC
+    Timestamp,
+    /// Fixed point number kept as a 64 bit integer, the length of the field is the number of
+    /// digits after the decimal point
+    Decimal,
+    /// String of any length, stored out of line in overflow blocks
+    Text,
+    /// Bytes of any length, stored out of line in overflow blocks
+    Blob,
 }
 
-impl From<u32> for FieldType {
-    fn from(value: u32) -> Self {
-        assert!(
-            value == FieldType::Integer as u32 || value == FieldType::Varchar as u32,
-            "Invalid field type value: {value}",
-        );
+impl TryFrom<u32> for FieldType {
+    type Error = DbError;
 
-        match value {
+    fn try_from(value: u32) -> DbResult<Self> {
+        Ok(match value {
             0 => FieldType::Integer,
             1 => FieldType::Varchar,
-            _ => unreachable!("Invalid field type"),
-        }
+            2 => FieldType::BigInt,
+            3 => FieldType::Double,
+            4 => FieldType::Boolean,
+            5 => FieldType::Date,
+            6 => FieldType::Timestamp,
+            7 => FieldType::Decimal,
+            8 => FieldType::Text,
+            9 => FieldType::Blob,
+            _ => return Err(DbError::UnknownFieldType(value)),
+        })
     }
 }
 
-/// Maintains the information about fields, particularly type and length of a field
+/// Maintains the information about fields, particularly type and length of a field and whether
+/// it can be NULL
 #[derive(Clone)]
 pub(crate) struct Schema {
     fields: Vec<String>,
@@ -40,7 +63,15 @@ impl Schema {
 
     pub fn add_field(&mut self, field_name: String, typ: FieldType, length: usize) {
         self.fields.push(field_name.clone());
-        self.info.insert(field_name, FieldInfo::new(typ, length));
+        self.info
+            .insert(field_name, FieldInfo::new(typ, length, false));
+    }
+
+    /// Adds a field which can be NULL, records start with every such field NULL
+    pub fn add_nullable_field(&mut self, field_name: String, typ: FieldType, length: usize) {
+        self.fields.push(field_name.clone());
+        self.info
+            .insert(field_name, FieldInfo::new(typ, length, true));
     }
 
     pub fn add_int_field(&mut self, field_name: String) {
@@ -51,11 +82,28 @@ impl Schema {
         self.add_field(field_name, FieldType::Varchar, length);
     }
 
+    /// Adds a decimal field with `scale` digits after the decimal point
+    pub fn add_decimal_field(&mut self, field_name: String, scale: usize) {
+        self.add_field(field_name, FieldType::Decimal, scale);
+    }
+
+    pub fn add_text_field(&mut self, field_name: String) {
+        self.add_field(field_name, FieldType::Text, 0);
+    }
+
+    pub fn add_blob_field(&mut self, field_name: String) {
+        self.add_field(field_name, FieldType::Blob, 0);
+    }
+
     pub fn add(&mut self, field_name: String, schema: Schema) -> DbResult<()> {
         let len = schema.length(&field_name)?;
         let typ = schema.typ(&field_name)?;
 
-        self.add_field(field_name, typ, len);
+        if schema.is_nullable(&field_name)? {
+            self.add_nullable_field(field_name, typ, len);
+        } else {
+            self.add_field(field_name, typ, len);
+        }
         Ok(())
     }
 
@@ -73,6 +121,13 @@ impl Schema {
             .map(|field| field.length)
     }
 
+    pub fn is_nullable(&self, field_name: &str) -> DbResult<bool> {
+        self.info
+            .get(field_name)
+            .ok_or(DbError::SchemaFieldNotFound)
+            .map(|field| field.nullable)
+    }
+
     pub fn add_all(&mut self, schema: Schema) -> DbResult<()> {
         for field_name in schema.fields() {
             self.add(field_name.clone(), schema.clone())?;
@@ -87,16 +142,35 @@ impl Schema {
     pub fn fields(&self) -> &[String] {
         &self.fields
     }
+
+    /// The TEXT and BLOB fields, which are kept in overflow blocks
+    pub fn overflow_fields(&self) -> Vec<String> {
+        self.fields
+            .iter()
+            .filter(|field| {
+                matches!(
+                    self.info[field.as_str()].typ,
+                    FieldType::Text | FieldType::Blob
+                )
+            })
+            .cloned()
+            .collect()
+    }
 }
 
 #[derive(Clone)]
 pub(crate) struct FieldInfo {
     typ: FieldType,
     length: usize,
+    nullable: bool,
 }
 
 impl FieldInfo {
-    fn new(typ: FieldType, length: usize) -> Self {
-        Self { typ, length }
+    fn new(typ: FieldType, length: usize, nullable: bool) -> Self {
+        Self {
+            typ,
+            length,
+            nullable,
+        }
     }
 }
@@ -0,0 +1,148 @@
+use crate::{
+    consts::{INTEGER_BYTES, PAGE_LSN_BYTES},
+    storage::disk::page::Page,
+};
+
+/// Number of slots in the directory
+pub(crate) const COUNT_OFFSET: usize = 0;
+/// Bytes the tuples take at the end of the block, including the gaps tuples which moved left
+const HEAP_BYTES_OFFSET: usize = INTEGER_BYTES;
+const DIRECTORY_OFFSET: usize = 2 * INTEGER_BYTES;
+/// An entry of the directory holds the offset of the tuple of the slot and its capacity
+const ENTRY_BYTES: usize = 2 * INTEGER_BYTES;
+/// Percent of the block new records can take, the rest is left for the records in the block to
+/// grow since they never move to another block
+const FILL_FACTOR: usize = 90;
+
+/// Position of the directory entry of the slot in the block
+pub(crate) fn entry_pos(slot: usize) -> usize {
+    DIRECTORY_OFFSET + slot * ENTRY_BYTES
+}
+
+/// Block of a table stored in the slotted format
+///
+/// The block starts with a directory of slots and the tuples are stored from the end of the
+/// block towards it, each as long as its values. A `RowId` names a slot of the directory, so
+/// it stays the same while the tuple moves around in the block. A block of zeroes is an empty
+/// slotted page.
+///
+/// The capacity of a slot never shrinks, a tuple which grows past it moves to a bigger space
+/// and the old one becomes a gap which compaction gives back once the block runs out of
+/// contiguous space. Undoing a change therefore always fits in place, and every change is a
+/// write of some bytes of a tuple that redo repeats on the block exactly as it happened. Slots
+/// freed keep their capacity for the next record claiming them
+pub(crate) struct SlottedPage<'a> {
+    page: &'a mut Page,
+    /// End of the space for the tuples, the page LSN follows it
+    usable: usize,
+}
+
+impl<'a> SlottedPage<'a> {
+    pub(crate) fn new(page: &'a mut Page) -> Self {
+        let usable = page.contents().len() - PAGE_LSN_BYTES;
+        Self { page, usable }
+    }
+
+    pub(crate) fn slot_count(&mut self) -> usize {
+        self.page.get_int(COUNT_OFFSET) as usize
+    }
+
+    /// Offset of the tuple of the slot in the block and its capacity
+    pub(crate) fn entry(&mut self, slot: usize) -> (usize, usize) {
+        let pos = entry_pos(slot);
+        (
+            self.page.get_int(pos) as usize,
+            self.page.get_int(pos + INTEGER_BYTES) as usize,
+        )
+    }
+
+    /// Whether `len` bytes of tuple fit into the slot, which might be added to the directory.
+    /// A new record has to leave the space the fill factor keeps for the records to grow,
+    /// unless the slot is big enough for it already
+    pub(crate) fn fits(&mut self, slot: usize, len: usize, new_record: bool) -> bool {
+        let count = self.slot_count();
+        let capacity = if slot < count { self.entry(slot).1 } else { 0 };
+        if capacity >= len {
+            return true;
+        }
+
+        let reserved = if new_record {
+            self.usable * (100 - FILL_FACTOR) / 100
+        } else {
+            0
+        };
+        let used = entry_pos(count.max(slot + 1)) + self.capacities(count) - capacity;
+        used + len + reserved <= self.usable
+    }
+
+    /// Writes `bytes` at `offset` of the tuple of the slot, adding the slot to the directory and
+    /// growing its capacity as needed. The caller has to make sure it `fits`
+    pub(crate) fn write(&mut self, slot: usize, offset: usize, bytes: &[u8]) {
+        let mut count = self.slot_count();
+        while count <= slot {
+            self.set_entry(count, 0, 0);
+            count += 1;
+        }
+        self.page.set_int(COUNT_OFFSET, count as i32);
+
+        let (mut pos, capacity) = self.entry(slot);
+        let len = offset + bytes.len();
+        if len > capacity {
+            // The old space of the tuple is free for compaction too
+            let tuple = self.page.contents()[pos..pos + capacity].to_vec();
+            self.set_entry(slot, 0, 0);
+            if self.heap_start() < entry_pos(count) + len {
+                self.compact();
+            }
+            assert!(
+                self.heap_start() >= entry_pos(count) + len,
+                "Tuple of {len} bytes does not fit the slotted page"
+            );
+
+            let new_pos = self.heap_start() - len;
+            self.page.contents()[new_pos..new_pos + capacity].copy_from_slice(&tuple);
+            let heap_bytes = self.usable - new_pos;
+            self.page.set_int(HEAP_BYTES_OFFSET, heap_bytes as i32);
+            self.set_entry(slot, new_pos, len);
+            pos = new_pos;
+        }
+
+        self.page.contents()[pos + offset..pos + len].copy_from_slice(bytes);
+    }
+
+    /// Moves the tuples to the end of the block, next to each other, so the gaps between them
+    /// become free space
+    fn compact(&mut self) {
+        let count = self.slot_count();
+        let tuples = (0..count)
+            .map(|slot| {
+                let (pos, capacity) = self.entry(slot);
+                (slot, self.page.contents()[pos..pos + capacity].to_vec())
+            })
+            .collect::<Vec<_>>();
+
+        let mut end = self.usable;
+        for (slot, tuple) in tuples {
+            end -= tuple.len();
+            self.page.contents()[end..end + tuple.len()].copy_from_slice(&tuple);
+            self.set_entry(slot, end, tuple.len());
+        }
+        self.page
+            .set_int(HEAP_BYTES_OFFSET, (self.usable - end) as i32);
+    }
+
+    /// Sum of the capacities of the first `count` slots
+    fn capacities(&mut self, count: usize) -> usize {
+        (0..count).map(|slot| self.entry(slot).1).sum()
+    }
+
+    fn heap_start(&mut self) -> usize {
+        self.usable - self.page.get_int(HEAP_BYTES_OFFSET) as usize
+    }
+
+    fn set_entry(&mut self, slot: usize, pos: usize, capacity: usize) {
+        let entry = entry_pos(slot);
+        self.page.set_int(entry, pos as i32);
+        self.page.set_int(entry + INTEGER_BYTES, capacity as i32);
+    }
+}
@@ -25,7 +25,7 @@ mod tests {
         sch.add_string_field("B".to_string(), 9);
         let layout = Layout::new(sch).expect("Unable to create layout");
 
-        let e = [("A", 4), ("B", 8)];
+        let e = [("A", 28), ("B", 32)];
         for (i, fldname) in layout.schema().fields().iter().enumerate() {
             assert_eq!(fldname, e[i].0);
 
@@ -36,7 +36,7 @@ mod tests {
 
         tx.pin(&blk).unwrap();
 
-        let mut rp = RecordPage::new(tx.clone(), blk.clone(), layout);
+        let mut rp = RecordPage::new(tx.clone(), blk.clone(), layout).unwrap();
         rp.format().unwrap();
 
         let mut slot = rp.insert_after(Slot::uninit()).unwrap();
@@ -47,7 +47,7 @@ mod tests {
             rp.set_int(slot, &String::from("A"), n).unwrap();
             rp.set_string(slot, &String::from("B"), format!("rec{n}"))
                 .unwrap();
-            assert!(slot <= 18.into());
+            assert!(slot <= 7.into());
             assert!((0..50).contains(&n));
             slot = rp.insert_after(slot).unwrap();
         }
@@ -59,21 +59,21 @@ mod tests {
             let b = rp.get_string(slot, &String::from("B")).unwrap();
             if a < 25 {
                 count += 1;
-                assert!(slot <= 18.into());
+                assert!(slot <= 7.into());
                 assert!(a < 25);
                 assert_eq!(format!("rec{a}"), b);
                 rp.delete(slot).unwrap();
             }
             slot = rp.next_after(slot).unwrap();
         }
-        assert!((0..=18).contains(&count));
+        assert!((0..=8).contains(&count));
 
         slot = rp.next_after(0.into()).unwrap();
 
         while slot.is_init() {
             let a = rp.get_int(slot, &String::from("A")).unwrap();
             let b = rp.get_string(slot, &String::from("B")).unwrap();
-            assert!(slot <= 18.into());
+            assert!(slot <= 7.into());
             assert!(a >= 25);
             assert_eq!(format!("rec{a}"), b);
             slot = rp.next_after(slot).unwrap();
@@ -0,0 +1,113 @@
+use crate::{
+    error::{DbError, DbResult},
+    utils::safe_lock::SafeLock,
+};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+
+/// Calls the owner of a transaction has in progress, so `TxManager::abort` rolls the
+/// transaction back in between them. A call which already passed its checks would otherwise
+/// log and change data after the rollback went through the log, without any locks
+#[derive(Default)]
+pub(super) struct Activity {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    calls: usize,
+    /// Set by `TxManager::abort`, no call starts from then on
+    aborted: bool,
+    /// Set once the aborted transaction was rolled back
+    rolled_back: bool,
+    /// Set once the transaction started to prepare, only the coordinator decides its outcome
+    /// from then on
+    prepared: bool,
+}
+
+/// A call in progress, it ends once dropped
+pub(super) struct Call {
+    activity: Option<Arc<Activity>>,
+}
+
+impl Activity {
+    /// Starts a call, `None` once the transaction was aborted
+    pub(super) fn enter(self: &Arc<Self>) -> Option<Call> {
+        let mut state = self.state.safe_lock();
+        if state.aborted {
+            return None;
+        }
+        state.calls += 1;
+        Some(Call {
+            activity: Some(self.clone()),
+        })
+    }
+
+    /// Marks the transaction aborted, false if it already was. A prepared transaction can't be
+    /// aborted
+    pub(super) fn abort(&self, txnum: u64) -> DbResult<bool> {
+        let mut state = self.state.safe_lock();
+        if state.prepared {
+            return Err(DbError::TransactionPrepared(txnum));
+        }
+        Ok(!std::mem::replace(&mut state.aborted, true))
+    }
+
+    /// Marks the transaction prepared, false once it was aborted
+    pub(super) fn prepare(&self) -> bool {
+        let mut state = self.state.safe_lock();
+        state.prepared = !state.aborted;
+        state.prepared
+    }
+
+    /// The transaction failed to prepare, it can be aborted again
+    pub(super) fn unprepare(&self) {
+        self.state.safe_lock().prepared = false;
+    }
+
+    pub(super) fn is_aborted(&self) -> bool {
+        self.state.safe_lock().aborted
+    }
+
+    /// Waits till the calls in progress ended
+    pub(super) fn wait_idle(&self) {
+        let state = self.state.safe_lock();
+        drop(
+            self.changed
+                .wait_while(state, |state| state.calls > 0)
+                .unwrap_or_else(PoisonError::into_inner),
+        );
+    }
+
+    /// Wakes up the owner waiting for the rollback of the aborted transaction
+    pub(super) fn rolled_back(&self) {
+        self.state.safe_lock().rolled_back = true;
+        self.changed.notify_all();
+    }
+
+    /// Waits till the aborted transaction was rolled back
+    pub(super) fn wait_rolled_back(&self) {
+        let state = self.state.safe_lock();
+        drop(
+            self.changed
+                .wait_while(state, |state| !state.rolled_back)
+                .unwrap_or_else(PoisonError::into_inner),
+        );
+    }
+}
+
+impl Call {
+    /// A call which isn't counted, made on behalf of the rollback itself
+    pub(super) fn untracked() -> Self {
+        Self { activity: None }
+    }
+}
+
+impl Drop for Call {
+    fn drop(&mut self) {
+        if let Some(activity) = &self.activity {
+            activity.state.safe_lock().calls -= 1;
+            activity.changed.notify_all();
+        }
+    }
+}
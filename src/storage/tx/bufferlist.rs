@@ -27,11 +27,16 @@ impl BufferList {
     }
 
     pub fn get_buffer(&self, blk: &Block) -> DbResult<usize> {
-        println!("Blk num {}", blk.num());
         self.buffers.get(blk).cloned().ok_or(DbError::InvalidValue)
     }
 
+    /// Blocks the transaction has pinned
+    pub fn pinned(&self) -> Vec<Block> {
+        self.buffers.keys().cloned().collect()
+    }
+
     /// Pin the buffer to a block and track it in the pinned buffers
+    #[tracing::instrument(level = "trace", name = "pin", skip_all, fields(block = %blk))]
     pub fn pin(&mut self, blk: &Block) -> DbResult<()> {
         let mut bm = self.bgr.safe_lock();
         let buffer = bm.pin(blk.clone())?;
@@ -41,14 +46,19 @@ impl BufferList {
         Ok(())
     }
 
-    /// Unpin the block from the buffer and remove it from the pinned buffer
+    /// Unpin the block from the buffer and remove it from the pinned buffer, a block pinned
+    /// more than once stays tracked till every pin is released
     pub fn unpin(&mut self, blk: &Block) -> DbResult<()> {
         let mut bm = self.bgr.safe_lock();
         let buffer = self.get_buffer(blk)?;
 
         bm.unpin(buffer);
-        self.pins.retain(|b| !b.eq(blk));
-        self.buffers.remove(blk);
+        if let Some(pos) = self.pins.iter().position(|b| b.eq(blk)) {
+            self.pins.remove(pos);
+        }
+        if !self.pins.contains(blk) {
+            self.buffers.remove(blk);
+        }
 
         Ok(())
     }
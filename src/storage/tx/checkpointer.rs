@@ -0,0 +1,68 @@
+use super::TxManager;
+use crate::{
+    storage::bufferpool::pool::BufferPoolManager, storage::log::manager::LogManager,
+    utils::safe_lock::SafeLock,
+};
+use std::{
+    sync::{Arc, Condvar, Mutex},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+/// Takes a checkpoint every `interval` on a thread of its own till it's dropped, so recovery
+/// after a crash only reads the end of the log
+pub struct Checkpointer {
+    stopped: Arc<(Mutex<bool>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Checkpointer {
+    pub fn start(
+        interval: Duration,
+        lm: Arc<Mutex<LogManager>>,
+        bm: Arc<Mutex<BufferPoolManager>>,
+        manager: Arc<TxManager>,
+    ) -> std::io::Result<Self> {
+        let stopped = Arc::new((Mutex::new(false), Condvar::new()));
+
+        let thread = {
+            let stopped = stopped.clone();
+            std::thread::Builder::new()
+                .name("checkpoint".to_string())
+                .spawn(move || {
+                    let (lock, cond) = &*stopped;
+                    let mut guard = lock.safe_lock();
+                    loop {
+                        guard = cond
+                            .wait_timeout_while(guard, interval, |stopped| !*stopped)
+                            .unwrap_or_else(|e| e.into_inner())
+                            .0;
+                        if *guard {
+                            return;
+                        }
+
+                        if let Err(e) = manager.checkpoint(lm.clone(), &bm) {
+                            tracing::error!(error = %e, "checkpoint failed");
+                        }
+                    }
+                })?
+        };
+
+        Ok(Self {
+            stopped,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for Checkpointer {
+    fn drop(&mut self) {
+        let (lock, cond) = &*self.stopped;
+        *lock.safe_lock() = true;
+        cond.notify_all();
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -1,84 +1,404 @@
 mod test;
 
-use super::locks::lock_table::LockTable;
-use crate::{error::DbResult, storage::disk::block::Block, utils::safe_lock::SafeLock};
-use once_cell::sync::Lazy;
-use std::{collections::HashMap, sync::Mutex};
-
-/// A shared global lock table to be used by multiple concurrent Transactions
-pub static LOCK_TABLE: Lazy<Mutex<LockTable>> = Lazy::new(|| Mutex::new(LockTable::new()));
-
-#[derive(PartialEq, Clone)]
-enum LockTypes {
-    Shared,
-    Exclusive,
+use super::{
+    locks::{
+        latch::Latch,
+        lock_mode::LockMode,
+        lock_table::{DeadlockPolicy, DEFAULT_LOCK_TIMEOUT},
+        lock_target::LockTarget,
+    },
+    manager::TxManager,
+};
+use crate::{
+    error::{DbError, DbResult},
+    storage::disk::block::Block,
+    utils::safe_lock::SafeLock,
+};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+/// Number of block and record locks a transaction can hold on a single file before they are escalated
+/// to a lock on the whole file, unless configured otherwise
+pub const DEFAULT_ESCALATION_THRESHOLD: usize = 1000;
+
+/// How much a transaction is isolated from the changes of concurrent transactions, the lower
+/// levels give up some isolation for holding fewer locks
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum IsolationLevel {
+    /// Reads take no locks and see changes which are not committed yet
+    ReadUncommitted,
+    /// Reads lock a record only while reading it, a record read twice can change in between
+    ReadCommitted,
+    /// Records read stay locked till the transaction finishes, but records inserted by
+    /// others can still show up in a repeated scan
+    RepeatableRead,
+    /// Scans lock the whole blocks and the end of the file, so nothing can be inserted
+    /// into the part of the table the transaction has seen
+    #[default]
+    Serializable,
 }
 
-impl std::fmt::Display for LockTypes {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::Shared => write!(f, "S"),
-            Self::Exclusive => write!(f, "X"),
-        }
-    }
+/// Block and record locks a transaction holds on a single file, kept so the escalation
+/// doesn't have to look through all the locks of the transaction
+#[derive(Clone, Copy, Default, Debug)]
+struct FileLocks {
+    count: usize,
+    /// Whether any of them was taken in a write mode
+    written: bool,
+    /// Count of locks at the last failed escalation, it's tried again only once another
+    /// threshold worth of locks were taken
+    failed_at: usize,
 }
 
 /// Every transaction will have a separate concurrency manager
 ///
-/// It tracks the kind of locks the transaction holds for a block
+/// It tracks the kind of locks the transaction holds on every resource. The tracked locks are
+/// shared between the clones of a transaction, so locks taken through any clone are released
+/// on commit
+///
+/// Locks are taken following the hierarchy database -> file -> block -> record, before
+/// locking a resource the transaction holds an intention lock on each of its ancestors.
 #[derive(Clone)]
 pub struct ConcurrencyManager {
-    locks: HashMap<Block, LockTypes>,
+    txnum: u64,
+    policy: DeadlockPolicy,
+    /// Holds the lock table shared with the other transactions of the server
+    manager: Arc<TxManager>,
+    /// Milliseconds the transaction waits for a single lock before giving up
+    lock_timeout: Arc<AtomicU64>,
+    /// Number of block locks on a single file after which the file gets locked instead
+    escalation_threshold: Arc<AtomicUsize>,
+    isolation: Arc<Mutex<IsolationLevel>>,
+    locks: Arc<Mutex<HashMap<LockTarget, LockMode>>>,
+    /// Block and record locks held on each file, guarded by the lock on `locks`
+    files: Arc<Mutex<HashMap<String, FileLocks>>>,
+    /// Set once the transaction was aborted by someone else
+    aborted: Arc<AtomicBool>,
 }
 
 impl ConcurrencyManager {
-    pub fn new() -> Self {
+    pub fn new(txnum: u64, policy: DeadlockPolicy, manager: Arc<TxManager>) -> Self {
         Self {
-            locks: HashMap::new(),
+            txnum,
+            policy,
+            manager,
+            lock_timeout: Arc::new(AtomicU64::new(DEFAULT_LOCK_TIMEOUT.as_millis() as u64)),
+            escalation_threshold: Arc::new(AtomicUsize::new(DEFAULT_ESCALATION_THRESHOLD)),
+            isolation: Arc::new(Mutex::new(IsolationLevel::default())),
+            locks: Arc::new(Mutex::new(HashMap::new())),
+            files: Arc::new(Mutex::new(HashMap::new())),
+            aborted: Arc::new(AtomicBool::new(false)),
         }
     }
+
+    pub fn policy(&self) -> DeadlockPolicy {
+        self.policy
+    }
+
+    pub fn lock_timeout(&self) -> Duration {
+        Duration::from_millis(self.lock_timeout.load(Ordering::SeqCst))
+    }
+
+    /// Sets how long the transaction waits for a lock before it's aborted
+    pub fn set_lock_timeout(&self, timeout: Duration) {
+        self.lock_timeout
+            .store(timeout.as_millis() as u64, Ordering::SeqCst);
+    }
+
+    pub fn escalation_threshold(&self) -> usize {
+        self.escalation_threshold.load(Ordering::SeqCst)
+    }
+
+    /// Sets the number of block locks on a single file after which the transaction tries to
+    /// lock the whole file instead
+    pub fn set_escalation_threshold(&self, threshold: usize) {
+        self.escalation_threshold.store(threshold, Ordering::SeqCst);
+    }
+
+    /// Sets the age the deadlock policy compares instead of the transaction number
+    pub fn set_age(&self, age: u64) {
+        self.manager.lock_table.set_age(self.txnum, age);
+    }
+
+    pub fn isolation_level(&self) -> IsolationLevel {
+        *self.isolation.safe_lock()
+    }
+
+    pub fn set_isolation_level(&self, level: IsolationLevel) {
+        *self.isolation.safe_lock() = level;
+    }
+
+    /// Takes the lock needed to read the resource under the isolation level of the
+    /// transaction. Returns true if the lock has to be released with `unlock` once the read
+    /// is over, which is the case for read committed transactions which did not hold a lock
+    /// on the resource before
+    pub fn read_lock(&mut self, target: &LockTarget) -> DbResult<bool> {
+        match self.isolation_level() {
+            IsolationLevel::ReadUncommitted => Ok(false),
+            IsolationLevel::ReadCommitted => {
+                let held = self.mode(target).is_some()
+                    || Self::is_covered(&self.locks.safe_lock(), target, LockMode::Shared);
+
+                self.lock(target, LockMode::Shared)?;
+                Ok(!held)
+            }
+            IsolationLevel::RepeatableRead | IsolationLevel::Serializable => {
+                self.lock(target, LockMode::Shared).map(|_| false)
+            }
+        }
+    }
+
+    /// Releases the lock on the resource before the transaction finishes, the intention locks
+    /// on its ancestors are kept
+    pub fn unlock(&mut self, target: &LockTarget) {
+        let mut locks = self.locks.safe_lock();
+        if locks.remove(target).is_some() {
+            self.manager.lock_table.unlock(target, self.txnum);
+            self.untrack(target);
+        }
+    }
+
     /// Tries to acquire Slock if there was no lock held by the
     /// transaction previously on the block
     pub fn slock(&mut self, block: &Block) -> DbResult<()> {
-        if !self.locks.contains_key(block) {
-            LOCK_TABLE.safe_lock().slock(block)?;
-            self.locks.insert(block.clone(), LockTypes::Shared);
+        self.lock(&LockTarget::Block(block.clone()), LockMode::Shared)
+    }
+
+    /// If the transaction already has an exclusive lock on the block or on any of its
+    /// ancestors then the lock will not be acquired. Otherwise we acquire the exclusive lock,
+    /// upgrading the shared lock if the transaction held one
+    pub fn xlock(&mut self, block: &Block) -> DbResult<()> {
+        self.lock(&LockTarget::Block(block.clone()), LockMode::Exclusive)
+    }
+
+    /// Locks the resource in the given mode after taking the intention locks on its ancestors.
+    /// Nothing is acquired if the locks held by the transaction already cover the request.
+    pub fn lock(&mut self, target: &LockTarget, mode: LockMode) -> DbResult<()> {
+        let mut locks = self.locks.safe_lock();
+
+        if Self::is_covered(&locks, target, mode) {
+            return Ok(());
+        }
+
+        for ancestor in target.ancestors() {
+            self.acquire(&mut locks, &ancestor, mode.intention())?;
+        }
+        let added = !locks.contains_key(target);
+        self.acquire(&mut locks, target, mode)?;
+
+        if self.track(target, mode, added) {
+            if let Some(file) = target.filename() {
+                self.escalate(&mut locks, file);
+            }
         }
 
         Ok(())
     }
 
-    /// If the transaction already has an exclusive lock or a shared lock then
-    /// the lock will not be acquired. Otherwise we acquire the shared lock and
-    /// then upgrade the lock to Exclusive
-    ///
-    pub fn xlock(&mut self, block: &Block) -> DbResult<()> {
-        if !self.has_x_lock(block) {
-            self.slock(block)?;
+    /// Like `lock` but gives up right away if another transaction holds the resource in a
+    /// conflicting mode, returning false. The intention locks on the ancestors are waited for
+    /// as usual
+    pub fn try_lock(&mut self, target: &LockTarget, mode: LockMode) -> DbResult<bool> {
+        let mut locks = self.locks.safe_lock();
 
-            // TODO: Investigate this, the transaction tests gets deadlocked,
-            // because the previous line calls the slock.
-            //
-            //LOCK_TABLE.safe_lock().xlock(block)?;
-            self.locks.insert(block.clone(), LockTypes::Exclusive);
+        if Self::is_covered(&locks, target, mode) {
+            return Ok(true);
         }
-        Ok(())
+
+        for ancestor in target.ancestors() {
+            self.acquire(&mut locks, &ancestor, mode.intention())?;
+        }
+
+        let held = locks.get(target).copied();
+        match self.manager.lock_table.lock(
+            target,
+            mode,
+            self.txnum,
+            DeadlockPolicy::Timeout,
+            Duration::ZERO,
+        ) {
+            Ok(()) => {
+                locks.insert(target.clone(), held.map_or(mode, |held| held.join(mode)));
+                self.track(target, mode, held.is_none());
+                Ok(true)
+            }
+            Err(DbError::LockAborted) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Latches the block till the returned guard is dropped
+    pub fn latch(&self, block: &Block) -> Latch {
+        self.manager.latches.latch(block)
+    }
+
+    /// Aborts the transaction on behalf of someone else, it can't take any more locks and is
+    /// woken up if it's waiting for one. The locks it holds are kept till it's rolled back
+    pub fn abort(&self) {
+        self.aborted.store(true, Ordering::SeqCst);
+        self.manager.lock_table.abort(self.txnum);
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        self.aborted.load(Ordering::SeqCst)
+    }
+
+    pub fn is_waiting(&self) -> bool {
+        self.manager.lock_table.is_waiting(self.txnum)
     }
 
-    /// Release all the locks acquired by the transaction on a block
+    /// Every lock the transaction holds along with its mode. The lock table is asked since the
+    /// locks tracked here stay locked while the transaction waits for another one
+    pub fn held(&self) -> Vec<(LockTarget, LockMode)> {
+        self.manager.lock_table.held_by(self.txnum)
+    }
+
+    /// Mode the transaction holds the lock on the resource in, this does not include the
+    /// locks implied by the locks on its ancestors
+    pub fn mode(&self, target: &LockTarget) -> Option<LockMode> {
+        self.locks.safe_lock().get(target).copied()
+    }
+
+    /// Release all the locks acquired by the transaction
     pub fn release(&mut self) -> DbResult<()> {
-        for blk in self.locks.keys() {
-            LOCK_TABLE.safe_lock().unlock(blk);
+        self.manager.lock_table.release_all(self.txnum);
+
+        self.locks.safe_lock().clear();
+        self.files.safe_lock().clear();
+        Ok(())
+    }
+
+    /// Releases the locks still held by a transaction which never finished. This is only meant
+    /// to be used by recovery, where such a transaction is known to be dead
+    pub fn release_orphaned(&self, txnum: u64) {
+        self.manager.lock_table.release_all(txnum);
+    }
+
+    fn acquire(
+        &self,
+        locks: &mut HashMap<LockTarget, LockMode>,
+        target: &LockTarget,
+        mode: LockMode,
+    ) -> DbResult<()> {
+        let held = locks.get(target).copied();
+
+        if held.is_some_and(|held| held.covers(mode)) {
+            return Ok(());
         }
 
-        self.locks.clear();
+        self.manager
+            .lock_table
+            .lock(target, mode, self.txnum, self.policy, self.lock_timeout())?;
+        locks.insert(target.clone(), held.map_or(mode, |held| held.join(mode)));
+
         Ok(())
     }
 
-    fn has_x_lock(&self, block: &Block) -> bool {
-        self.locks
-            .get(block)
-            .map(|l| l.eq(&LockTypes::Exclusive))
-            .unwrap_or_default()
+    /// Whether the locks held on the resource or on any of its ancestors already grant the
+    /// resource in the given mode
+    fn is_covered(
+        locks: &HashMap<LockTarget, LockMode>,
+        target: &LockTarget,
+        mode: LockMode,
+    ) -> bool {
+        if locks.get(target).is_some_and(|held| held.covers(mode)) {
+            return true;
+        }
+
+        target.ancestors().iter().any(|ancestor| {
+            locks
+                .get(ancestor)
+                .is_some_and(|held| held.covers_descendants(mode))
+        })
+    }
+
+    /// Counts a block or record lock taken on a file. Returns true if the count went past
+    /// the escalation threshold, or grew by the threshold since escalating last failed
+    fn track(&self, target: &LockTarget, mode: LockMode, added: bool) -> bool {
+        if !matches!(target, LockTarget::Block(_) | LockTarget::Record(..)) {
+            return false;
+        }
+        let Some(file) = target.filename() else {
+            return false;
+        };
+
+        let mut files = self.files.safe_lock();
+        let held = files.entry(file.to_string()).or_default();
+        held.written |= mode.is_write();
+        if !added {
+            return false;
+        }
+        held.count += 1;
+
+        let threshold = self.escalation_threshold();
+        held.count > held.failed_at.saturating_add(threshold)
+    }
+
+    /// Stops counting a block or record lock released before the transaction finishes
+    fn untrack(&self, target: &LockTarget) {
+        if !matches!(target, LockTarget::Block(_) | LockTarget::Record(..)) {
+            return;
+        }
+        let Some(file) = target.filename() else {
+            return;
+        };
+        if let Some(held) = self.files.safe_lock().get_mut(file) {
+            held.count = held.count.saturating_sub(1);
+        }
+    }
+
+    /// Once the transaction holds more block and record locks on the file than the escalation
+    /// threshold the whole file is locked instead, in exclusive mode if any of them was written.
+    ///
+    /// The escalation does not wait for other transactions, if the file lock is not available
+    /// right away the transaction keeps using the fine grained locks and only tries again once
+    /// it took more than another threshold worth of them.
+    fn escalate(&self, locks: &mut HashMap<LockTarget, LockMode>, filename: &str) {
+        let mut files = self.files.safe_lock();
+        let Some(held) = files.get_mut(filename) else {
+            return;
+        };
+
+        let mode = if held.written {
+            LockMode::Exclusive
+        } else {
+            LockMode::Shared
+        };
+
+        let file = LockTarget::File(filename.to_string());
+        if self
+            .manager
+            .lock_table
+            .lock(
+                &file,
+                mode,
+                self.txnum,
+                DeadlockPolicy::Timeout,
+                Duration::ZERO,
+            )
+            .is_err()
+        {
+            held.failed_at = held.count;
+            return;
+        }
+        files.remove(filename);
+
+        let file_mode = locks.get(&file).copied();
+        locks.insert(file.clone(), file_mode.map_or(mode, |held| held.join(mode)));
+
+        locks.retain(|target, _| {
+            let descendant = matches!(target, LockTarget::Block(_) | LockTarget::Record(..))
+                && target.filename() == Some(filename);
+            if descendant {
+                self.manager.lock_table.unlock(target, self.txnum);
+            }
+            !descendant
+        });
     }
 }
@@ -8,11 +8,20 @@ mod tests {
     };
 
     use crate::{
-        server::{DBServer, DBServerOptions},
+        error::DbError,
+        server::{DBServer, DBServerOptions, TxOptions},
         storage::bufferpool::pool::BufferPoolManager,
         storage::disk::{block::Block, manager::Manager},
         storage::log::manager::LogManager,
-        storage::tx::Transactions,
+        storage::record::{
+            layout::Layout,
+            rowid::RowId,
+            scan::{RowImpl, Scan, TableScan, UpdateScan},
+            schema::Schema,
+        },
+        storage::tx::{
+            DeadlockPolicy, IsolationLevel, LockMode, LockTarget, Transactions, TxManager,
+        },
     };
 
     #[test]
@@ -32,17 +41,20 @@ mod tests {
         let fm_a = fm.clone();
         let lm_a = lm.clone();
         let bm_a = bm.clone();
-        let handler_a = thread::spawn(move || run_a(fm_a, lm_a, bm_a));
+        let tm_a = db.tx_manager();
+        let handler_a = thread::spawn(move || run_a(fm_a, lm_a, bm_a, tm_a));
 
         let fm_b = fm.clone();
         let lm_b = lm.clone();
         let bm_b = bm.clone();
-        let handler_b = thread::spawn(move || run_b(fm_b, lm_b, bm_b));
+        let tm_b = db.tx_manager();
+        let handler_b = thread::spawn(move || run_b(fm_b, lm_b, bm_b, tm_b));
 
         let fm_c = fm.clone();
         let lm_c = lm.clone();
         let bm_c = bm.clone();
-        let handler_c = thread::spawn(move || run_c(fm_c, lm_c, bm_c));
+        let tm_c = db.tx_manager();
+        let handler_c = thread::spawn(move || run_c(fm_c, lm_c, bm_c, tm_c));
 
         handler_a.join().unwrap();
         handler_b.join().unwrap();
@@ -51,8 +63,13 @@ mod tests {
         fs::remove_dir_all("concurrencytest").unwrap();
     }
 
-    fn run_a(fm: Arc<Manager>, lm: Arc<Mutex<LogManager>>, bm: Arc<Mutex<BufferPoolManager>>) {
-        let mut tx_a = Transactions::new(fm, bm, lm).unwrap();
+    fn run_a(
+        fm: Arc<Manager>,
+        lm: Arc<Mutex<LogManager>>,
+        bm: Arc<Mutex<BufferPoolManager>>,
+        tm: Arc<TxManager>,
+    ) {
+        let mut tx_a = Transactions::new(fm, bm, lm, tm, DeadlockPolicy::default()).unwrap();
         let blk1 = Block::new("testfile".to_string(), 1);
         let blk2 = Block::new("testfile".to_string(), 2);
         tx_a.pin(&blk1).unwrap();
@@ -73,8 +90,13 @@ mod tests {
         println!("TX A: commit");
     }
 
-    fn run_b(fm: Arc<Manager>, lm: Arc<Mutex<LogManager>>, bm: Arc<Mutex<BufferPoolManager>>) {
-        let mut tx_b = Transactions::new(fm, bm, lm).unwrap();
+    fn run_b(
+        fm: Arc<Manager>,
+        lm: Arc<Mutex<LogManager>>,
+        bm: Arc<Mutex<BufferPoolManager>>,
+        tm: Arc<TxManager>,
+    ) {
+        let mut tx_b = Transactions::new(fm, bm, lm, tm, DeadlockPolicy::default()).unwrap();
         let blk1 = Block::new("testfile".to_string(), 1);
         let blk2 = Block::new("testfile".to_string(), 2);
         tx_b.pin(&blk1).unwrap();
@@ -95,13 +117,20 @@ mod tests {
         println!("TX B: COMMIT");
     }
 
-    fn run_c(fm: Arc<Manager>, lm: Arc<Mutex<LogManager>>, bm: Arc<Mutex<BufferPoolManager>>) {
-        let mut tx_c = Transactions::new(fm, bm, lm).unwrap();
+    fn run_c(
+        fm: Arc<Manager>,
+        lm: Arc<Mutex<LogManager>>,
+        bm: Arc<Mutex<BufferPoolManager>>,
+        tm: Arc<TxManager>,
+    ) {
+        let mut tx_c = Transactions::new(fm, bm, lm, tm, DeadlockPolicy::default()).unwrap();
         let blk1 = Block::new("testfile".to_string(), 1);
         let blk2 = Block::new("testfile".to_string(), 2);
         tx_c.pin(&blk1).unwrap();
         tx_c.pin(&blk2).unwrap();
-        thread::sleep(Duration::from_millis(10));
+        // Requests are served in order, waiting for block 1 before B reads it would leave A
+        // waiting for B, B for C and C for A
+        thread::sleep(Duration::from_millis(40));
 
         tx_c.set_int(&blk1, 0, 0, false).unwrap();
 
@@ -111,4 +140,385 @@ mod tests {
 
         tx_c.commit().unwrap();
     }
+
+    #[test]
+    fn hierarchytest() {
+        let db = DBServer::new_with_params(
+            DBServerOptions::default()
+                .block_size(400)
+                .directory("hierarchytest".to_string())
+                .pool_size(8)
+                .lock_timeout(Duration::from_millis(100)),
+        )
+        .unwrap();
+        let file = "hierarchyfile".to_string();
+        let blk0 = Block::new(file.clone(), 0);
+        let blk1 = Block::new(file.clone(), 1);
+
+        let mut tx1 = db.new_tx().unwrap();
+        tx1.pin(&blk0).unwrap();
+        tx1.set_int(&blk0, 0, 1, false).unwrap();
+        assert_eq!(
+            tx1.lock_mode(&LockTarget::File(file.clone())),
+            Some(LockMode::IntentionExclusive)
+        );
+        assert_eq!(
+            tx1.lock_mode(&LockTarget::Database),
+            Some(LockMode::IntentionExclusive)
+        );
+
+        // Other blocks of the file can still be used, the file as a whole cannot
+        let mut tx2 = db.new_tx().unwrap();
+        tx2.pin(&blk1).unwrap();
+        tx2.pin(&blk0).unwrap();
+        tx2.get_int(&blk1, 0).unwrap();
+        assert!(matches!(tx2.get_int(&blk0, 0), Err(DbError::LockAborted)));
+        assert!(matches!(
+            tx2.lock(&LockTarget::File(file.clone()), LockMode::Exclusive),
+            Err(DbError::LockAborted)
+        ));
+        tx2.rollback().unwrap();
+
+        tx1.commit().unwrap();
+
+        let mut tx3 = db.new_tx().unwrap();
+        tx3.lock(&LockTarget::File(file.clone()), LockMode::Exclusive)
+            .unwrap();
+        tx3.pin(&blk0).unwrap();
+        tx3.set_int(&blk0, 0, 2, false).unwrap();
+        assert_eq!(tx3.lock_mode(&LockTarget::Block(blk0.clone())), None);
+        tx3.commit().unwrap();
+
+        fs::remove_dir_all("hierarchytest").unwrap();
+    }
+
+    #[test]
+    fn escalationtest() {
+        let db = DBServer::new_with_params(
+            DBServerOptions::default()
+                .block_size(400)
+                .directory("escalationtest".to_string())
+                .pool_size(8)
+                .lock_timeout(Duration::from_millis(100))
+                .lock_escalation_threshold(2),
+        )
+        .unwrap();
+        let file = "escalationfile".to_string();
+        let blocks = (0..3)
+            .map(|num| Block::new(file.clone(), num))
+            .collect::<Vec<_>>();
+
+        let mut tx1 = db.new_tx().unwrap();
+        for blk in &blocks {
+            tx1.pin(blk).unwrap();
+            tx1.get_int(blk, 0).unwrap();
+        }
+
+        assert_eq!(
+            tx1.lock_mode(&LockTarget::File(file.clone())),
+            Some(LockMode::Shared)
+        );
+        for blk in &blocks {
+            assert_eq!(tx1.lock_mode(&LockTarget::Block(blk.clone())), None);
+        }
+
+        // Readers are fine with the escalated lock, writers are not
+        let mut tx2 = db.new_tx().unwrap();
+        tx2.pin(&blocks[0]).unwrap();
+        tx2.get_int(&blocks[0], 0).unwrap();
+        assert!(matches!(
+            tx2.set_int(&blocks[0], 0, 1, false),
+            Err(DbError::LockAborted)
+        ));
+        tx2.rollback().unwrap();
+
+        // Writing a block of the escalated file upgrades the file lock
+        tx1.set_int(&blocks[1], 0, 1, false).unwrap();
+        assert_eq!(
+            tx1.lock_mode(&LockTarget::File(file.clone())),
+            Some(LockMode::SharedIntentionExclusive)
+        );
+        tx1.commit().unwrap();
+
+        fs::remove_dir_all("escalationtest").unwrap();
+    }
+
+    #[test]
+    fn failedescalationtest() {
+        let db = DBServer::new_with_params(
+            DBServerOptions::default()
+                .block_size(400)
+                .directory("failedescalationtest".to_string())
+                .pool_size(8)
+                .lock_timeout(Duration::from_millis(100))
+                .lock_escalation_threshold(2),
+        )
+        .unwrap();
+        let file = "failedescalationfile".to_string();
+        let blocks = (0..7)
+            .map(|num| Block::new(file.clone(), num))
+            .collect::<Vec<_>>();
+
+        // The writer keeps the reader from locking the whole file
+        let mut writer = db.new_tx().unwrap();
+        writer.pin(&blocks[6]).unwrap();
+        writer.set_int(&blocks[6], 0, 1, false).unwrap();
+
+        let mut reader = db.new_tx().unwrap();
+        for blk in &blocks[..3] {
+            reader.pin(blk).unwrap();
+            reader.get_int(blk, 0).unwrap();
+        }
+        assert_eq!(
+            reader.lock_mode(&LockTarget::File(file.clone())),
+            Some(LockMode::IntentionShared)
+        );
+        writer.commit().unwrap();
+
+        // Reading the blocks it holds doesn't try again, neither do a threshold worth of locks
+        reader.get_int(&blocks[2], 0).unwrap();
+        for blk in &blocks[3..5] {
+            reader.pin(blk).unwrap();
+            reader.get_int(blk, 0).unwrap();
+        }
+        assert_eq!(
+            reader.lock_mode(&LockTarget::File(file.clone())),
+            Some(LockMode::IntentionShared)
+        );
+
+        // Once it took more than that the file is locked
+        reader.pin(&blocks[5]).unwrap();
+        reader.get_int(&blocks[5], 0).unwrap();
+        assert_eq!(
+            reader.lock_mode(&LockTarget::File(file.clone())),
+            Some(LockMode::Shared)
+        );
+        for blk in &blocks[..6] {
+            assert_eq!(reader.lock_mode(&LockTarget::Block(blk.clone())), None);
+        }
+        reader.commit().unwrap();
+
+        fs::remove_dir_all("failedescalationtest").unwrap();
+    }
+
+    #[test]
+    fn recordescalationtest() {
+        let db = DBServer::new_with_params(
+            DBServerOptions::default()
+                .block_size(400)
+                .directory("recordescalationtest".to_string())
+                .pool_size(8)
+                .lock_timeout(Duration::from_millis(100))
+                .lock_escalation_threshold(4),
+        )
+        .unwrap();
+
+        let mut sch = Schema::new();
+        sch.add_int_field("A".to_string());
+        let layout = Layout::new(sch).unwrap();
+        let table = "recordescalation".to_string();
+
+        let mut tx = db.new_tx().unwrap();
+        let mut ts = TableScan::new(tx.clone(), table.clone(), layout.clone()).unwrap();
+        for i in 0..10 {
+            ts.insert().unwrap();
+            ts.set_int("A", i).unwrap();
+        }
+        ts.close().unwrap();
+        tx.commit().unwrap();
+
+        // The rows are all in a few blocks, the record locks alone get the file locked
+        let mut reader = db
+            .new_tx_with(TxOptions::default().isolation_level(IsolationLevel::RepeatableRead))
+            .unwrap();
+        let mut ts = TableScan::new(reader.clone(), table.clone(), layout.clone()).unwrap();
+        let mut rids = Vec::new();
+        while ts.next().unwrap() {
+            ts.get_int("A").unwrap();
+            rids.push(ts.get_row_id());
+        }
+        ts.close().unwrap();
+
+        assert_eq!(rids.len(), 10);
+        assert_eq!(
+            reader.lock_mode(&LockTarget::File(format!("{table}.tbl"))),
+            Some(LockMode::Shared)
+        );
+        for rid in rids {
+            assert_eq!(
+                reader.lock_mode(&LockTarget::Record(format!("{table}.tbl"), rid)),
+                None
+            );
+        }
+        reader.commit().unwrap();
+
+        fs::remove_dir_all("recordescalationtest").unwrap();
+    }
+
+    #[test]
+    fn isolationtest() {
+        let db = DBServer::new_with_params(
+            DBServerOptions::default()
+                .block_size(400)
+                .directory("isolationtest".to_string())
+                .pool_size(8)
+                .lock_timeout(Duration::from_millis(100)),
+        )
+        .unwrap();
+
+        let mut sch = Schema::new();
+        sch.add_int_field("A".to_string());
+        let layout = Layout::new(sch).unwrap();
+        let scan = |tx: &Transactions| {
+            TableScan::new(tx.clone(), "isolation".to_string(), layout.clone()).unwrap()
+        };
+        let begin = |level: IsolationLevel| {
+            db.new_tx_with(TxOptions::default().isolation_level(level))
+                .unwrap()
+        };
+        let read = |ts: &mut TableScan, rid: &RowId| {
+            ts.move_to_row_id(rid.clone()).unwrap();
+            ts.get_int("A")
+        };
+        let count = |ts: &mut TableScan| {
+            let mut count = 0;
+            ts.before_first().unwrap();
+            while ts.next().unwrap() {
+                count += 1;
+            }
+            count
+        };
+
+        let mut tx = db.new_tx().unwrap();
+        let mut ts = scan(&tx);
+        ts.insert().unwrap();
+        ts.set_int("A", 0).unwrap();
+        let rid = ts.get_row_id();
+        ts.close().unwrap();
+        tx.commit().unwrap();
+
+        // Dirty read: only read uncommitted sees a change which is rolled back later
+        let mut writer = db.new_tx().unwrap();
+        let mut ws = scan(&writer);
+        ws.move_to_row_id(rid.clone()).unwrap();
+        ws.set_int("A", 10).unwrap();
+
+        let mut uncommitted = begin(IsolationLevel::ReadUncommitted);
+        let mut us = scan(&uncommitted);
+        assert_eq!(read(&mut us, &rid).unwrap(), 10);
+
+        let mut committed = begin(IsolationLevel::ReadCommitted);
+        let mut cs = scan(&committed);
+        assert!(matches!(read(&mut cs, &rid), Err(DbError::LockAborted)));
+        cs.close().unwrap();
+        committed.rollback().unwrap();
+
+        ws.close().unwrap();
+        writer.rollback().unwrap();
+        assert_eq!(read(&mut us, &rid).unwrap(), 0);
+        us.close().unwrap();
+        uncommitted.commit().unwrap();
+
+        // Non repeatable read: read committed lets go of the record right after reading it
+        let mut committed = begin(IsolationLevel::ReadCommitted);
+        let mut cs = scan(&committed);
+        assert_eq!(read(&mut cs, &rid).unwrap(), 0);
+
+        let mut writer = db.new_tx().unwrap();
+        let mut ws = scan(&writer);
+        ws.move_to_row_id(rid.clone()).unwrap();
+        ws.set_int("A", 20).unwrap();
+        ws.close().unwrap();
+        writer.commit().unwrap();
+
+        assert_eq!(read(&mut cs, &rid).unwrap(), 20);
+        cs.close().unwrap();
+        committed.commit().unwrap();
+
+        let mut repeatable = begin(IsolationLevel::RepeatableRead);
+        let mut rs = scan(&repeatable);
+        assert_eq!(read(&mut rs, &rid).unwrap(), 20);
+
+        let mut writer = db.new_tx().unwrap();
+        let mut ws = scan(&writer);
+        ws.move_to_row_id(rid.clone()).unwrap();
+        assert!(matches!(ws.set_int("A", 30), Err(DbError::LockAborted)));
+        ws.close().unwrap();
+        writer.rollback().unwrap();
+        assert_eq!(read(&mut rs, &rid).unwrap(), 20);
+
+        // Phantom: repeatable read still lets records be inserted next to the ones it read
+        assert_eq!(count(&mut rs), 1);
+
+        let mut writer = db.new_tx().unwrap();
+        let mut ws = scan(&writer);
+        ws.insert().unwrap();
+        ws.set_int("A", 1).unwrap();
+        ws.close().unwrap();
+        writer.commit().unwrap();
+
+        assert_eq!(count(&mut rs), 2);
+        rs.close().unwrap();
+        repeatable.commit().unwrap();
+
+        let mut serializable = begin(IsolationLevel::Serializable);
+        let mut ss = scan(&serializable);
+        assert_eq!(count(&mut ss), 2);
+
+        let mut writer = db.new_tx().unwrap();
+        let mut ws = scan(&writer);
+        assert!(matches!(ws.insert(), Err(DbError::LockAborted)));
+        ws.close().unwrap();
+        writer.rollback().unwrap();
+
+        assert_eq!(count(&mut ss), 2);
+        ss.close().unwrap();
+        serializable.commit().unwrap();
+
+        fs::remove_dir_all("isolationtest").unwrap();
+    }
+
+    #[test]
+    fn separateserverstest() {
+        let open = |dir: &str| {
+            DBServer::new_with_params(
+                DBServerOptions::default()
+                    .block_size(400)
+                    .directory(dir.to_string())
+                    .pool_size(8)
+                    .lock_timeout(Duration::from_millis(100)),
+            )
+            .unwrap()
+        };
+        let db1 = open("separateserverstest1");
+        let db2 = open("separateserverstest2");
+        let blk = Block::new("testfile".to_string(), 0);
+
+        // Both servers start numbering their transactions from scratch
+        let mut tx1 = db1.new_tx().unwrap();
+        let mut tx2 = db2.new_tx().unwrap();
+        assert_eq!(tx1.txnum(), tx2.txnum());
+
+        // A file with the same name on another server is a different file, locking it does not
+        // wait for the first server
+        tx1.pin(&blk).unwrap();
+        tx1.set_int(&blk, 0, 1, true).unwrap();
+        tx2.pin(&blk).unwrap();
+        tx2.set_int(&blk, 0, 2, true).unwrap();
+
+        let mut tx3 = db1.new_tx().unwrap();
+        tx3.pin(&blk).unwrap();
+        assert!(matches!(tx3.get_int(&blk, 0), Err(DbError::LockAborted)));
+        tx3.rollback().unwrap();
+
+        tx2.commit().unwrap();
+        assert_eq!(
+            tx1.lock_mode(&LockTarget::Block(blk.clone())),
+            Some(LockMode::Exclusive)
+        );
+        tx1.commit().unwrap();
+
+        fs::remove_dir_all("separateserverstest1").unwrap();
+        fs::remove_dir_all("separateserverstest2").unwrap();
+    }
 }
@@ -0,0 +1,58 @@
+use super::Transactions;
+use crate::error::DbResult;
+use std::ops::{Deref, DerefMut};
+
+/// Number of times `DBServer::transaction` runs a transaction which keeps failing on locks,
+/// unless configured otherwise
+pub const DEFAULT_TRANSACTION_RETRIES: usize = 3;
+
+/// Longest pause in milliseconds before the first retry of `DBServer::transaction`, each
+/// further retry can wait that much longer
+pub const RETRY_BACKOFF_MS: u64 = 10;
+
+/// A transaction which is rolled back when the guard is dropped without being committed, so
+/// leaving a function early with `?` never leaves its locks and pinned buffers behind
+///
+/// The guard derefs to the transaction, clones of it handed to scans belong to the same
+/// transaction. A transaction which was prepared is left alone on drop, its outcome is up to
+/// the coordinator.
+pub struct TxGuard {
+    tx: Transactions,
+}
+
+impl TxGuard {
+    pub fn new(tx: Transactions) -> Self {
+        Self { tx }
+    }
+
+    pub fn commit(mut self) -> DbResult<()> {
+        self.tx.commit()
+    }
+
+    pub fn rollback(mut self) -> DbResult<()> {
+        self.tx.rollback()
+    }
+}
+
+impl Deref for TxGuard {
+    type Target = Transactions;
+
+    fn deref(&self) -> &Self::Target {
+        &self.tx
+    }
+}
+
+impl DerefMut for TxGuard {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.tx
+    }
+}
+
+impl Drop for TxGuard {
+    fn drop(&mut self) {
+        if self.tx.is_running() && self.tx.gid().is_none() {
+            // Nobody is left to report the error to
+            let _ = self.tx.rollback();
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use crate::{storage::disk::block::Block, utils::safe_lock::SafeLock};
+use std::collections::HashSet;
+use std::sync::{Arc, Condvar, Mutex};
+
+/// Short lived mutual exclusion on a block, unlike locks latches are not tied to a
+/// transaction and are released as soon as the guarded operation is over. They protect the
+/// operations which have to inspect and change a block in one go, e.g finding an empty slot
+/// and claiming it.
+pub struct LatchTable {
+    latched: Mutex<HashSet<Block>>,
+    released: Condvar,
+}
+
+/// Guard of a latched block, the latch is released when the guard is dropped
+pub struct Latch {
+    table: Arc<LatchTable>,
+    block: Block,
+}
+
+impl LatchTable {
+    pub fn new() -> Self {
+        Self {
+            latched: Mutex::new(HashSet::new()),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Waits till nobody else has the block latched and latches it
+    pub fn latch(self: &Arc<Self>, block: &Block) -> Latch {
+        let mut latched = self.latched.safe_lock();
+
+        while latched.contains(block) {
+            latched = match self.released.wait(latched) {
+                Ok(guard) => guard,
+                Err(e) => e.into_inner(),
+            };
+        }
+        latched.insert(block.clone());
+
+        Latch {
+            table: self.clone(),
+            block: block.clone(),
+        }
+    }
+}
+
+impl Drop for Latch {
+    fn drop(&mut self) {
+        self.table.latched.safe_lock().remove(&self.block);
+        self.table.released.notify_all();
+    }
+}
@@ -0,0 +1,95 @@
+/// Modes a lock can be held in. The intention modes are taken on the ancestors of a
+/// resource before locking the resource itself, so a coarse grained lock request can find
+/// out about fine grained locks held further down the hierarchy.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LockMode {
+    /// The transaction intends to read some of the descendants
+    IntentionShared,
+    /// The transaction intends to write some of the descendants
+    IntentionExclusive,
+    /// The resource and all of its descendants are read
+    Shared,
+    /// The resource and all of its descendants are read, some of the descendants are written
+    SharedIntentionExclusive,
+    /// The resource and all of its descendants are written
+    Exclusive,
+}
+
+impl LockMode {
+    /// Whether two different transactions can hold the lock in these modes at the same time
+    pub fn is_compatible(self, other: LockMode) -> bool {
+        use LockMode::*;
+
+        matches!(
+            (self, other),
+            (IntentionShared, IntentionShared)
+                | (IntentionShared, IntentionExclusive)
+                | (IntentionShared, Shared)
+                | (IntentionShared, SharedIntentionExclusive)
+                | (IntentionExclusive, IntentionShared)
+                | (IntentionExclusive, IntentionExclusive)
+                | (Shared, IntentionShared)
+                | (Shared, Shared)
+                | (SharedIntentionExclusive, IntentionShared)
+        )
+    }
+
+    /// The weakest mode which grants everything both the modes grant, this is the mode a lock
+    /// ends up in when a transaction holding it in one mode requests the other one
+    pub fn join(self, other: LockMode) -> LockMode {
+        use LockMode::*;
+
+        match (self, other) {
+            (a, b) if a == b => a,
+            (Exclusive, _) | (_, Exclusive) => Exclusive,
+            (SharedIntentionExclusive, _) | (_, SharedIntentionExclusive) => {
+                SharedIntentionExclusive
+            }
+            (IntentionExclusive, Shared) | (Shared, IntentionExclusive) => SharedIntentionExclusive,
+            (IntentionShared, other) | (other, IntentionShared) => other,
+            _ => unreachable!("Every pair of lock modes is handled above"),
+        }
+    }
+
+    /// Whether holding the lock in this mode already grants the `other` mode
+    pub fn covers(self, other: LockMode) -> bool {
+        self.join(other) == self
+    }
+
+    /// The intention mode to be held on the ancestors of a resource locked in this mode
+    pub fn intention(self) -> LockMode {
+        match self {
+            Self::IntentionShared | Self::Shared => Self::IntentionShared,
+            _ => Self::IntentionExclusive,
+        }
+    }
+
+    /// Whether holding this mode on a resource implicitly locks all of its descendants in the
+    /// `other` mode
+    pub fn covers_descendants(self, other: LockMode) -> bool {
+        match self {
+            Self::Exclusive => true,
+            Self::Shared | Self::SharedIntentionExclusive => {
+                matches!(other, Self::IntentionShared | Self::Shared)
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the mode allows writing the resource or any of its descendants
+    pub fn is_write(self) -> bool {
+        !matches!(self, Self::IntentionShared | Self::Shared)
+    }
+}
+
+impl std::fmt::Display for LockMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IntentionShared => write!(f, "IS"),
+            Self::IntentionExclusive => write!(f, "IX"),
+            Self::Shared => write!(f, "S"),
+            Self::SharedIntentionExclusive => write!(f, "SIX"),
+            Self::Exclusive => write!(f, "X"),
+        }
+    }
+}
@@ -1,91 +1,464 @@
+use super::{lock_mode::LockMode, lock_target::LockTarget};
 use crate::{
     error::{DbError, DbResult},
-    storage::disk::block::Block,
+    utils::safe_lock::SafeLock,
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
 use std::time::{Duration, Instant};
 
-/// Maintains the Lock status for a Block, Granularity of the lock is on a single block
-pub struct LockTable(HashMap<Block, i32>);
+/// Time a transaction waits for a lock unless configured otherwise
+pub const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_millis(10000);
 
-impl LockTable {
-    /// Max time till you can keep a transaction waiting
-    const MAX_TIME: u32 = 10000_u32;
+/// Decides what happens when a transaction requests a lock that conflicts with the
+/// locks held by other transactions.
+///
+/// The timestamp based schemes use the transaction number as the age of a transaction,
+/// i.e a smaller transaction number means an older transaction, unless the transaction was
+/// given another age with `LockTable::set_age`.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum DeadlockPolicy {
+    /// Wait till the lock timeout for the lock and abort if it's still not available
+    #[default]
+    Timeout,
+    /// An older transaction waits for a younger one, a younger transaction requesting a lock
+    /// held by an older one is aborted immediately
+    WaitDie,
+    /// An older transaction wounds (aborts) the younger holders of the lock and waits for them
+    /// to release it, a younger transaction waits for an older one
+    WoundWait,
+}
+
+/// Holders and waiters of the lock on a single resource
+#[derive(Default)]
+struct LockState {
+    holders: HashMap<u64, LockMode>,
+    /// Tickets and transactions of the waiting requests in the order they are granted
+    waiters: VecDeque<(u64, u64)>,
+    next_ticket: u64,
+    /// Set once the queue is removed from the table, a request which finds a retired queue
+    /// has to look the resource up again
+    retired: bool,
+}
+
+impl LockState {
+    /// Transactions other than `txnum` which prevent it from getting the lock in `mode`
+    fn conflicts(&self, txnum: u64, mode: LockMode) -> Vec<u64> {
+        self.holders
+            .iter()
+            .filter(|(holder, held)| **holder != txnum && !held.is_compatible(mode))
+            .map(|(holder, _)| *holder)
+            .collect()
+    }
+
+    /// Grants the lock in `mode`, a transaction already holding the lock ends up holding it
+    /// in the mode covering both
+    fn grant(&mut self, txnum: u64, mode: LockMode) {
+        self.holders
+            .entry(txnum)
+            .and_modify(|held| *held = held.join(mode))
+            .or_insert(mode);
+    }
+
+    /// Queues a new request, lock upgrades are served before every other waiter since the
+    /// upgrading transaction is already blocking them
+    fn enqueue(&mut self, txnum: u64, upgrade: bool) -> u64 {
+        let ticket = self.next_ticket;
+        self.next_ticket += 1;
+
+        if upgrade {
+            self.waiters.push_front((ticket, txnum));
+        } else {
+            self.waiters.push_back((ticket, txnum));
+        }
+        ticket
+    }
+
+    fn dequeue(&mut self, ticket: Option<u64>) {
+        if let Some(ticket) = ticket {
+            self.waiters.retain(|(t, _)| *t != ticket);
+        }
+    }
+
+    /// A new request only goes first when nobody is queued, otherwise it would overtake the
+    /// waiters and could starve them
+    fn is_turn(&self, ticket: Option<u64>) -> bool {
+        match ticket {
+            None => self.waiters.is_empty(),
+            Some(ticket) => self.waiters.front().is_some_and(|(t, _)| *t == ticket),
+        }
+    }
+
+    /// Transactions queued before the request with `ticket`, which are served first
+    fn ahead(&self, ticket: u64) -> Vec<u64> {
+        self.waiters
+            .iter()
+            .take_while(|(t, _)| *t != ticket)
+            .map(|(_, txnum)| *txnum)
+            .collect()
+    }
 
+    fn release(&mut self, txnum: u64) {
+        self.holders.remove(&txnum);
+    }
+
+    fn is_unused(&self) -> bool {
+        self.holders.is_empty() && self.waiters.is_empty()
+    }
+}
+
+/// Lock of a single resource, waiters sleep on the condition variable of the resource they
+/// wait for
+#[derive(Default)]
+struct LockQueue {
+    state: Mutex<LockState>,
+    cond: Condvar,
+}
+
+/// A single request for a lock by a transaction
+struct LockRequest {
+    txnum: u64,
+    mode: LockMode,
+    policy: DeadlockPolicy,
+    deadline: Instant,
+}
+
+#[derive(Default)]
+struct LockTableInner {
+    locks: HashMap<LockTarget, Arc<LockQueue>>,
+    /// Transactions which were wounded by an older transaction and have to abort
+    wounded: HashSet<u64>,
+    /// Transactions which were aborted by someone else and didn't release their locks yet
+    aborted: HashSet<u64>,
+    /// Lock queue each waiting transaction is sleeping on
+    waiting: HashMap<u64, Arc<LockQueue>>,
+    /// Ages of the transactions which are not as old as their transaction number says
+    ages: HashMap<u64, u64>,
+}
+
+impl LockTableInner {
+    fn age(&self, txnum: u64) -> u64 {
+        self.ages.get(&txnum).copied().unwrap_or(txnum)
+    }
+}
+
+/// Maintains the Lock status of the lockable resources, i.e the database, files, blocks and
+/// records. The lock table itself knows nothing about the hierarchy, taking the intention locks
+/// on the ancestors is up to the caller
+///
+/// The table mutex is only held to look up the lock of a resource, waiting happens on the
+/// lock of the resource so transactions working on other resources are never blocked by it.
+/// A request compatible with the current holders is granted straight away, conflicting
+/// requests are queued and granted in the order they arrived.
+///
+/// Lock ordering: a resource lock may be held while taking the table mutex, never the other
+/// way around and never two resource locks at once
+pub struct LockTable {
+    inner: Mutex<LockTableInner>,
+}
+
+impl LockTable {
     pub fn new() -> Self {
-        Self(HashMap::new())
+        Self {
+            inner: Mutex::new(LockTableInner::default()),
+        }
     }
 
-    /// Shared lock can be obtained by on or more transactions, usually for reading
-    /// When you acquire the shared lock you cannot acquire exclusive lock and vise versa
+    /// Acquires the lock on the resource in the given mode, a transaction already holding the
+    /// lock in another mode gets it upgraded to the mode covering both.
     ///
-    /// We first check if the block already has an exclusive lock if so we park the current
-    /// thread till `MAX_TIME` milliseconds. If the block still has an exclusive lock then
-    /// the `LockAborted` error is returned
-    pub fn slock(&mut self, blk: &Block) -> DbResult<()> {
-        let time = Instant::now();
-
-        while self.has_x_lock(blk) && !Self::did_wait_too_long(time) {
-            std::thread::park_timeout(Duration::from_millis(Self::MAX_TIME as u64));
+    /// If any other transaction holds the lock in an incompatible mode the `policy` decides
+    /// whether the transaction waits or is aborted. A waiting transaction gets the
+    /// `LockAborted` error if the lock is still held after `timeout`
+    pub fn lock(
+        &self,
+        target: &LockTarget,
+        mode: LockMode,
+        txnum: u64,
+        policy: DeadlockPolicy,
+        timeout: Duration,
+    ) -> DbResult<()> {
+        let request = LockRequest {
+            txnum,
+            mode,
+            policy,
+            deadline: Instant::now() + timeout,
+        };
+
+        loop {
+            let queue = self.queue(target);
+            let state = queue.state.safe_lock();
+
+            if state.retired {
+                continue;
+            }
+
+            let (mut state, result) = self.wait_for(&queue, state, target, &request);
+
+            // A request which failed right away leaves an unused lock behind
+            if state.is_unused() {
+                self.retire(target, &queue, &mut state);
+            }
+
+            return result;
         }
+    }
+
+    /// Mode the transaction holds the lock on the resource in
+    pub fn mode(&self, target: &LockTarget, txnum: u64) -> Option<LockMode> {
+        let queue = self.inner.safe_lock().locks.get(target).cloned()?;
+        let state = queue.state.safe_lock();
+        state.holders.get(&txnum).copied()
+    }
+
+    /// Every lock held by `txnum` along with its mode. Only the resource being looked at is
+    /// locked at a time, so this works while the transaction is waiting for a lock
+    pub fn held_by(&self, txnum: u64) -> Vec<(LockTarget, LockMode)> {
+        let queues = {
+            let inner = self.inner.safe_lock();
+            inner
+                .locks
+                .iter()
+                .map(|(target, queue)| (target.clone(), queue.clone()))
+                .collect::<Vec<_>>()
+        };
 
-        if self.has_x_lock(blk) {
-            return Err(DbError::LockAborted);
+        queues
+            .into_iter()
+            .filter_map(|(target, queue)| {
+                let held = queue.state.safe_lock().holders.get(&txnum).copied();
+                held.map(|mode| (target, mode))
+            })
+            .collect()
+    }
+
+    /// Releases the lock `txnum` holds on the resource, the waiters of the resource are woken
+    /// up to check whether it's their turn now
+    pub fn unlock(&self, target: &LockTarget, txnum: u64) {
+        let queue = self.inner.safe_lock().locks.get(target).cloned();
+
+        if let Some(queue) = queue {
+            self.release_queue(target, &queue, txnum);
         }
+    }
 
-        let lock = self.get_lock(blk);
-        self.0.insert(blk.clone(), lock + 1);
+    /// Makes the deadlock policies treat `txnum` as if its transaction number was `age`, e.g a
+    /// transaction run again after a deadlock keeps the age of its first attempt
+    pub fn set_age(&self, txnum: u64, age: u64) {
+        self.inner.safe_lock().ages.insert(txnum, age);
+    }
 
-        Ok(())
+    /// Releases every lock held by `txnum` and forgets whether it was wounded or aborted. This is called
+    /// once the transaction is finished
+    pub fn release_all(&self, txnum: u64) {
+        let queues = {
+            let mut inner = self.inner.safe_lock();
+            inner.wounded.remove(&txnum);
+            // Releasing its locks is the last thing an aborted transaction does
+            inner.aborted.remove(&txnum);
+            inner.ages.remove(&txnum);
+            inner
+                .locks
+                .iter()
+                .map(|(target, queue)| (target.clone(), queue.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        for (target, queue) in queues {
+            self.release_queue(&target, &queue, txnum);
+        }
     }
 
-    /// Exclusive lock can only be obtained by a single transaction, any transaction writing the
-    /// data should acquire an exclusive lock before committing
-    ///
-    /// We first check if there's any Shared lock already for the current block if so we wait till
-    /// `MAX_TIME` milliseconds, If the block is still ShareLocked then the `LockAborted` error is
-    /// returned
-    pub fn xlock(&mut self, blk: &Block) -> DbResult<()> {
-        let time = Instant::now();
-
-        while self.has_slock(blk) && !Self::did_wait_too_long(time) {
-            std::thread::park_timeout(Duration::from_millis(Self::MAX_TIME as u64));
+    fn release_queue(&self, target: &LockTarget, queue: &Arc<LockQueue>, txnum: u64) {
+        let mut state = queue.state.safe_lock();
+        state.release(txnum);
+
+        if state.is_unused() {
+            self.retire(target, queue, &mut state);
+        }
+
+        queue.cond.notify_all();
+    }
+
+    /// Removes the lock of the resource from the table once nobody holds or waits for it
+    fn retire(&self, target: &LockTarget, queue: &Arc<LockQueue>, state: &mut LockState) {
+        if state.retired {
+            return;
         }
 
-        if self.has_slock(blk) {
-            return Err(DbError::LockAborted);
+        let mut inner = self.inner.safe_lock();
+        if inner
+            .locks
+            .get(target)
+            .is_some_and(|q| Arc::ptr_eq(q, queue))
+        {
+            inner.locks.remove(target);
+            state.retired = true;
         }
+    }
 
-        self.0.insert(blk.clone(), -1);
-        Ok(())
+    /// Returns the lock of the resource, creating it if nobody holds or waits for it yet
+    fn queue(&self, target: &LockTarget) -> Arc<LockQueue> {
+        self.inner
+            .safe_lock()
+            .locks
+            .entry(target.clone())
+            .or_default()
+            .clone()
     }
 
-    /// If the lock was a shared lock, Lock value will be decremented on the lock table
-    /// if the transaction trying unlock is the last transaction holding the lock on the
-    /// block then the waiting threads are notified to wake
-    pub fn unlock(&mut self, blk: &Block) {
-        let val = self.get_lock(blk);
+    /// Waits on the lock of the resource till the request can be granted, the guard of the
+    /// lock is handed back to the caller once the request is granted or failed
+    fn wait_for<'a>(
+        &self,
+        queue: &'a Arc<LockQueue>,
+        mut state: MutexGuard<'a, LockState>,
+        target: &LockTarget,
+        request: &LockRequest,
+    ) -> (MutexGuard<'a, LockState>, DbResult<()>) {
+        let txnum = request.txnum;
+        let mut ticket = None;
+        let mut wait_span = None;
 
-        if val > 1 {
-            self.0.insert(blk.clone(), val - 1);
-        } else {
-            self.0.remove(blk);
-            std::thread::current().unpark();
+        let result = loop {
+            {
+                let inner = self.inner.safe_lock();
+                if inner.aborted.contains(&txnum) {
+                    break Err(DbError::TransactionAborted(txnum));
+                }
+                if inner.wounded.contains(&txnum) {
+                    break Err(DbError::DeadlockAborted(txnum));
+                }
+            }
+
+            let mut holders = state.conflicts(txnum, request.mode);
+
+            if holders.is_empty() && state.is_turn(ticket) {
+                state.grant(txnum, request.mode);
+                break Ok(());
+            }
+
+            // Being in the queue also keeps the lock of the resource from being retired
+            if ticket.is_none() {
+                let upgrade = state.holders.contains_key(&txnum);
+                ticket = Some(state.enqueue(txnum, upgrade));
+                self.inner.safe_lock().waiting.insert(txnum, queue.clone());
+
+                wait_span = Some(
+                    tracing::debug_span!("lock_wait", txnum, target = %target, mode = ?request.mode)
+                        .entered(),
+                );
+                tracing::trace!(?holders, "waiting for the lock");
+            }
+
+            // The waiters ahead in the queue are waited for as well as the holders
+            if let Some(ticket) = ticket {
+                holders.extend(state.ahead(ticket));
+            }
+
+            match request.policy {
+                DeadlockPolicy::Timeout => {}
+                DeadlockPolicy::WaitDie => {
+                    let inner = self.inner.safe_lock();
+                    let age = inner.age(txnum);
+                    if holders.iter().any(|holder| inner.age(*holder) < age) {
+                        break Err(DbError::DeadlockAborted(txnum));
+                    }
+                }
+                DeadlockPolicy::WoundWait => {
+                    let younger = {
+                        let inner = self.inner.safe_lock();
+                        let age = inner.age(txnum);
+                        holders
+                            .into_iter()
+                            .filter(|holder| inner.age(*holder) > age)
+                            .collect::<Vec<u64>>()
+                    };
+
+                    if !younger.is_empty() {
+                        // The wounded transactions might be sleeping on this very lock, so
+                        // let go of it while waking them up
+                        drop(state);
+                        let wounded_any = self.wound(&younger);
+                        state = queue.state.safe_lock();
+
+                        if wounded_any {
+                            continue;
+                        }
+                    }
+                }
+            }
+
+            let Some(remaining) = request.deadline.checked_duration_since(Instant::now()) else {
+                break Err(DbError::LockAborted);
+            };
+
+            state = match queue.cond.wait_timeout(state, remaining) {
+                Ok((guard, _)) => guard,
+                Err(e) => e.into_inner().0,
+            };
+        };
+
+        if let Err(e) = &result {
+            tracing::debug!(txnum, target = %target, error = %e, "lock request failed");
         }
+        drop(wait_span);
+
+        if ticket.is_some() {
+            state.dequeue(ticket);
+            self.inner.safe_lock().waiting.remove(&txnum);
+
+            // The next waiter in line might be able to get the lock now
+            queue.cond.notify_all();
+        }
+
+        (state, result)
     }
 
-    fn get_lock(&self, blk: &Block) -> i32 {
-        *self.0.get(blk).unwrap_or(&0)
+    /// Makes every lock request of the transaction fail with `TransactionAborted`, waking it
+    /// up if it's waiting for a lock right now
+    pub fn abort(&self, txnum: u64) {
+        let sleeping = {
+            let mut inner = self.inner.safe_lock();
+            inner.aborted.insert(txnum);
+            inner.waiting.get(&txnum).cloned()
+        };
+
+        Self::wake(sleeping);
     }
 
-    fn has_x_lock(&self, blk: &Block) -> bool {
-        self.get_lock(blk) < 0
+    /// Whether the transaction is waiting for a lock
+    pub fn is_waiting(&self, txnum: u64) -> bool {
+        self.inner.safe_lock().waiting.contains_key(&txnum)
     }
 
-    fn has_slock(&self, blk: &Block) -> bool {
-        self.get_lock(blk) > 1
+    /// Marks the transactions as wounded and wakes them up if they are waiting for a lock,
+    /// returns true if any of them was not wounded before
+    fn wound(&self, victims: &[u64]) -> bool {
+        let (wounded_any, sleeping) = {
+            let mut inner = self.inner.safe_lock();
+            let mut wounded_any = false;
+            let mut sleeping = Vec::new();
+
+            for victim in victims {
+                if inner.wounded.insert(*victim) {
+                    wounded_any = true;
+                    sleeping.extend(inner.waiting.get(victim).cloned());
+                }
+            }
+            (wounded_any, sleeping)
+        };
+
+        Self::wake(sleeping);
+        wounded_any
     }
-    fn did_wait_too_long(start_time: Instant) -> bool {
-        start_time.elapsed() > Duration::from_millis(Self::MAX_TIME as u64)
+
+    fn wake(sleeping: impl IntoIterator<Item = Arc<LockQueue>>) {
+        for queue in sleeping {
+            // Taking the resource lock makes sure the victim is either sleeping or has not
+            // checked whether it has to abort yet, so the notification is never lost
+            let _state = queue.state.safe_lock();
+            queue.cond.notify_all();
+        }
     }
 }
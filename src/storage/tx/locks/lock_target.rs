@@ -0,0 +1,65 @@
+use crate::storage::{disk::block::Block, record::rowid::RowId};
+
+/// A lockable resource. Resources form a hierarchy, the database contains the files,
+/// a file contains its blocks and a block contains its records.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum LockTarget {
+    Database,
+    File(String),
+    Block(Block),
+    Record(String, RowId),
+}
+
+impl LockTarget {
+    /// The resource directly containing this one, `None` for the database
+    pub fn parent(&self) -> Option<LockTarget> {
+        match self {
+            Self::Database => None,
+            Self::File(_) => Some(Self::Database),
+            Self::Block(blk) => Some(Self::File(blk.filename().to_string())),
+            Self::Record(file, row_id) => {
+                Some(Self::Block(Block::new(file.clone(), row_id.blk_num())))
+            }
+        }
+    }
+
+    /// Every resource containing this one, starting from the database
+    pub fn ancestors(&self) -> Vec<LockTarget> {
+        let mut ancestors = Vec::new();
+        let mut current = self.parent();
+
+        while let Some(target) = current {
+            current = target.parent();
+            ancestors.push(target);
+        }
+
+        ancestors.reverse();
+        ancestors
+    }
+
+    /// Name of the file the resource belongs to, `None` for the database
+    pub fn filename(&self) -> Option<&str> {
+        match self {
+            Self::Database => None,
+            Self::File(file) | Self::Record(file, _) => Some(file),
+            Self::Block(blk) => Some(blk.filename()),
+        }
+    }
+}
+
+impl std::fmt::Display for LockTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Database => write!(f, "[database]"),
+            Self::File(file) => write!(f, "[file {file}]"),
+            Self::Block(blk) => write!(f, "{blk}"),
+            Self::Record(file, row_id) => write!(
+                f,
+                "[file {} block {} slot {:?}]",
+                file,
+                row_id.blk_num(),
+                row_id.slot()
+            ),
+        }
+    }
+}
@@ -1 +1,5 @@
+pub(super) mod latch;
+pub(super) mod lock_mode;
 pub(super) mod lock_table;
+pub(super) mod lock_target;
+mod test;
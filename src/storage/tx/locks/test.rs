@@ -0,0 +1,404 @@
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        error::DbError,
+        storage::disk::block::Block,
+        storage::tx::locks::{
+            lock_mode::LockMode,
+            lock_table::{DeadlockPolicy, LockTable},
+            lock_target::LockTarget,
+        },
+    };
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    #[test]
+    fn waitdietest() {
+        let lt = Arc::new(LockTable::new());
+        let blk1 = LockTarget::Block(Block::new("waitdie".to_string(), 1));
+        let blk2 = LockTarget::Block(Block::new("waitdie".to_string(), 2));
+
+        // A younger transaction asking for a lock held by an older one dies right away
+        lt.lock(
+            &blk1,
+            LockMode::Exclusive,
+            1,
+            DeadlockPolicy::WaitDie,
+            TIMEOUT,
+        )
+        .unwrap();
+        assert!(matches!(
+            lt.lock(&blk1, LockMode::Shared, 2, DeadlockPolicy::WaitDie, TIMEOUT),
+            Err(DbError::DeadlockAborted(2))
+        ));
+        assert!(matches!(
+            lt.lock(
+                &blk1,
+                LockMode::Exclusive,
+                2,
+                DeadlockPolicy::WaitDie,
+                TIMEOUT
+            ),
+            Err(DbError::DeadlockAborted(2))
+        ));
+
+        // An older transaction asking for a lock held by a younger one waits for it
+        lt.lock(
+            &blk2,
+            LockMode::Exclusive,
+            3,
+            DeadlockPolicy::WaitDie,
+            TIMEOUT,
+        )
+        .unwrap();
+        let lt_old = lt.clone();
+        let blk_old = blk2.clone();
+        let handler = thread::spawn(move || {
+            lt_old.lock(
+                &blk_old,
+                LockMode::Shared,
+                1,
+                DeadlockPolicy::WaitDie,
+                TIMEOUT,
+            )
+        });
+
+        thread::sleep(Duration::from_millis(50));
+        assert!(!handler.is_finished());
+
+        lt.release_all(3);
+        handler.join().unwrap().unwrap();
+
+        lt.release_all(1);
+        lt.lock(
+            &blk1,
+            LockMode::Exclusive,
+            2,
+            DeadlockPolicy::WaitDie,
+            TIMEOUT,
+        )
+        .unwrap();
+
+        // A transaction given the age of an older one waits like it
+        lt.set_age(4, 1);
+        assert!(matches!(
+            lt.lock(
+                &blk1,
+                LockMode::Shared,
+                4,
+                DeadlockPolicy::WaitDie,
+                Duration::from_millis(50)
+            ),
+            Err(DbError::LockAborted)
+        ));
+        lt.release_all(4);
+        lt.release_all(2);
+
+        // Its age is forgotten once it's finished
+        lt.lock(
+            &blk1,
+            LockMode::Exclusive,
+            2,
+            DeadlockPolicy::WaitDie,
+            TIMEOUT,
+        )
+        .unwrap();
+        assert!(matches!(
+            lt.lock(&blk1, LockMode::Shared, 4, DeadlockPolicy::WaitDie, TIMEOUT),
+            Err(DbError::DeadlockAborted(4))
+        ));
+        lt.release_all(2);
+    }
+
+    #[test]
+    fn woundwaittest() {
+        let lt = Arc::new(LockTable::new());
+        let blk1 = LockTarget::Block(Block::new("woundwait".to_string(), 1));
+        let blk2 = LockTarget::Block(Block::new("woundwait".to_string(), 2));
+
+        // A younger transaction asking for a lock held by an older one waits for it
+        lt.lock(
+            &blk1,
+            LockMode::Exclusive,
+            1,
+            DeadlockPolicy::WoundWait,
+            TIMEOUT,
+        )
+        .unwrap();
+        let lt_young = lt.clone();
+        let blk_young = blk1.clone();
+        let handler = thread::spawn(move || {
+            lt_young.lock(
+                &blk_young,
+                LockMode::Shared,
+                2,
+                DeadlockPolicy::WoundWait,
+                TIMEOUT,
+            )
+        });
+
+        thread::sleep(Duration::from_millis(50));
+        assert!(!handler.is_finished());
+
+        lt.release_all(1);
+        handler.join().unwrap().unwrap();
+
+        // An older transaction asking for a lock held by a younger one wounds it and waits till
+        // the wounded transaction releases its locks
+        let lt_old = lt.clone();
+        let blk_old = blk1.clone();
+        let handler = thread::spawn(move || {
+            lt_old.lock(
+                &blk_old,
+                LockMode::Exclusive,
+                1,
+                DeadlockPolicy::WoundWait,
+                TIMEOUT,
+            )
+        });
+
+        thread::sleep(Duration::from_millis(50));
+        assert!(!handler.is_finished());
+
+        assert!(matches!(
+            lt.lock(
+                &blk2,
+                LockMode::Shared,
+                2,
+                DeadlockPolicy::WoundWait,
+                TIMEOUT
+            ),
+            Err(DbError::DeadlockAborted(2))
+        ));
+
+        lt.release_all(2);
+        handler.join().unwrap().unwrap();
+
+        // Once released the wounded transaction number does not carry the mark anymore
+        lt.lock(
+            &blk2,
+            LockMode::Shared,
+            2,
+            DeadlockPolicy::WoundWait,
+            TIMEOUT,
+        )
+        .unwrap();
+        lt.release_all(1);
+        lt.release_all(2);
+    }
+
+    #[test]
+    fn fifotest() {
+        let lt = Arc::new(LockTable::new());
+        let blk = LockTarget::Block(Block::new("fifo".to_string(), 1));
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        lt.lock(
+            &blk,
+            LockMode::Exclusive,
+            1,
+            DeadlockPolicy::Timeout,
+            TIMEOUT,
+        )
+        .unwrap();
+
+        // Waiters queue up one after another, every one of them wants the lock exclusively
+        let handlers = (2..=4)
+            .map(|txnum| {
+                let lt = lt.clone();
+                let blk = blk.clone();
+                let order = order.clone();
+                let handler = thread::spawn(move || {
+                    lt.lock(
+                        &blk,
+                        LockMode::Exclusive,
+                        txnum,
+                        DeadlockPolicy::Timeout,
+                        TIMEOUT,
+                    )
+                    .unwrap();
+                    order.lock().unwrap().push(txnum);
+                    thread::sleep(Duration::from_millis(10));
+                    lt.release_all(txnum);
+                });
+                thread::sleep(Duration::from_millis(30));
+                handler
+            })
+            .collect::<Vec<_>>();
+
+        lt.release_all(1);
+        for handler in handlers {
+            handler.join().unwrap();
+        }
+
+        assert_eq!(*order.lock().unwrap(), vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn fifosharedtest() {
+        let lt = Arc::new(LockTable::new());
+        let blk = LockTarget::Block(Block::new("fifoshared".to_string(), 1));
+
+        lt.lock(&blk, LockMode::Shared, 1, DeadlockPolicy::Timeout, TIMEOUT)
+            .unwrap();
+
+        let lt_writer = lt.clone();
+        let blk_writer = blk.clone();
+        let writer = thread::spawn(move || {
+            lt_writer.lock(
+                &blk_writer,
+                LockMode::Exclusive,
+                2,
+                DeadlockPolicy::Timeout,
+                TIMEOUT,
+            )
+        });
+        while !lt.is_waiting(2) {
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        // A new reader gets in line behind the writer instead of sharing the lock right away
+        assert!(matches!(
+            lt.lock(
+                &blk,
+                LockMode::Shared,
+                3,
+                DeadlockPolicy::Timeout,
+                Duration::from_millis(50)
+            ),
+            Err(DbError::LockAborted)
+        ));
+        assert_eq!(lt.mode(&blk, 3), None);
+
+        lt.release_all(1);
+        writer.join().unwrap().unwrap();
+        assert_eq!(lt.mode(&blk, 2), Some(LockMode::Exclusive));
+        lt.release_all(2);
+        lt.release_all(3);
+    }
+
+    #[test]
+    fn locktimeouttest() {
+        let lt = LockTable::new();
+        let blk = LockTarget::Block(Block::new("locktimeout".to_string(), 1));
+
+        lt.lock(&blk, LockMode::Shared, 1, DeadlockPolicy::Timeout, TIMEOUT)
+            .unwrap();
+
+        let time = Instant::now();
+        assert!(matches!(
+            lt.lock(
+                &blk,
+                LockMode::Exclusive,
+                2,
+                DeadlockPolicy::Timeout,
+                Duration::from_millis(50)
+            ),
+            Err(DbError::LockAborted)
+        ));
+        assert!(time.elapsed() >= Duration::from_millis(50));
+        assert!(time.elapsed() < TIMEOUT);
+
+        // Other blocks can be locked and unlocked while a transaction is waiting
+        let lt = Arc::new(lt);
+        let lt_waiting = lt.clone();
+        let blk_waiting = blk.clone();
+        let handler = thread::spawn(move || {
+            lt_waiting.lock(
+                &blk_waiting,
+                LockMode::Exclusive,
+                2,
+                DeadlockPolicy::Timeout,
+                TIMEOUT,
+            )
+        });
+
+        thread::sleep(Duration::from_millis(20));
+        let other = LockTarget::Block(Block::new("locktimeout".to_string(), 2));
+        lt.lock(
+            &other,
+            LockMode::Exclusive,
+            3,
+            DeadlockPolicy::Timeout,
+            Duration::from_millis(50),
+        )
+        .unwrap();
+        lt.release_all(3);
+        assert!(!handler.is_finished());
+
+        lt.release_all(1);
+        handler.join().unwrap().unwrap();
+        lt.release_all(2);
+
+        // An aborted transaction is refused locks till it released the ones it holds
+        lt.abort(4);
+        assert!(matches!(
+            lt.lock(&blk, LockMode::Shared, 4, DeadlockPolicy::Timeout, TIMEOUT),
+            Err(DbError::TransactionAborted(4))
+        ));
+        lt.release_all(4);
+        lt.lock(&blk, LockMode::Shared, 4, DeadlockPolicy::Timeout, TIMEOUT)
+            .unwrap();
+        lt.release_all(4);
+    }
+
+    #[test]
+    fn lockmodetest() {
+        use LockMode::*;
+
+        let modes = [
+            IntentionShared,
+            IntentionExclusive,
+            Shared,
+            SharedIntentionExclusive,
+            Exclusive,
+        ];
+        let compatible = [
+            [true, true, true, true, false],
+            [true, true, false, false, false],
+            [true, false, true, false, false],
+            [true, false, false, false, false],
+            [false, false, false, false, false],
+        ];
+
+        for (i, a) in modes.iter().enumerate() {
+            for (j, b) in modes.iter().enumerate() {
+                assert_eq!(a.is_compatible(*b), compatible[i][j], "{a} {b}");
+                assert_eq!(a.join(*b), b.join(*a));
+                assert!(a.join(*b).covers(*a) && a.join(*b).covers(*b));
+            }
+        }
+
+        assert_eq!(IntentionExclusive.join(Shared), SharedIntentionExclusive);
+        assert_eq!(IntentionShared.join(Shared), Shared);
+        assert!(!Shared.covers(IntentionExclusive));
+
+        // An upgrade to a mode incompatible with other holders has to wait for them
+        let lt = LockTable::new();
+        let file = LockTarget::File("lockmode".to_string());
+        lt.lock(&file, IntentionShared, 1, DeadlockPolicy::Timeout, TIMEOUT)
+            .unwrap();
+        lt.lock(
+            &file,
+            IntentionExclusive,
+            2,
+            DeadlockPolicy::Timeout,
+            TIMEOUT,
+        )
+        .unwrap();
+        lt.lock(&file, Shared, 1, DeadlockPolicy::Timeout, Duration::ZERO)
+            .unwrap_err();
+        lt.release_all(2);
+        lt.lock(&file, Shared, 1, DeadlockPolicy::Timeout, Duration::ZERO)
+            .unwrap();
+        assert_eq!(lt.mode(&file, 1), Some(Shared));
+        lt.release_all(1);
+        assert_eq!(lt.mode(&file, 1), None);
+    }
+}
@@ -0,0 +1,226 @@
+use super::{
+    locks::{latch::LatchTable, lock_table::LockTable},
+    mvcc::TxStatusTable,
+    recovery::recovery_mgr::RecoveryManager,
+    txid::TxIdGenerator,
+    IsolationLevel, LockMode, LockTarget, Transactions,
+};
+use crate::{
+    error::{DbError, DbResult},
+    storage::bufferpool::pool::BufferPoolManager,
+    storage::disk::block::Block,
+    storage::log::manager::LogManager,
+    utils::safe_lock::SafeLock,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, RwLock},
+    time::SystemTime,
+};
+
+/// What a running transaction is busy with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TxActivity {
+    Running,
+    /// Waiting for a lock held by another transaction
+    WaitingForLock,
+    /// Aborted through `TxManager::abort`, it's rolled back once the call its owner is in the
+    /// middle of ends
+    Aborted,
+}
+
+/// Description of a running transaction, see `TxManager::transactions`
+#[derive(Clone, Debug)]
+pub struct TxInfo {
+    pub txnum: u64,
+    pub started: SystemTime,
+    pub activity: TxActivity,
+    pub read_only: bool,
+    pub isolation_level: IsolationLevel,
+    /// Global id of the transaction if it's prepared
+    pub gid: Option<String>,
+    pub locks: Vec<(LockTarget, LockMode)>,
+    pub pinned: Vec<Block>,
+}
+
+struct Registered {
+    started: SystemTime,
+    tx: Transactions,
+}
+
+/// State shared by all the transactions of a server: the locks and latches they hold, which
+/// of them are running and the numbers they are given.
+///
+/// Every server has its own, so the transactions of two servers running in the same process
+/// never see each other even when their files have the same names.
+pub struct TxManager {
+    pub(super) lock_table: LockTable,
+    pub(super) latches: Arc<LatchTable>,
+    pub(super) status: TxStatusTable,
+    ids: TxIdGenerator,
+    /// Transactions which did not commit or roll back yet
+    registry: Mutex<HashMap<u64, Registered>>,
+    /// Prepared transactions by their global id, they wait for the coordinator
+    prepared: Mutex<HashMap<String, Transactions>>,
+    /// Held shared by a transaction while it logs its start and registers, exclusively while
+    /// a checkpoint lists the running transactions. A transaction which logged anything before
+    /// the checkpoint is either listed or finished
+    pub(super) checkpoint_gate: RwLock<()>,
+}
+
+impl TxManager {
+    pub fn new(ids: TxIdGenerator) -> Self {
+        Self {
+            lock_table: LockTable::new(),
+            latches: Arc::new(LatchTable::new()),
+            status: TxStatusTable::new(),
+            ids,
+            registry: Mutex::new(HashMap::new()),
+            prepared: Mutex::new(HashMap::new()),
+            checkpoint_gate: RwLock::new(()),
+        }
+    }
+
+    /// Creates the state for a server whose log might already hold transactions, the numbers
+    /// handed out continue after them
+    pub fn recover(lm: Arc<Mutex<LogManager>>) -> Self {
+        Self::new(TxIdGenerator::recover(lm))
+    }
+
+    /// Number for a new transaction, unique even across restarts of the server
+    pub fn next_txnum(&self) -> u64 {
+        self.ids.next()
+    }
+
+    /// Lists the running transactions, oldest first
+    pub fn transactions(&self) -> Vec<TxInfo> {
+        let registry = self.registry.safe_lock();
+
+        let mut infos = registry
+            .values()
+            .map(|Registered { started, tx }| TxInfo {
+                txnum: tx.txnum,
+                started: *started,
+                activity: if tx.concurrency.is_aborted() {
+                    TxActivity::Aborted
+                } else if tx.concurrency.is_waiting() {
+                    TxActivity::WaitingForLock
+                } else {
+                    TxActivity::Running
+                },
+                read_only: tx.read_only,
+                isolation_level: tx.isolation_level(),
+                gid: tx.gid().map(str::to_string),
+                locks: tx.concurrency.held(),
+                pinned: tx.buffer.safe_lock().pinned(),
+            })
+            .collect::<Vec<_>>();
+
+        infos.sort_by_key(|info| info.txnum);
+        infos
+    }
+
+    /// Aborts a running transaction, e.g one which runs for too long. Its changes are rolled
+    /// back and its locks released right away, whoever runs the transaction gets the
+    /// `TransactionAborted` error from then on. A call the owner is in the middle of is waited
+    /// for, one waiting for a lock is cut short
+    ///
+    /// Prepared transactions can't be aborted, only the coordinator decides their outcome
+    pub fn abort(&self, txnum: u64) -> DbResult<()> {
+        let registered = self
+            .registry
+            .safe_lock()
+            .get(&txnum)
+            .map(|registered| registered.tx.clone());
+        let Some(mut tx) = registered else {
+            return Err(DbError::TransactionNotFound(txnum));
+        };
+
+        tx.abort()
+    }
+
+    /// Logs a checkpoint without waiting for the running transactions and returns its LSN,
+    /// see `RecoveryManager::checkpoint`
+    pub fn checkpoint(
+        &self,
+        lm: Arc<Mutex<LogManager>>,
+        bm: &Mutex<BufferPoolManager>,
+    ) -> DbResult<u64> {
+        Ok(RecoveryManager::checkpoint(lm, bm, self)?)
+    }
+
+    /// Global ids of the prepared transactions waiting for their outcome, including the ones
+    /// recovery found after a crash
+    pub fn prepared(&self) -> Vec<String> {
+        let mut gids = self
+            .prepared
+            .safe_lock()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        gids.sort();
+        gids
+    }
+
+    /// Second phase of a distributed commit, commits the transaction prepared as `gid`
+    pub fn commit_prepared(&self, gid: &str) -> DbResult<()> {
+        self.take_prepared(gid)?.commit()
+    }
+
+    /// Second phase of a distributed commit, rolls back the transaction prepared as `gid`
+    pub fn rollback_prepared(&self, gid: &str) -> DbResult<()> {
+        self.take_prepared(gid)?.rollback()
+    }
+
+    fn take_prepared(&self, gid: &str) -> DbResult<Transactions> {
+        self.prepared
+            .safe_lock()
+            .remove(gid)
+            .ok_or_else(|| DbError::PreparedTransactionNotFound(gid.to_string()))
+    }
+
+    pub(super) fn prepare(&self, gid: &str, tx: Transactions) -> DbResult<()> {
+        let mut prepared = self.prepared.safe_lock();
+        if prepared.contains_key(gid) {
+            return Err(DbError::DuplicateGid(gid.to_string()));
+        }
+
+        prepared.insert(gid.to_string(), tx);
+        Ok(())
+    }
+
+    pub(super) fn forget_prepared(&self, gid: &str) {
+        self.prepared.safe_lock().remove(gid);
+    }
+
+    /// Numbers of the running transactions which write to the log
+    pub(super) fn running(&self) -> Vec<i64> {
+        let mut txnums = self
+            .registry
+            .safe_lock()
+            .values()
+            .filter(|registered| !registered.tx.read_only)
+            .map(|registered| registered.tx.txnum as i64)
+            .collect::<Vec<_>>();
+        txnums.sort();
+        txnums
+    }
+
+    pub(super) fn is_registered(&self, txnum: u64) -> bool {
+        self.registry.safe_lock().contains_key(&txnum)
+    }
+
+    pub(super) fn register(&self, tx: Transactions) {
+        self.registry.safe_lock().insert(
+            tx.txnum,
+            Registered {
+                started: SystemTime::now(),
+                tx,
+            },
+        );
+    }
+
+    pub(super) fn unregister(&self, txnum: u64) {
+        self.registry.safe_lock().remove(&txnum);
+    }
+}
@@ -1,110 +1,652 @@
+mod activity;
 mod bufferlist;
+mod checkpointer;
 mod concurrency;
+mod guard;
 mod locks;
+mod manager;
+mod mvcc;
 mod recovery;
 mod test;
+mod txid;
+
+pub use checkpointer::Checkpointer;
+pub use concurrency::IsolationLevel;
+pub use guard::{TxGuard, DEFAULT_TRANSACTION_RETRIES, RETRY_BACKOFF_MS};
+pub use locks::{lock_mode::LockMode, lock_table::DeadlockPolicy, lock_target::LockTarget};
+pub use manager::{TxActivity, TxInfo, TxManager};
+pub use mvcc::NO_TX;
+pub(crate) use recovery::log_record::{create_log_record, LogOperation};
+pub use recovery::row::{RowChange, RowOp};
+pub use txid::TxIdGenerator;
 
 use crate::{
+    common::slot::Slot,
     error::{DbError, DbResult},
-    storage::bufferpool::pool::BufferPoolManager,
-    storage::disk::{block::Block, manager::Manager},
+    storage::bufferpool::{buffer::Buffer, pool::BufferPoolManager},
+    storage::disk::{block::Block, manager::Manager, page::Page},
     storage::log::manager::LogManager,
-    storage::tx::recovery::recovery_mgr::RecoveryManager,
+    storage::record::{
+        overflow::{self, OverflowRef, PendingChains},
+        rowid::RowId,
+        slotted_page::SlottedPage,
+    },
+    storage::tx::activity::{Activity, Call},
+    storage::tx::locks::latch::Latch,
+    storage::tx::mvcc::Snapshot,
+    storage::tx::recovery::{
+        overflow::OverflowRecord,
+        recovery_mgr::{InDoubt, RecoveryManager},
+    },
     utils::safe_lock::SafeLock,
 };
-use std::sync::{atomic::AtomicU32, Arc, Mutex};
+use std::{
+    sync::{Arc, Mutex, OnceLock, PoisonError},
+    time::Duration,
+};
 
 static END_OF_FILE: u64 = u64::MAX;
 
-static NEXT_TRANSACTION_ID: AtomicU32 = AtomicU32::new(0);
-
-fn next_transaction_id() -> u32 {
-    NEXT_TRANSACTION_ID.fetch_add(1, std::sync::atomic::Ordering::SeqCst)
-}
-
 #[derive(Clone)]
 pub struct Transactions {
     file_mgr: Arc<Manager>,
     concurrency: concurrency::ConcurrencyManager,
+    manager: Arc<TxManager>,
     bm: Arc<Mutex<BufferPoolManager>>,
-    txnum: u32,
+    txnum: u64,
     buffer: Arc<Mutex<bufferlist::BufferList>>,
     recovery_mgr: RecoveryManager,
+    /// Set for transactions reading under snapshot isolation
+    snapshot: Arc<OnceLock<Snapshot>>,
+    read_only: bool,
+    /// Global id the transaction was prepared with, see `prepare`
+    prepared: Arc<OnceLock<String>>,
+    /// Overflow chains to free once the transaction finishes
+    chains: Arc<Mutex<PendingChains>>,
+    /// Calls of the owner in progress, see `TxManager::abort`
+    activity: Arc<Activity>,
+    /// Set on the clone the recovery manager undoes the changes through, which has to keep
+    /// working once the transaction is aborted
+    undoing: bool,
+    /// Parent of the spans of everything the transaction does
+    span: tracing::Span,
 }
 
 impl Transactions {
+    /// Starts a transaction of the server the `manager` belongs to, it gets locks from the
+    /// lock table of that server only
     pub fn new(
         fm: Arc<Manager>,
         bm: Arc<Mutex<BufferPoolManager>>,
         lm: Arc<Mutex<LogManager>>,
+        manager: Arc<TxManager>,
+        deadlock_policy: DeadlockPolicy,
+    ) -> DbResult<Self> {
+        Self::create(fm, bm, lm, manager, deadlock_policy, false)
+    }
+
+    /// Starts a transaction which only reads. It writes nothing to the log and reads from a
+    /// snapshot taken right away, so it takes no read locks either. Any attempt to change the
+    /// database fails with `DbError::ReadOnlyTransaction`
+    pub fn new_read_only(
+        fm: Arc<Manager>,
+        bm: Arc<Mutex<BufferPoolManager>>,
+        lm: Arc<Mutex<LogManager>>,
+        manager: Arc<TxManager>,
+        deadlock_policy: DeadlockPolicy,
     ) -> DbResult<Self> {
-        let txnum = next_transaction_id();
+        let mut tx = Self::create(fm, bm, lm, manager, deadlock_policy, true)?;
+        tx.begin_snapshot();
+        Ok(tx)
+    }
+
+    fn create(
+        fm: Arc<Manager>,
+        bm: Arc<Mutex<BufferPoolManager>>,
+        lm: Arc<Mutex<LogManager>>,
+        manager: Arc<TxManager>,
+        deadlock_policy: DeadlockPolicy,
+        read_only: bool,
+    ) -> DbResult<Self> {
+        let txnum = manager.next_txnum();
+        // A checkpoint sees the transaction once its start is logged
+        let _gate = manager
+            .checkpoint_gate
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
 
         let mut txn = Self {
             file_mgr: fm.clone(),
-            concurrency: concurrency::ConcurrencyManager::new(),
+            concurrency: concurrency::ConcurrencyManager::new(
+                txnum,
+                deadlock_policy,
+                manager.clone(),
+            ),
+            manager: manager.clone(),
             bm: bm.clone(),
             txnum,
-            recovery_mgr: RecoveryManager::new(txnum as i32, lm.clone(), bm.clone())?,
+            recovery_mgr: RecoveryManager::new(txnum as i64, lm.clone(), bm.clone(), read_only)?,
             buffer: Arc::new(Mutex::new(bufferlist::BufferList::new(bm.clone()))),
+            snapshot: Arc::new(OnceLock::new()),
+            read_only,
+            prepared: Arc::new(OnceLock::new()),
+            chains: Arc::new(Mutex::new(PendingChains::default())),
+            activity: Arc::new(Activity::default()),
+            undoing: false,
+            span: tracing::debug_span!("transaction", txnum, read_only),
         };
 
+        manager.status.begin(txnum);
+        tracing::debug!(parent: &txn.span, "transaction started");
         txn.init();
+        manager.register(txn.clone());
         Ok(txn)
     }
 
+    /// Brings back a transaction recovery found prepared, it takes the locks on the blocks the
+    /// transaction changed again and waits to be resolved through the manager
+    fn resume(&self, in_doubt: InDoubt) -> DbResult<()> {
+        let InDoubt { txnum, gid, blocks } = in_doubt;
+
+        let mut txn = Self {
+            file_mgr: self.file_mgr.clone(),
+            concurrency: concurrency::ConcurrencyManager::new(
+                txnum,
+                self.concurrency.policy(),
+                self.manager.clone(),
+            ),
+            manager: self.manager.clone(),
+            bm: self.bm.clone(),
+            txnum,
+            recovery_mgr: self.recovery_mgr.resume(txnum as i64),
+            buffer: Arc::new(Mutex::new(bufferlist::BufferList::new(self.bm.clone()))),
+            snapshot: Arc::new(OnceLock::new()),
+            read_only: false,
+            prepared: Arc::new(OnceLock::new()),
+            chains: Arc::new(Mutex::new(PendingChains::default())),
+            activity: Arc::new(Activity::default()),
+            undoing: false,
+            span: tracing::debug_span!("transaction", txnum, gid),
+        };
+
+        self.manager.status.begin(txnum);
+        tracing::info!(parent: &txn.span, "resumed prepared transaction");
+        txn.init();
+        for block in &blocks {
+            txn.concurrency.xlock(block)?;
+        }
+        txn.prepared.get_or_init(|| gid.clone());
+        txn.activity.prepare();
+
+        self.manager.register(txn.clone());
+        self.manager.prepare(&gid, txn)
+    }
+
     pub fn init(&mut self) {
-        let txn = Arc::new(Mutex::new(self.clone()));
+        let txn = Arc::new(Mutex::new(Self {
+            undoing: true,
+            ..self.clone()
+        }));
 
         let recovery_mgr = self.recovery_mgr.clone();
 
         self.recovery_mgr = recovery_mgr.clone().init_transaction(txn.clone());
     }
 
-    pub fn txnum(&self) -> u32 {
+    pub fn txnum(&self) -> u64 {
         self.txnum
     }
 
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Starts a call of the owner, it fails once the transaction was aborted by someone else.
+    /// `TxManager::abort` waits for the calls in progress before it rolls the transaction back,
+    /// so the call holds on to the returned guard till it's done
+    fn enter(&self) -> DbResult<Call> {
+        if self.undoing {
+            return Ok(Call::untracked());
+        }
+        self.activity
+            .enter()
+            .ok_or(DbError::TransactionAborted(self.txnum))
+    }
+
+    /// Fails once the transaction is prepared, all it can do then is commit or roll back
+    fn check_unprepared(&self) -> DbResult<()> {
+        if !self.undoing && self.prepared.get().is_some() {
+            return Err(DbError::TransactionPrepared(self.txnum));
+        }
+        Ok(())
+    }
+
+    /// Starts a call like `enter` which fails for read only transactions, made before anything
+    /// gets changed or locked for writing
+    fn enter_writable(&self) -> DbResult<Call> {
+        let call = self.enter()?;
+        self.check_unprepared()?;
+        if self.read_only {
+            return Err(DbError::ReadOnlyTransaction(self.txnum));
+        }
+        Ok(call)
+    }
+
+    /// Switches the transaction to snapshot isolation. From now on it reads the records as
+    /// they were committed at this point without taking any locks, writes still lock the
+    /// records and fail with `WriteConflict` if a record was changed after the snapshot.
+    ///
+    /// This should be called before the transaction reads anything, calling it again keeps
+    /// the first snapshot
+    pub fn begin_snapshot(&mut self) {
+        self.snapshot
+            .get_or_init(|| self.manager.status.snapshot(self.txnum));
+    }
+
+    pub fn has_snapshot(&self) -> bool {
+        self.snapshot.get().is_some()
+    }
+
+    /// Whether the version of a record created by `xmin` and deleted by `xmax` is visible to
+    /// the transaction. Transactions without a snapshot see the latest committed version, or
+    /// the newest one when reading uncommitted data
+    pub fn is_visible(&self, xmin: i64, xmax: i64) -> bool {
+        let view = match self.snapshot.get() {
+            Some(snapshot) => *snapshot,
+            None if self.isolation_level() == IsolationLevel::ReadUncommitted => {
+                Snapshot::uncommitted(self.txnum)
+            }
+            None => Snapshot::latest(self.txnum),
+        };
+        self.is_visible_to(&view, xmin, xmax)
+    }
+
+    /// Whether the version is the latest committed one, or one written by the transaction
+    /// itself. Only such a version can be changed
+    pub fn is_current(&self, xmin: i64, xmax: i64) -> bool {
+        self.is_visible_to(&Snapshot::latest(self.txnum), xmin, xmax)
+    }
+
+    /// Whether a version deleted by `xmax` is invisible to every transaction, so its slot can
+    /// be reused
+    pub fn is_dead(&self, xmax: i64) -> bool {
+        xmax != NO_TX && self.manager.status.is_dead(xmax as u64)
+    }
+
+    fn is_visible_to(&self, snapshot: &Snapshot, xmin: i64, xmax: i64) -> bool {
+        let status = &self.manager.status;
+        let sees = |txnum: i64| txnum != NO_TX && status.sees(snapshot, txnum as u64);
+        sees(xmin) && !sees(xmax)
+    }
+
+    /// Time the transaction waits for a lock held by another transaction before it's aborted
+    /// with `LockAborted`
+    pub fn lock_timeout(&self) -> Duration {
+        self.concurrency.lock_timeout()
+    }
+
+    pub fn set_lock_timeout(&mut self, timeout: Duration) {
+        self.concurrency.set_lock_timeout(timeout);
+    }
+
+    /// Makes the deadlock policy treat the transaction as if it started along with transaction
+    /// `age`, a retried transaction passes the number of its first attempt so it doesn't lose
+    /// its priority to the transactions started since
+    pub fn set_age(&mut self, age: u64) {
+        self.concurrency.set_age(age);
+    }
+
+    pub fn isolation_level(&self) -> IsolationLevel {
+        self.concurrency.isolation_level()
+    }
+
+    /// Sets how long the transaction holds the locks it reads under, transactions reading
+    /// under a snapshot take no read locks at any level
+    pub fn set_isolation_level(&mut self, level: IsolationLevel) {
+        self.concurrency.set_isolation_level(level);
+    }
+
+    /// Number of block locks the transaction can hold on a single file before it tries to
+    /// lock the whole file instead
+    pub fn set_lock_escalation_threshold(&mut self, threshold: usize) {
+        self.concurrency.set_escalation_threshold(threshold);
+    }
+
+    /// Locks a resource explicitly, e.g a whole file before changing its definition. The
+    /// intention locks on the ancestors of the resource are taken as well
+    pub fn lock(&mut self, target: &LockTarget, mode: LockMode) -> DbResult<()> {
+        let _call = match mode.is_write() {
+            true => self.enter_writable()?,
+            false => self.enter()?,
+        };
+        self.concurrency.lock(target, mode)
+    }
+
+    /// Mode the transaction explicitly holds the lock on the resource in
+    pub fn lock_mode(&self, target: &LockTarget) -> Option<LockMode> {
+        self.concurrency.mode(target)
+    }
+
+    #[tracing::instrument(level = "trace", parent = &self.span, skip_all)]
     pub fn pin(&mut self, block: &Block) -> DbResult<()> {
+        let _call = self.enter()?;
         self.buffer.safe_lock().pin(block)
     }
 
+    /// Unpins the block. `TxManager::abort` gives back every buffer the transaction pinned
+    /// when it rolls the transaction back, unpinning does nothing once it was aborted
     pub fn unpin(&mut self, block: &Block) -> DbResult<()> {
+        if !self.undoing && self.activity.is_aborted() {
+            return Ok(());
+        }
         self.buffer.safe_lock().unpin(block)
     }
 
+    /// Commits the changes of the transaction, it fails with `TransactionAborted` once the
+    /// transaction was rolled back by `TxManager::abort`
+    #[tracing::instrument(level = "debug", parent = &self.span, skip_all)]
     pub fn commit(&mut self) -> DbResult<()> {
+        let _call = self.enter()?;
         self.recovery_mgr.commit()?;
+        self.free_chains(true)?;
+        self.manager.status.commit(self.txnum);
 
-        println!("transaction {} committed", self.txnum);
+        tracing::debug!("transaction committed");
         self.concurrency.release()?;
 
         self.buffer.safe_lock().unpin_all()?;
+        self.finish();
+
+        Ok(())
+    }
 
+    /// First phase of a distributed commit. A prepare record is forced to the log, so the
+    /// transaction can be committed or rolled back even after a crash. Its
+    /// locks are held till then and it can't change anything anymore.
+    ///
+    /// The transaction is known to the server by `gid` from now on, the coordinator resolves it
+    /// through `TxManager::commit_prepared` or `TxManager::rollback_prepared`
+    #[tracing::instrument(level = "debug", parent = &self.span, skip(self))]
+    pub fn prepare(&mut self, gid: &str) -> DbResult<()> {
+        let _call = self.enter()?;
+        self.check_unprepared()?;
+        if !self.activity.prepare() {
+            return Err(DbError::TransactionAborted(self.txnum));
+        }
+
+        if let Err(e) = self.manager.prepare(gid, self.clone()) {
+            self.activity.unprepare();
+            return Err(e);
+        }
+        if let Err(e) = self.recovery_mgr.prepare(gid) {
+            self.manager.forget_prepared(gid);
+            self.activity.unprepare();
+            return Err(e);
+        }
+        self.prepared.get_or_init(|| gid.to_string());
+
+        tracing::debug!("transaction prepared");
+        Ok(())
+    }
+
+    /// Whether the transaction has not committed or rolled back yet, an aborted transaction
+    /// stops running once `TxManager::abort` rolled it back
+    pub fn is_running(&self) -> bool {
+        self.manager.is_registered(self.txnum)
+    }
+
+    /// Global id of the transaction, if it was prepared
+    pub fn gid(&self) -> Option<&str> {
+        self.prepared.get().map(String::as_str)
+    }
+
+    /// Records that the transaction wrote a new overflow chain to `file`
+    pub(crate) fn chain_written(&self, file: &str, reference: OverflowRef) {
+        self.chains.safe_lock().written(file, reference);
+    }
+
+    /// Records that a record of the transaction stopped referring to the overflow chain, see
+    /// `PendingChains::replaced`
+    pub(crate) fn chain_replaced(&self, file: &str, reference: OverflowRef) {
+        self.chains.safe_lock().replaced(file, reference);
+    }
+
+    /// Records that the overflow chain belonged to a version vacuum threw away, it's freed
+    /// once the transaction commits
+    pub(crate) fn chain_released(&self, file: &str, reference: OverflowRef) {
+        self.chains.safe_lock().released(file, reference);
+    }
+
+    /// Frees the overflow chains nothing refers to now that the transaction committed or rolled
+    /// back. The commit or rollback is already logged, so a crash before the chains are freed
+    /// only leaves their blocks unused
+    fn free_chains(&mut self, committed: bool) -> DbResult<()> {
+        let chains = self.chains.safe_lock().take(committed);
+        if chains.is_empty() {
+            return Ok(());
+        }
+
+        // The transaction is done, it might have been aborted or prepared before
+        let mut tx = Self {
+            undoing: true,
+            ..self.clone()
+        };
+        for (file, reference) in chains {
+            overflow::free_chain(&mut tx, &file, reference)?;
+        }
         Ok(())
     }
 
+    /// Forgets the transaction once it committed or rolled back
+    fn finish(&self) {
+        if let Some(gid) = self.prepared.get() {
+            self.manager.forget_prepared(gid);
+        }
+        self.manager.unregister(self.txnum);
+    }
+
+    /// Undoes the changes of the transaction. A transaction which was aborted is rolled back
+    /// by `TxManager::abort`, this only waits for it to finish
+    #[tracing::instrument(level = "debug", parent = &self.span, skip_all)]
     pub fn rollback(&mut self) -> DbResult<()> {
+        let Ok(_call) = self.enter() else {
+            self.activity.wait_rolled_back();
+            return Ok(());
+        };
+        self.roll_back()
+    }
+
+    /// Rolls the transaction back on behalf of another thread, see `TxManager::abort`. The
+    /// calls of the owner in progress are waited for, a wait for a lock is cut short, and every
+    /// call the owner makes afterwards fails with `TransactionAborted`
+    #[tracing::instrument(level = "debug", parent = &self.span, skip_all)]
+    pub(super) fn abort(&mut self) -> DbResult<()> {
+        if !self.activity.abort(self.txnum)? {
+            return Ok(());
+        }
+        self.concurrency.abort();
+        self.activity.wait_idle();
+
+        // The owner might have finished the transaction meanwhile
+        let result = match self.is_running() {
+            true => self.roll_back(),
+            false => Err(DbError::TransactionNotFound(self.txnum)),
+        };
+        self.activity.rolled_back();
+        tracing::info!("transaction aborted");
+        result
+    }
+
+    /// Undoes the changes of the transaction, then releases its locks and buffers
+    fn roll_back(&mut self) -> DbResult<()> {
         self.recovery_mgr.rollback()?;
+        self.free_chains(false)?;
+        self.manager.status.rollback(self.txnum);
 
-        println!("transaction {} rolled back", self.txnum);
+        tracing::debug!("transaction rolled back");
         self.concurrency.release()?;
 
         self.buffer.safe_lock().unpin_all()?;
+        self.finish();
 
         Ok(())
     }
 
+    /// Marks the point the transaction can later roll back to with `rollback_to`
+    pub fn savepoint(&mut self, name: &str) -> DbResult<()> {
+        let _call = self.enter()?;
+        self.check_unprepared()?;
+        self.recovery_mgr.savepoint(name)
+    }
+
+    /// Undoes the changes made since the savepoint, the locks taken since are kept till the
+    /// transaction finishes
+    pub fn rollback_to(&mut self, name: &str) -> DbResult<()> {
+        let _call = self.enter()?;
+        self.check_unprepared()?;
+        self.recovery_mgr.rollback_to(name)
+    }
+
+    /// Forgets the savepoint without undoing anything
+    pub fn release(&mut self, name: &str) -> DbResult<()> {
+        let _call = self.enter()?;
+        self.check_unprepared()?;
+        self.recovery_mgr.release(name)
+    }
+
+    /// Releases the locks of a transaction which never committed or rolled back and forgets
+    /// about it, used by recovery before undoing the changes of such a transaction
+    pub(crate) fn forget_orphaned(&self, txnum: u64) {
+        if txnum != self.txnum {
+            self.concurrency.release_orphaned(txnum);
+            self.manager.status.rollback(txnum);
+        }
+    }
+
+    /// Redoes the logged changes which never reached the disk and undoes the transactions
+    /// which did not finish before a crash. The prepared ones are kept holding their locks, they can be listed with `TxManager::prepared`
     pub fn recover(&mut self) -> DbResult<()> {
-        self.bm.safe_lock().flush_all(self.txnum as i32)?;
-        self.recovery_mgr.recover()?;
+        self.recover_from(None)
+    }
+
+    /// Like `recover` but for a restored backup, which has to be recovered from the checkpoint
+    /// taken when the backup started
+    pub fn recover_backup(&mut self, start_lsn: u64) -> DbResult<()> {
+        self.recover_from(Some(start_lsn))
+    }
+
+    #[tracing::instrument(level = "info", name = "recover", parent = &self.span, skip(self))]
+    fn recover_from(&mut self, last_checkpoint: Option<u64>) -> DbResult<()> {
+        self.bm.safe_lock().flush_all(self.txnum as i64)?;
+        let in_doubt = self.recovery_mgr.recover(last_checkpoint)?;
+
+        // Everything redone and undone is already flushed, the locks taken
+        // while undoing are not needed anymore
+        self.manager.status.rollback(self.txnum);
+        self.concurrency.release()?;
+        self.manager.unregister(self.txnum);
+
+        for prepared in in_doubt {
+            self.resume(prepared)?;
+        }
 
         Ok(())
     }
 
     pub fn get_int(&mut self, block: &Block, offset: u32) -> DbResult<i32> {
-        self.concurrency.slock(block)?;
+        self.read_locked(&LockTarget::Block(block.clone()), |tx| {
+            tx.get_int_latched(block, offset)
+        })
+    }
+
+    pub fn get_string(&mut self, block: &Block, offset: u32) -> DbResult<String> {
+        self.read_locked(&LockTarget::Block(block.clone()), |tx| {
+            tx.get_string_latched(block, offset)
+        })
+    }
+
+    pub fn set_string(
+        &mut self,
+        block: &Block,
+        offset: u32,
+        val: String,
+        ok_to_log: bool,
+    ) -> DbResult<()> {
+        let _call = self.enter_writable()?;
+        self.concurrency.xlock(block)?;
+        self.set_string_latched(block, offset, val, ok_to_log)
+    }
+
+    pub fn set_int(
+        &mut self,
+        block: &Block,
+        offset: u32,
+        val: i32,
+        ok_to_log: bool,
+    ) -> DbResult<()> {
+        let _call = self.enter_writable()?;
+        self.concurrency.xlock(block)?;
+        self.set_int_latched(block, offset, val, ok_to_log)
+    }
+
+    pub fn set_long(
+        &mut self,
+        block: &Block,
+        offset: u32,
+        val: i64,
+        ok_to_log: bool,
+    ) -> DbResult<()> {
+        let _call = self.enter_writable()?;
+        self.concurrency.xlock(block)?;
+        self.set_long_latched(block, offset, val, ok_to_log)
+    }
+
+    /// Locks a single record of the block, the block itself only gets an intention lock so
+    /// other transactions can keep working on the other records of the block
+    pub fn lock_record(&mut self, block: &Block, slot: Slot, mode: LockMode) -> DbResult<()> {
+        self.lock(&Self::record(block, slot), mode)
+    }
+
+    /// Reads from a record holding the lock the isolation level of the transaction asks for
+    pub fn read_record<T>(
+        &mut self,
+        block: &Block,
+        slot: Slot,
+        f: impl FnOnce(&mut Self) -> DbResult<T>,
+    ) -> DbResult<T> {
+        self.read_locked(&Self::record(block, slot), f)
+    }
+
+    /// Locks a block about to be scanned. Only serializable transactions lock the whole block,
+    /// keeping others from inserting records into the part of the table they have seen
+    pub fn lock_scan(&mut self, block: &Block) -> DbResult<()> {
+        let _call = self.enter()?;
+        if self.isolation_level() == IsolationLevel::Serializable && !self.has_snapshot() {
+            self.concurrency
+                .lock(&LockTarget::Block(block.clone()), LockMode::Shared)?;
+        }
+        Ok(())
+    }
+
+    /// Like `lock_record` but returns false instead of waiting if another transaction holds
+    /// the record in a conflicting mode
+    pub fn try_lock_record(&mut self, block: &Block, slot: Slot, mode: LockMode) -> DbResult<bool> {
+        let _call = match mode.is_write() {
+            true => self.enter_writable()?,
+            false => self.enter()?,
+        };
+        self.concurrency.try_lock(&Self::record(block, slot), mode)
+    }
 
+    /// Latches the block till the returned guard is dropped. Unlike locks a latch is not held
+    /// till commit, it only keeps other transactions out of the block for a single operation
+    pub fn latch(&self, block: &Block) -> Latch {
+        self.concurrency.latch(block)
+    }
+
+    /// Reads an int without locking the block, the caller is expected to hold the latch of
+    /// the block and a lock on the record the value belongs to
+    pub fn get_int_latched(&mut self, block: &Block, offset: u32) -> DbResult<i32> {
         let buffer = self.buffer.safe_lock().get_buffer(block)?;
         let mut bm = self.bm.safe_lock();
         let buffer = bm.get_buffer_mut(buffer);
@@ -112,9 +654,17 @@ impl Transactions {
         Ok(buffer.contents().get_int(offset as usize))
     }
 
-    pub fn get_string(&mut self, block: &Block, offset: u32) -> DbResult<String> {
-        self.concurrency.slock(block)?;
+    /// Reads a long without locking the block, see `get_int_latched`
+    pub fn get_long_latched(&mut self, block: &Block, offset: u32) -> DbResult<i64> {
+        let buffer = self.buffer.safe_lock().get_buffer(block)?;
+        let mut bm = self.bm.safe_lock();
+        let buffer = bm.get_buffer_mut(buffer);
 
+        Ok(buffer.contents().get_long(offset as usize))
+    }
+
+    /// Reads a string without locking the block, see `get_int_latched`
+    pub fn get_string_latched(&mut self, block: &Block, offset: u32) -> DbResult<String> {
         let buffer = self.buffer.safe_lock().get_buffer(block)?;
         let mut bm = self.bm.safe_lock();
         let buffer = bm.get_buffer_mut(buffer);
@@ -122,70 +672,343 @@ impl Transactions {
         Ok(buffer.contents().get_string(offset as usize))
     }
 
-    pub fn set_string(
+    /// Writes a string without locking the block, the caller is expected to hold the latch of
+    /// the block and an exclusive lock on the record the value belongs to
+    pub fn set_string_latched(
         &mut self,
         block: &Block,
         offset: u32,
         val: String,
         ok_to_log: bool,
     ) -> DbResult<()> {
-        self.concurrency.xlock(block)?;
+        let _call = self.enter_writable()?;
+        self.modify_latched(
+            block,
+            |recovery_mgr, buffer| {
+                ok_to_log
+                    .then(|| recovery_mgr.set_string(buffer, offset, &val))
+                    .transpose()
+            },
+            |page| page.set_string(offset as usize, val.clone()),
+        )
+    }
+
+    /// Writes an int without locking the block, see `set_string_latched`
+    pub fn set_int_latched(
+        &mut self,
+        block: &Block,
+        offset: u32,
+        val: i32,
+        ok_to_log: bool,
+    ) -> DbResult<()> {
+        let _call = self.enter_writable()?;
+        self.modify_latched(
+            block,
+            |recovery_mgr, buffer| {
+                ok_to_log
+                    .then(|| recovery_mgr.set_int(buffer, offset, val))
+                    .transpose()
+            },
+            |page| page.set_int(offset as usize, val),
+        )
+    }
+
+    /// Writes a long without locking the block, see `set_string_latched`
+    pub fn set_long_latched(
+        &mut self,
+        block: &Block,
+        offset: u32,
+        val: i64,
+        ok_to_log: bool,
+    ) -> DbResult<()> {
+        let _call = self.enter_writable()?;
+        self.modify_latched(
+            block,
+            |recovery_mgr, buffer| {
+                ok_to_log
+                    .then(|| recovery_mgr.set_long(buffer, offset, val))
+                    .transpose()
+            },
+            |page| page.set_long(offset as usize, val),
+        )
+    }
+
+    /// Puts back the string the record logged at `undone` overwrote, logging a compensation
+    /// record on behalf of the transaction `txnum` which made the change
+    pub(crate) fn compensate_string_latched(
+        &mut self,
+        block: &Block,
+        offset: u32,
+        val: String,
+        txnum: i64,
+        undone: u64,
+    ) -> DbResult<()> {
+        self.modify_latched(
+            block,
+            |recovery_mgr, buffer| {
+                recovery_mgr
+                    .compensate_string(buffer, offset, &val, txnum, undone)
+                    .map(Some)
+            },
+            |page| page.set_string(offset as usize, val.clone()),
+        )
+    }
+
+    /// Puts back an int, see `compensate_string_latched`
+    pub(crate) fn compensate_int_latched(
+        &mut self,
+        block: &Block,
+        offset: u32,
+        val: i32,
+        txnum: i64,
+        undone: u64,
+    ) -> DbResult<()> {
+        self.modify_latched(
+            block,
+            |recovery_mgr, buffer| {
+                recovery_mgr
+                    .compensate_int(buffer, offset, val, txnum, undone)
+                    .map(Some)
+            },
+            |page| page.set_int(offset as usize, val),
+        )
+    }
+
+    /// Puts back a long, see `compensate_string_latched`
+    pub(crate) fn compensate_long_latched(
+        &mut self,
+        block: &Block,
+        offset: u32,
+        val: i64,
+        txnum: i64,
+        undone: u64,
+    ) -> DbResult<()> {
+        self.modify_latched(
+            block,
+            |recovery_mgr, buffer| {
+                recovery_mgr
+                    .compensate_long(buffer, offset, val, txnum, undone)
+                    .map(Some)
+            },
+            |page| page.set_long(offset as usize, val),
+        )
+    }
+
+    /// Writes again the string logged at `lsn` unless the block already has the change,
+    /// nothing is logged
+    pub(crate) fn redo_string_latched(
+        &mut self,
+        block: &Block,
+        offset: u32,
+        val: String,
+        lsn: u64,
+    ) -> DbResult<()> {
+        self.redo_latched(block, lsn, |page| page.set_string(offset as usize, val))
+    }
+
+    /// Writes again an int, see `redo_string_latched`
+    pub(crate) fn redo_int_latched(
+        &mut self,
+        block: &Block,
+        offset: u32,
+        val: i32,
+        lsn: u64,
+    ) -> DbResult<()> {
+        self.redo_latched(block, lsn, |page| page.set_int(offset as usize, val))
+    }
 
+    /// Writes again a long, see `redo_string_latched`
+    pub(crate) fn redo_long_latched(
+        &mut self,
+        block: &Block,
+        offset: u32,
+        val: i64,
+        lsn: u64,
+    ) -> DbResult<()> {
+        self.redo_latched(block, lsn, |page| page.set_long(offset as usize, val))
+    }
+
+    /// Reads `len` bytes of the block as they are, see `get_int_latched`
+    pub(crate) fn get_bytes_latched(
+        &mut self,
+        block: &Block,
+        offset: usize,
+        len: usize,
+    ) -> DbResult<Vec<u8>> {
         let buffer = self.buffer.safe_lock().get_buffer(block)?;
         let mut bm = self.bm.safe_lock();
-
         let buffer = bm.get_buffer_mut(buffer);
 
-        let mut lsn: i32 = -1;
+        Ok(buffer.contents().contents()[offset..offset + len].to_vec())
+    }
 
-        if ok_to_log {
-            lsn = self.recovery_mgr.set_string(buffer, offset, &val)? as i32;
-        }
+    /// Changes a record, logging the whole change in a single row level record. The caller is
+    /// expected to hold the latch of the block and an exclusive lock on the record
+    pub(crate) fn change_row_latched(&mut self, block: &Block, change: &RowChange) -> DbResult<()> {
+        let _call = self.enter_writable()?;
+        self.modify_latched(
+            block,
+            |recovery_mgr, _| recovery_mgr.change_row(change).map(Some),
+            |page| Self::write_row(page, change),
+        )
+    }
+
+    /// Undoes a row change logged at `undone` by the transaction `txnum`, see
+    /// `compensate_string_latched`
+    pub(crate) fn compensate_row_latched(
+        &mut self,
+        block: &Block,
+        change: &RowChange,
+        txnum: i64,
+        undone: u64,
+    ) -> DbResult<()> {
+        self.modify_latched(
+            block,
+            |recovery_mgr, _| recovery_mgr.compensate_row(change, txnum, undone).map(Some),
+            |page| Self::write_row(page, change),
+        )
+    }
 
-        let page = buffer.contents();
-        page.set_string(offset as usize, val);
-        buffer.set_modified(self.txnum as i32, lsn);
+    /// Makes a row change again, see `redo_string_latched`
+    pub(crate) fn redo_row_latched(
+        &mut self,
+        block: &Block,
+        change: &RowChange,
+        lsn: u64,
+    ) -> DbResult<()> {
+        self.redo_latched(block, lsn, |page| Self::write_row(page, change))
+    }
+
+    /// Writes `bytes` at `offset` of a block of an overflow file, either to a chain the
+    /// transaction is writing or to the free list of the file, logging them in as many records
+    /// as the log needs. The caller is expected to hold the latch of the block
+    pub(crate) fn write_overflow_latched(
+        &mut self,
+        block: &Block,
+        offset: usize,
+        bytes: &[u8],
+    ) -> DbResult<()> {
+        let _call = self.enter_writable()?;
+        let chunk = OverflowRecord::max_bytes(self.blocksize() as usize, block);
+        for (i, bytes) in bytes.chunks(chunk).enumerate() {
+            let offset = offset + i * chunk;
+            self.modify_latched(
+                block,
+                |recovery_mgr, _| {
+                    recovery_mgr
+                        .write_overflow(block, offset as u32, bytes)
+                        .map(Some)
+                },
+                |page| page.contents()[offset..offset + bytes.len()].copy_from_slice(bytes),
+            )?;
+        }
         Ok(())
     }
-    pub fn set_int(
+
+    /// Writes the bytes of an overflow chain again, see `redo_string_latched`
+    pub(crate) fn redo_overflow_latched(
         &mut self,
         block: &Block,
         offset: u32,
-        val: i32,
-        ok_to_log: bool,
+        bytes: &[u8],
+        lsn: u64,
     ) -> DbResult<()> {
-        self.concurrency.xlock(block)?;
+        self.redo_latched(block, lsn, |page| {
+            let offset = offset as usize;
+            page.contents()[offset..offset + bytes.len()].copy_from_slice(bytes)
+        })
+    }
+
+    fn write_row(page: &mut Page, change: &RowChange) {
+        if change.is_slotted() {
+            let slot = change.rid.slot().inner();
+            SlottedPage::new(page).write(slot, change.offset, &change.new);
+            return;
+        }
 
+        let pos = change.pos();
+        page.contents()[pos..pos + change.new.len()].copy_from_slice(&change.new);
+    }
+
+    /// Logs the change with `log` before making it with `write`, the page LSN of the block
+    /// becomes the LSN of the record so the log is flushed up to it before the block is
+    fn modify_latched(
+        &mut self,
+        block: &Block,
+        log: impl FnOnce(&mut RecoveryManager, &mut Buffer) -> std::io::Result<Option<u64>>,
+        write: impl FnOnce(&mut Page),
+    ) -> DbResult<()> {
         let buffer = self.buffer.safe_lock().get_buffer(block)?;
         let mut bm = self.bm.safe_lock();
-
         let buffer = bm.get_buffer_mut(buffer);
 
-        let mut lsn = -1;
+        let lsn = log(&mut self.recovery_mgr, buffer)?;
 
-        if ok_to_log {
-            lsn = self.recovery_mgr.set_int(buffer, offset, val)? as i32;
-        }
+        write(buffer.contents());
+        buffer.set_modified(self.txnum as i64, lsn.map_or(-1, |lsn| lsn as i64));
+        Ok(())
+    }
 
-        let page = buffer.contents();
-        page.set_int(offset as usize, val);
-        buffer.set_modified(self.txnum as i32, lsn);
+    /// Makes the change only if the page LSN of the block shows it never reached the disk
+    fn redo_latched(
+        &mut self,
+        block: &Block,
+        lsn: u64,
+        write: impl FnOnce(&mut Page),
+    ) -> DbResult<()> {
+        let buffer = self.buffer.safe_lock().get_buffer(block)?;
+        let mut bm = self.bm.safe_lock();
+        let buffer = bm.get_buffer_mut(buffer);
 
+        if buffer.page_lsn() < lsn {
+            write(buffer.contents());
+            buffer.set_modified(self.txnum as i64, lsn as i64);
+        }
         Ok(())
     }
 
+    fn read_locked<T>(
+        &mut self,
+        target: &LockTarget,
+        f: impl FnOnce(&mut Self) -> DbResult<T>,
+    ) -> DbResult<T> {
+        let _call = self.enter()?;
+        let release = self.concurrency.read_lock(target)?;
+        let result = f(self);
+
+        if release {
+            self.concurrency.unlock(target);
+        }
+        result
+    }
+
+    fn record(block: &Block, slot: Slot) -> LockTarget {
+        LockTarget::Record(block.filename().to_string(), RowId::new(block.num(), slot))
+    }
+
     /// Return the number of blocks in the specified file.
-    /// This method first obtains an SLock on the
+    /// Serializable transactions first obtain an SLock on the
     /// "end of the file", before asking the file manager
     /// to return the file size.
     pub fn size(&mut self, filename: String) -> DbResult<u64> {
-        let blk = Block::new(filename.clone(), END_OF_FILE);
-        self.concurrency.slock(&blk)?;
+        let _call = self.enter()?;
+        if self.isolation_level() == IsolationLevel::Serializable {
+            let blk = Block::new(filename.clone(), END_OF_FILE);
+            self.concurrency.slock(&blk)?;
+        }
+
+        self.file_mgr.size(&filename).map_err(DbError::IoError)
+    }
 
+    /// Return the number of blocks in the specified file without locking the end of the file,
+    /// so blocks appended by transactions still running are counted too. Used by snapshot
+    /// reads, the records of such transactions are not visible to them anyway
+    pub fn size_unlocked(&self, filename: String) -> DbResult<u64> {
         self.file_mgr.size(&filename).map_err(DbError::IoError)
     }
 
     pub fn append(&mut self, filename: String) -> DbResult<Block> {
+        let _call = self.enter_writable()?;
         let blk = Block::new(filename.clone(), END_OF_FILE);
         self.concurrency.xlock(&blk)?;
 
@@ -0,0 +1,170 @@
+mod test;
+
+use crate::utils::safe_lock::SafeLock;
+use std::{collections::HashMap, sync::Mutex};
+
+/// Stamp of a version which nobody deleted
+pub const NO_TX: i64 = -1;
+
+/// Where a transaction is in its life
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TxState {
+    Active,
+    /// Committed with the given commit sequence number
+    Committed(u64),
+}
+
+/// The committed state of the database as a transaction sees it. Everything committed before
+/// the snapshot was taken is visible, everything committed after it is not.
+#[derive(Clone, Copy, Debug)]
+pub struct Snapshot {
+    txnum: u64,
+    /// Commit sequence number the next commit after the snapshot gets
+    csn: u64,
+    /// Whether the changes of the running transactions are visible too
+    dirty: bool,
+}
+
+impl Snapshot {
+    /// A view of everything committed so far and whatever is committed later on, this is
+    /// what the transactions reading under locks see
+    pub fn latest(txnum: u64) -> Self {
+        Self {
+            txnum,
+            csn: u64::MAX,
+            dirty: false,
+        }
+    }
+
+    /// A view of the newest versions, committed or not. This is what the transactions reading
+    /// uncommitted data see
+    pub fn uncommitted(txnum: u64) -> Self {
+        Self {
+            txnum,
+            csn: u64::MAX,
+            dirty: true,
+        }
+    }
+
+    pub fn txnum(&self) -> u64 {
+        self.txnum
+    }
+}
+
+#[derive(Default)]
+struct TxStatusInner {
+    /// Commit sequence number each active transaction started at
+    active: HashMap<u64, u64>,
+    /// Committed transactions which are not visible to every active transaction yet
+    committed: HashMap<u64, u64>,
+    next_csn: u64,
+}
+
+impl TxStatusInner {
+    /// Oldest commit sequence number any active transaction could still see
+    fn horizon(&self) -> u64 {
+        self.active.values().copied().min().unwrap_or(self.next_csn)
+    }
+
+    /// Forgets the committed transactions every active transaction sees, an unknown
+    /// transaction is treated as one committed long ago
+    fn prune(&mut self) {
+        let horizon = self.horizon();
+        self.committed.retain(|_, csn| *csn >= horizon);
+    }
+}
+
+/// Keeps track of which transactions are running and in which order they committed, this is
+/// what the visibility of the row versions is decided with.
+///
+/// Rolled back transactions are simply forgotten, their versions are removed by undoing their
+/// changes before that. Transactions the table does not know about, e.g the ones from before a
+/// restart, are treated as committed.
+pub struct TxStatusTable {
+    inner: Mutex<TxStatusInner>,
+}
+
+impl TxStatusTable {
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(TxStatusInner::default()),
+        }
+    }
+
+    /// Registers a new active transaction
+    pub fn begin(&self, txnum: u64) {
+        let mut inner = self.inner.safe_lock();
+        let csn = inner.next_csn;
+        inner.active.insert(txnum, csn);
+    }
+
+    /// Takes a snapshot for the transaction, it sees everything committed till now
+    ///
+    /// The transaction has to be registered with `begin` before, the versions the snapshot
+    /// sees are then kept around till the transaction finishes
+    pub fn snapshot(&self, txnum: u64) -> Snapshot {
+        let csn = self.inner.safe_lock().next_csn;
+        Snapshot {
+            txnum,
+            csn,
+            dirty: false,
+        }
+    }
+
+    /// Marks the transaction committed, every snapshot taken from now on sees its changes
+    pub fn commit(&self, txnum: u64) {
+        let mut inner = self.inner.safe_lock();
+        inner.active.remove(&txnum);
+
+        let csn = inner.next_csn;
+        inner.next_csn += 1;
+        inner.committed.insert(txnum, csn);
+        inner.prune();
+    }
+
+    /// Forgets a transaction which rolled back, its changes have to be undone already
+    pub fn rollback(&self, txnum: u64) {
+        let mut inner = self.inner.safe_lock();
+        inner.active.remove(&txnum);
+        inner.prune();
+    }
+
+    /// Whether the changes made by `txnum` are part of the snapshot, a transaction always sees
+    /// its own changes
+    pub fn sees(&self, snapshot: &Snapshot, txnum: u64) -> bool {
+        if txnum == snapshot.txnum {
+            return true;
+        }
+
+        match self.state(txnum) {
+            Some(TxState::Active) => snapshot.dirty,
+            Some(TxState::Committed(csn)) => csn < snapshot.csn,
+            None => true,
+        }
+    }
+
+    /// State of the transaction, `None` if it's not running and every active transaction sees
+    /// it as committed
+    pub fn state(&self, txnum: u64) -> Option<TxState> {
+        let inner = self.inner.safe_lock();
+
+        if inner.active.contains_key(&txnum) {
+            return Some(TxState::Active);
+        }
+        inner.committed.get(&txnum).copied().map(TxState::Committed)
+    }
+
+    /// Whether a version deleted by `txnum` is invisible to every running and future
+    /// transaction, such a version can be thrown away
+    pub fn is_dead(&self, txnum: u64) -> bool {
+        let inner = self.inner.safe_lock();
+
+        if inner.active.contains_key(&txnum) {
+            return false;
+        }
+        inner
+            .committed
+            .get(&txnum)
+            .is_none_or(|csn| *csn < inner.horizon())
+    }
+}
@@ -0,0 +1,37 @@
+#[cfg(test)]
+mod tests {
+    use crate::storage::tx::mvcc::{Snapshot, TxState, TxStatusTable};
+
+    #[test]
+    fn txstatustest() {
+        let status = TxStatusTable::new();
+
+        status.begin(1);
+        status.begin(2);
+        let snapshot = status.snapshot(2);
+        assert_eq!(status.state(1), Some(TxState::Active));
+        assert!(status.sees(&snapshot, 2));
+        assert!(!status.sees(&snapshot, 1));
+
+        // Commits after the snapshot was taken stay invisible to it
+        status.commit(1);
+        assert!(!status.sees(&snapshot, 1));
+        assert!(status.sees(&Snapshot::latest(2), 1));
+
+        status.begin(3);
+        assert!(status.sees(&status.snapshot(3), 1));
+
+        // Versions deleted by 1 are still visible to the snapshot of 2
+        assert!(!status.is_dead(1));
+        assert!(!status.is_dead(3));
+
+        status.commit(2);
+        status.rollback(3);
+        assert!(status.is_dead(1));
+        assert!(status.is_dead(2));
+
+        // Every running transaction sees them, so they are forgotten
+        assert_eq!(status.state(1), None);
+        assert_eq!(status.state(3), None);
+    }
+}
@@ -1,41 +1,93 @@
-use super::log_record::{LogOperation, RecordLog};
+use super::log_record::{LogOperation, RecordLog, RecordReader};
 use crate::{
+    consts::{INTEGER_BYTES, LONG_BYTES},
+    error::DbResult,
     storage::{disk::page::Page, log::manager::LogManager, tx},
     utils::safe_lock::SafeLock,
 };
 
 use std::sync::{Arc, Mutex};
 
-pub struct Checkpoint {}
+/// Saves the log in the write-ahead-log in the following format
+///
+/// CHECKPOINT [REDO LSN] [ACTIVE COUNT] [TRANSACTION NUM]...
+///
+/// - Redo LSN: Every change logged before it is on disk, redo starts there
+/// - Transaction nums: The transactions running when the checkpoint was taken, their records
+///   before the checkpoint still have to be undone if they never finish
+///
+/// Checkpoints of version 0 have no fields at all, they were quiescent so nothing before them
+/// has to be redone or undone
+pub struct Checkpoint {
+    redo_lsn: Option<u64>,
+    active: Vec<i64>,
+}
 
 impl Checkpoint {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(mut p: RecordReader, version: i32) -> DbResult<Self> {
+        if version == 0 {
+            return Ok(Self {
+                redo_lsn: None,
+                active: Vec::new(),
+            });
+        }
+        let redo_lsn_pos = INTEGER_BYTES;
+        let redo_lsn = Some(p.long(redo_lsn_pos)? as u64);
+        let count_pos = redo_lsn_pos + LONG_BYTES;
+        let count = p.len(count_pos)?;
+
+        let active = (0..count)
+            .map(|i| p.long(count_pos + INTEGER_BYTES + i * LONG_BYTES))
+            .collect::<DbResult<_>>()?;
+
+        Ok(Self { redo_lsn, active })
     }
 
-    pub fn write_to_log(lm: Arc<Mutex<LogManager>>) -> std::io::Result<u32> {
-        let mut page = Page::new(4);
+    pub fn write_to_log(
+        lm: Arc<Mutex<LogManager>>,
+        redo_lsn: u64,
+        active: &[i64],
+    ) -> std::io::Result<u64> {
+        let redo_lsn_pos = INTEGER_BYTES;
+        let count_pos = redo_lsn_pos + LONG_BYTES;
+        let active_pos = count_pos + INTEGER_BYTES;
 
-        page.set_int(0, LogOperation::Checkpoint as i32);
+        let mut page = Page::new((active_pos + active.len() * LONG_BYTES) as u64);
+
+        page.set_int(0, LogOperation::Checkpoint.header());
+        page.set_long(redo_lsn_pos, redo_lsn as i64);
+        page.set_int(count_pos, active.len() as i32);
+        for (i, txnum) in active.iter().enumerate() {
+            page.set_long(active_pos + i * LONG_BYTES, *txnum);
+        }
         lm.safe_lock().append(page.contents())
     }
 }
 
 impl RecordLog for Checkpoint {
-    fn undo(&self, _tx: &mut tx::Transactions) -> crate::error::DbResult<()> {
+    fn undo(&self, _lsn: u64, _tx: &mut tx::Transactions) -> crate::error::DbResult<()> {
         Ok(())
     }
 
     fn op(&self) -> LogOperation {
         LogOperation::Checkpoint
     }
-    fn tx_number(&self) -> i32 {
+    fn tx_number(&self) -> i64 {
         -1
     }
+    fn redo_lsn(&self) -> Option<u64> {
+        self.redo_lsn
+    }
+    fn active(&self) -> Vec<i64> {
+        self.active.clone()
+    }
 }
 
 impl std::fmt::Display for Checkpoint {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "<CHECKPOINT>")
+        match self.redo_lsn {
+            Some(redo_lsn) => write!(f, "<CHECKPOINT {} {:?}>", redo_lsn, self.active),
+            None => write!(f, "<CHECKPOINT>"),
+        }
     }
 }
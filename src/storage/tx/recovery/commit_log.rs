@@ -1,44 +1,71 @@
-use super::log_record::{LogOperation, RecordLog};
+use super::log_record::{LogOperation, RecordLog, RecordReader};
 use crate::{
-    consts::INTEGER_BYTES, storage::disk::page::Page, storage::log::manager::LogManager,
-    storage::tx, utils::safe_lock::SafeLock,
+    consts::{INTEGER_BYTES, LONG_BYTES},
+    error::DbResult,
+    storage::disk::page::Page,
+    storage::log::manager::LogManager,
+    storage::tx,
+    utils::safe_lock::SafeLock,
 };
 
-use std::sync::{Arc, Mutex};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
+/// Saves the log in the write-ahead-log in the following format
+///
+/// COMMIT [TRANSACTION NUM] [TIME]
+///
+/// - Time: Milliseconds since the unix epoch when the transaction committed, point-in-time
+///   recovery stops at it. Commit records before version 2 don't have it
 pub struct CommitLog {
-    txnum: i32,
+    txnum: i64,
+    time: Option<SystemTime>,
 }
 
 impl CommitLog {
-    pub fn new(mut page: Page) -> Self {
-        let txnum_pos = INTEGER_BYTES;
-        Self {
-            txnum: page.get_int(txnum_pos),
-        }
+    pub fn new(mut p: RecordReader, version: i32) -> DbResult<Self> {
+        let (txnum, time_pos) = p.txnum(version)?;
+        let time = if version >= 2 {
+            Some(UNIX_EPOCH + Duration::from_millis(p.long(time_pos)? as u64))
+        } else {
+            None
+        };
+
+        Ok(Self { txnum, time })
     }
 
-    pub fn write_to_log(lm: Arc<Mutex<LogManager>>, tx_num: i32) -> std::io::Result<u32> {
-        let mut page = Page::new(2 * INTEGER_BYTES as u64);
+    pub fn write_to_log(lm: Arc<Mutex<LogManager>>, tx_num: i64) -> std::io::Result<u64> {
+        let txnum_pos = INTEGER_BYTES;
+        let time_pos = txnum_pos + LONG_BYTES;
+        let mut page = Page::new((time_pos + LONG_BYTES) as u64);
 
-        page.set_int(0, LogOperation::Commit as i32);
-        page.set_int(INTEGER_BYTES, tx_num);
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        page.set_int(0, LogOperation::Commit.header());
+        page.set_long(txnum_pos, tx_num);
+        page.set_long(time_pos, time.as_millis() as i64);
 
         lm.safe_lock().append(page.contents())
     }
 }
 
 impl RecordLog for CommitLog {
-    fn undo(&self, _tx: &mut tx::Transactions) -> crate::error::DbResult<()> {
+    fn undo(&self, _lsn: u64, _tx: &mut tx::Transactions) -> crate::error::DbResult<()> {
         Ok(())
     }
 
     fn op(&self) -> LogOperation {
         LogOperation::Commit
     }
-    fn tx_number(&self) -> i32 {
+    fn tx_number(&self) -> i64 {
         self.txnum
     }
+    fn commit_time(&self) -> Option<SystemTime> {
+        self.time
+    }
 }
 
 impl std::fmt::Display for CommitLog {
@@ -1,15 +1,19 @@
-use crate::storage::disk::page::Page;
+use std::time::SystemTime;
 
-use crate::error::DbResult;
+use crate::consts::{INTEGER_BYTES, LONG_BYTES};
+use crate::storage::disk::{block::Block, page::Page};
+
+use crate::error::{DbError, DbResult};
 use crate::storage::tx::{
     recovery::{
-        checkpoint::*, commit_log::*, rollback::*, set_int::*, set_string::*, start_log::*,
+        checkpoint::*, commit_log::*, overflow::*, prepare::*, rollback::*, row::*, savepoint::*,
+        set_int::*, set_long::*, set_string::*, start_log::*,
     },
     Transactions,
 };
 
 #[repr(i32)]
-#[derive(Eq, PartialEq, Debug)]
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
 pub enum LogOperation {
     Checkpoint = 1,
     Start = 2,
@@ -17,28 +21,188 @@ pub enum LogOperation {
     Rollback = 4,
     SetInt = 5,
     SetString = 6,
+    Savepoint = 7,
+    SetLong = 8,
+    Prepare = 9,
+    Insert = 10,
+    Delete = 11,
+    Update = 12,
+    Overflow = 13,
 }
 
+/// Version of the record layouts written to the log
+///
+/// Every record starts with a header holding the operation in the low 16 bits and the version
+/// it was written with in the high 16 bits. A record type can be added without touching the
+/// version, older logs simply don't have it. Changing the layout of a record bumps the version,
+/// records keep the version they were written with so the old layout can still be decoded
+///
+/// Version 0 are the records written before the header had a version. Only the first six
+/// operations existed, their transaction number is 4 bytes long, checkpoints are quiescent and
+/// have no fields and changes only have the old value. Version 2 added the commit time to `Commit`
+pub const LOG_VERSION: i32 = 2;
+
 impl LogOperation {
-    fn from_i32(val: i32) -> Self {
-        unsafe { std::mem::transmute(val) }
+    /// Header starting a record of this operation written with the current version
+    pub fn header(self) -> i32 {
+        (LOG_VERSION << 16) | self as i32
+    }
+
+    /// Splits the header of a record into its operation and version
+    pub fn decode(header: i32) -> DbResult<(Self, i32)> {
+        let version = header >> 16;
+        if !(0..=LOG_VERSION).contains(&version) {
+            return Err(DbError::UnsupportedLogVersion(version));
+        }
+        Ok((Self::try_from(header & 0xffff)?, version))
+    }
+}
+
+impl TryFrom<i32> for LogOperation {
+    type Error = DbError;
+
+    fn try_from(val: i32) -> DbResult<Self> {
+        Ok(match val {
+            1 => Self::Checkpoint,
+            2 => Self::Start,
+            3 => Self::Commit,
+            4 => Self::Rollback,
+            5 => Self::SetInt,
+            6 => Self::SetString,
+            7 => Self::Savepoint,
+            8 => Self::SetLong,
+            9 => Self::Prepare,
+            10 => Self::Insert,
+            11 => Self::Delete,
+            12 => Self::Update,
+            13 => Self::Overflow,
+            _ => return Err(DbError::UnknownLogOperation(val)),
+        })
     }
 }
 
-pub trait RecordLog {
+pub trait RecordLog: std::fmt::Display {
     fn op(&self) -> LogOperation;
-    fn tx_number(&self) -> i32;
-    fn undo(&self, tx: &mut Transactions) -> DbResult<()>;
+    fn tx_number(&self) -> i64;
+    /// Id of the savepoint the record marks, if it's a savepoint marker
+    fn savepoint(&self) -> Option<i32> {
+        None
+    }
+    /// Global id of the prepared transaction, if it's a prepare record
+    fn gid(&self) -> Option<String> {
+        None
+    }
+    /// Block the record changed, if it changed one
+    fn block(&self) -> Option<Block> {
+        None
+    }
+    /// LSN recovery has to redo from, if it's a checkpoint which has it
+    fn redo_lsn(&self) -> Option<u64> {
+        None
+    }
+    /// Transactions running when the checkpoint was taken, if it's a checkpoint
+    fn active(&self) -> Vec<i64> {
+        Vec::new()
+    }
+    /// When the transaction committed, if it's a commit record which has it
+    fn commit_time(&self) -> Option<SystemTime> {
+        None
+    }
+    /// LSN of the record a compensation record undid, if it's one
+    fn compensates(&self) -> Option<u64> {
+        None
+    }
+    /// Reverts the change of the record written at `lsn`, logging a compensation record
+    fn undo(&self, lsn: u64, tx: &mut Transactions) -> DbResult<()>;
+    /// Reapplies the change of the record written at `lsn` unless the page already has it
+    fn redo(&self, _lsn: u64, _tx: &mut Transactions) -> DbResult<()> {
+        Ok(())
+    }
+}
+
+/// Reads the fields of a record out of its bytes. A field running past the end of the bytes or
+/// a string which isn't UTF-8 makes the record corrupt instead of panicking
+pub struct RecordReader {
+    page: Page,
+}
+
+impl RecordReader {
+    fn new(bytes: Vec<u8>) -> Self {
+        Self {
+            page: Page::new_with_data(bytes),
+        }
+    }
+
+    fn corrupt(&self) -> DbError {
+        DbError::CorruptLogRecord(self.page.size())
+    }
+
+    /// Fails unless `len` bytes starting at `offset` are part of the record
+    fn check(&self, offset: usize, len: usize) -> DbResult<()> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.page.size() => Ok(()),
+            _ => Err(self.corrupt()),
+        }
+    }
+
+    pub fn int(&mut self, offset: usize) -> DbResult<i32> {
+        self.check(offset, INTEGER_BYTES)?;
+        Ok(self.page.get_int(offset))
+    }
+
+    pub fn long(&mut self, offset: usize) -> DbResult<i64> {
+        self.check(offset, LONG_BYTES)?;
+        Ok(self.page.get_long(offset))
+    }
+
+    /// Reads a length or a count, which can't be negative
+    pub fn len(&mut self, offset: usize) -> DbResult<usize> {
+        let len = self.int(offset)?;
+        usize::try_from(len).map_err(|_| self.corrupt())
+    }
+
+    pub fn bytes(&mut self, offset: usize) -> DbResult<Vec<u8>> {
+        let len = self.len(offset)?;
+        self.check(offset + INTEGER_BYTES, len)?;
+        Ok(self.page.get_bytes(offset).to_vec())
+    }
+
+    pub fn string(&mut self, offset: usize) -> DbResult<String> {
+        let bytes = self.bytes(offset)?;
+        String::from_utf8(bytes).map_err(|_| self.corrupt())
+    }
+
+    /// Reads the transaction number following the header along with the offset of the next
+    /// field. Records of version 0 have a 4 byte transaction number
+    pub fn txnum(&mut self, version: i32) -> DbResult<(i64, usize)> {
+        if version == 0 {
+            Ok((self.int(INTEGER_BYTES)?.into(), 2 * INTEGER_BYTES))
+        } else {
+            Ok((self.long(INTEGER_BYTES)?, INTEGER_BYTES + LONG_BYTES))
+        }
+    }
 }
 
-pub fn create_log_record(bytes: Vec<u8>) -> Box<dyn RecordLog> {
-    let mut page = Page::new_with_data(bytes);
-    match LogOperation::from_i32(page.get_int(0)) {
-        LogOperation::Checkpoint => Box::new(Checkpoint::new()),
-        LogOperation::Rollback => Box::new(Rollback::new(page)),
-        LogOperation::Commit => Box::new(CommitLog::new(page)),
-        LogOperation::SetInt => Box::new(SetIntRecord::new(page)),
-        LogOperation::SetString => Box::new(SetStringRecord::new(page)),
-        LogOperation::Start => Box::new(StartLog::new(page)),
+/// Decodes a record read from the log with the layout of the version it was written with
+pub fn create_log_record(bytes: Vec<u8>) -> DbResult<Box<dyn RecordLog>> {
+    let mut p = RecordReader::new(bytes);
+    let (op, version) = LogOperation::decode(p.int(0)?)?;
+    if version == 0 && op as i32 > LogOperation::SetString as i32 {
+        return Err(DbError::UnknownLogOperation(op as i32));
     }
+    Ok(match op {
+        LogOperation::Checkpoint => Box::new(Checkpoint::new(p, version)?),
+        LogOperation::Rollback => Box::new(Rollback::new(p, version)?),
+        LogOperation::Commit => Box::new(CommitLog::new(p, version)?),
+        LogOperation::SetInt => Box::new(SetIntRecord::new(p, version)?),
+        LogOperation::SetString => Box::new(SetStringRecord::new(p, version)?),
+        LogOperation::Start => Box::new(StartLog::new(p, version)?),
+        LogOperation::Savepoint => Box::new(SavepointLog::new(p)?),
+        LogOperation::SetLong => Box::new(SetLongRecord::new(p)?),
+        LogOperation::Prepare => Box::new(PrepareLog::new(p)?),
+        LogOperation::Insert => Box::new(RowRecord::new(p, RowOp::Insert)?),
+        LogOperation::Delete => Box::new(RowRecord::new(p, RowOp::Delete)?),
+        LogOperation::Update => Box::new(RowRecord::new(p, RowOp::Update)?),
+        LogOperation::Overflow => Box::new(OverflowRecord::new(p)?),
+    })
 }
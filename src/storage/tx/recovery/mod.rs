@@ -1,9 +1,14 @@
 mod checkpoint;
 mod commit_log;
-mod log_record;
+pub(crate) mod log_record;
+pub(crate) mod overflow;
+mod prepare;
 pub mod recovery_mgr;
 mod rollback;
+pub mod row;
+mod savepoint;
 mod set_int;
+mod set_long;
 mod set_string;
 mod start_log;
 mod test;
@@ -0,0 +1,121 @@
+use crate::{
+    consts::{INTEGER_BYTES, LONG_BYTES},
+    error::DbResult,
+    storage::disk::{block::Block, page::Page},
+    storage::log::manager::{LogManager, HEADER_BYTES},
+    storage::tx::Transactions,
+    utils::safe_lock::SafeLock,
+};
+use std::sync::{Arc, Mutex};
+
+use super::log_record::{LogOperation, RecordLog, RecordReader};
+
+/// Saves the log in the write-ahead-log in the following format
+///
+/// OVERFLOW [TRANSACTION NUM] [FILE NAME] [BLOCK NUM] [OFFSET] [BYTES]
+///
+/// Bytes written to a block of an overflow file, to a new chain or to the free list, see
+/// `OverflowWriter`. Only a record the transaction changed can point to a new chain, so undoing
+/// the change of the record is enough, and the free list is only changed for chains nothing
+/// refers to whatever happens to the transaction. The record has no old bytes, it is only ever
+/// redone
+pub struct OverflowRecord {
+    txnum: i64,
+    block: Block,
+    offset: u32,
+    bytes: Vec<u8>,
+}
+
+impl OverflowRecord {
+    pub fn new(mut p: RecordReader) -> DbResult<Self> {
+        let txnum_pos = INTEGER_BYTES;
+        let txnum = p.long(txnum_pos)?;
+        let filename_pos = txnum_pos + LONG_BYTES;
+
+        let file_name = p.string(filename_pos)?;
+        let blknum_pos = filename_pos + Page::max_len(file_name.len());
+        let blk_num = p.int(blknum_pos)?;
+        let offset_pos = blknum_pos + INTEGER_BYTES;
+        let offset = p.int(offset_pos)?;
+        let bytes_pos = offset_pos + INTEGER_BYTES;
+        let bytes = p.bytes(bytes_pos)?;
+
+        Ok(Self {
+            txnum,
+            block: Block::new(file_name, blk_num as u64),
+            offset: offset as u32,
+            bytes,
+        })
+    }
+
+    /// Most bytes a single record can carry for the block. A record has to fit in a block of
+    /// the log of `block_size` bytes, which starts with its header and stores the length of
+    /// every record before it
+    pub fn max_bytes(block_size: usize, block: &Block) -> usize {
+        let log_bytes = HEADER_BYTES + INTEGER_BYTES;
+        let record_bytes = INTEGER_BYTES + LONG_BYTES + 3 * INTEGER_BYTES;
+        block_size - log_bytes - record_bytes - Page::max_len(block.filename().len())
+    }
+
+    pub fn write_to_log(
+        lm: Arc<Mutex<LogManager>>,
+        tx_num: i64,
+        block: &Block,
+        offset: u32,
+        bytes: &[u8],
+    ) -> std::io::Result<u64> {
+        let txnum_pos = INTEGER_BYTES;
+        let filename_pos = txnum_pos + LONG_BYTES;
+        let blknum_pos = filename_pos + Page::max_len(block.filename().len());
+        let offset_pos = blknum_pos + INTEGER_BYTES;
+        let bytes_pos = offset_pos + INTEGER_BYTES;
+
+        let mut page = Page::new((bytes_pos + INTEGER_BYTES + bytes.len()) as u64);
+
+        page.set_int(0, LogOperation::Overflow.header());
+        page.set_long(txnum_pos, tx_num);
+        page.set_string(filename_pos, block.filename().to_string());
+        page.set_int(blknum_pos, block.num() as i32);
+        page.set_int(offset_pos, offset as i32);
+        page.set_bytes(bytes_pos, bytes);
+
+        lm.safe_lock().append(page.contents())
+    }
+}
+
+impl RecordLog for OverflowRecord {
+    fn op(&self) -> LogOperation {
+        LogOperation::Overflow
+    }
+
+    fn tx_number(&self) -> i64 {
+        self.txnum
+    }
+    fn block(&self) -> Option<Block> {
+        Some(self.block.clone())
+    }
+
+    fn undo(&self, _lsn: u64, _tx: &mut Transactions) -> DbResult<()> {
+        Ok(())
+    }
+
+    fn redo(&self, lsn: u64, tx: &mut Transactions) -> DbResult<()> {
+        tx.pin(&self.block)?;
+        {
+            let _latch = tx.latch(&self.block);
+            tx.redo_overflow_latched(&self.block, self.offset, &self.bytes, lsn)?;
+        }
+        tx.unpin(&self.block)?;
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for OverflowRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "<OVERFLOW {} {} {} {:?} >",
+            self.txnum, self.block, self.offset, self.bytes
+        )
+    }
+}
@@ -0,0 +1,74 @@
+use super::log_record::{LogOperation, RecordLog, RecordReader};
+use crate::{
+    consts::{INTEGER_BYTES, LONG_BYTES},
+    storage::disk::page::Page,
+    storage::log::manager::LogManager,
+    storage::tx::Transactions,
+    utils::safe_lock::SafeLock,
+};
+use std::sync::{Arc, Mutex};
+
+/// Marks a transaction as prepared for the second phase of a distributed commit. The
+/// transaction is neither undone nor committed by recovery, it waits for the coordinator to
+/// decide its outcome
+///
+/// PREPARE [TRANSACTION NUM] [GID]
+///
+/// - Gid: Global id the coordinator knows the transaction by
+pub struct PrepareLog {
+    txnum: i64,
+    gid: String,
+}
+
+impl PrepareLog {
+    pub fn new(mut p: RecordReader) -> crate::error::DbResult<Self> {
+        let txnum_pos = INTEGER_BYTES;
+        let gid_pos = txnum_pos + LONG_BYTES;
+
+        Ok(Self {
+            txnum: p.long(txnum_pos)?,
+            gid: p.string(gid_pos)?,
+        })
+    }
+
+    pub fn write_to_log(
+        lm: Arc<Mutex<LogManager>>,
+        tx_num: i64,
+        gid: String,
+    ) -> std::io::Result<u64> {
+        let txnum_pos = INTEGER_BYTES;
+        let gid_pos = txnum_pos + LONG_BYTES;
+        let reclen = gid_pos + Page::max_len(gid.len());
+
+        let mut page = Page::new(reclen as u64);
+        page.set_int(0, LogOperation::Prepare.header());
+        page.set_long(txnum_pos, tx_num);
+        page.set_string(gid_pos, gid);
+
+        lm.safe_lock().append(page.contents())
+    }
+}
+
+impl RecordLog for PrepareLog {
+    fn op(&self) -> LogOperation {
+        LogOperation::Prepare
+    }
+
+    fn tx_number(&self) -> i64 {
+        self.txnum
+    }
+
+    fn gid(&self) -> Option<String> {
+        Some(self.gid.clone())
+    }
+
+    fn undo(&self, _lsn: u64, _tx: &mut Transactions) -> crate::error::DbResult<()> {
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for PrepareLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<PREPARE {} {} >", self.txnum, self.gid)
+    }
+}
@@ -3,17 +3,32 @@ use super::{
     start_log::StartLog,
 };
 use crate::{
-    error::DbResult,
+    error::{DbError, DbResult},
     storage::bufferpool::{buffer::Buffer, pool::BufferPoolManager},
+    storage::disk::block::Block,
     storage::log::manager::LogManager,
     storage::tx::recovery::{
-        checkpoint::Checkpoint, commit_log::CommitLog, rollback::Rollback, set_int::SetIntRecord,
+        checkpoint::Checkpoint,
+        commit_log::CommitLog,
+        overflow::OverflowRecord,
+        prepare::PrepareLog,
+        rollback::Rollback,
+        row::{RowChange, RowRecord},
+        savepoint::SavepointLog,
+        set_int::SetIntRecord,
+        set_long::SetLongRecord,
         set_string::SetStringRecord,
     },
-    storage::tx::Transactions,
+    storage::tx::{Transactions, TxManager},
     utils::safe_lock::SafeLock,
 };
-use std::sync::{Arc, Mutex};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicI32, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+};
 
 /// Recovery manager uses transaction log records to recover the database to the previous state it was before a shutdown or a crash.
 ///
@@ -43,34 +58,84 @@ use std::sync::{Arc, Mutex};
 /// Redo-only recovery can be achieved by not flushing the data to disk unless it’s committed.
 /// But to achieve this, the database should keep a buffer pinned for all the modifications a transaction is doing, which makes it a very risky choice.
 ///
-/// ** This recovery manager uses undo only recovery **
+/// ## ARIES:
+/// This recovery manager does neither. Every block keeps the LSN of the last record which
+/// changed it, its page LSN, and a block is only written once the log is flushed up to it.
+/// Commit only forces the log; the blocks reach the disk whenever the buffer pool evicts them.
+/// Recovery repeats history, redoing every logged change the block on disk doesn't have yet,
+/// then undoes the unfinished transactions. Undoing a change logs a compensation record,
+/// so a crash in the middle of an undo never undoes the same change twice.
+///
+/// ## Checkpoints:
+/// A checkpoint is taken while transactions run. It logs the transactions running at the time
+/// and the redo LSN, the smallest LSN a dirty block in the buffer pool still needs. Recovery
+/// reads the log back to whichever comes first, the redo LSN or the start of those transactions.
+///
+/// ## Prepared transactions:
+/// A transaction which logged a `Prepare` record but neither committed nor rolled back is in
+/// doubt, only the coordinator of the distributed commit knows its outcome. Recovery leaves its
+/// changes alone and hands it back, see `InDoubt`.
 ///
 #[derive(Clone)]
 pub struct RecoveryManager {
     lm: Arc<Mutex<LogManager>>,
     bm: Arc<Mutex<BufferPoolManager>>,
     tx: Option<Arc<Mutex<Transactions>>>,
-    txnum: i32,
+    txnum: i64,
+    /// Read only transactions never write to the log
+    read_only: bool,
+    /// Savepoints of the transaction which were not released yet, oldest first, along with
+    /// the ids their markers were logged with
+    savepoints: Arc<Mutex<Vec<(String, i32)>>>,
+    next_savepoint: Arc<AtomicI32>,
+}
+
+/// A prepared transaction found by recovery which still waits for its outcome
+pub struct InDoubt {
+    pub txnum: u64,
+    pub gid: String,
+    /// Blocks the transaction changed, they have to stay locked till it's resolved
+    pub blocks: Vec<Block>,
 }
 
 impl RecoveryManager {
     /// Creates an object for recovery manager, txnum should be incremented
-    /// before passing it here
+    /// before passing it here. Nothing is logged for a read only transaction, not even its start
     pub fn new(
-        txnum: i32,
+        txnum: i64,
         lm: Arc<Mutex<LogManager>>,
         bm: Arc<Mutex<BufferPoolManager>>,
+        read_only: bool,
     ) -> DbResult<Self> {
         let rmr = Self {
             tx: None,
             lm: lm.clone(),
             bm,
             txnum,
+            read_only,
+            savepoints: Arc::new(Mutex::new(Vec::new())),
+            next_savepoint: Arc::new(AtomicI32::new(0)),
         };
-        StartLog::write_to_log(lm.clone(), txnum)?;
+        if !read_only {
+            StartLog::write_to_log(lm.clone(), txnum)?;
+        }
         Ok(rmr)
     }
 
+    /// Creates the recovery manager of a transaction found in doubt by recovery, its start
+    /// is already logged
+    pub fn resume(&self, txnum: i64) -> Self {
+        Self {
+            tx: None,
+            lm: self.lm.clone(),
+            bm: self.bm.clone(),
+            txnum,
+            read_only: false,
+            savepoints: Arc::new(Mutex::new(Vec::new())),
+            next_savepoint: Arc::new(AtomicI32::new(0)),
+        }
+    }
+
     /// HACK: This had to be used since there's a cyclic dependency
     /// between Transactions and RecoveryManager
     pub fn init_transaction(self, tx: Arc<Mutex<Transactions>>) -> Self {
@@ -80,10 +145,12 @@ impl RecoveryManager {
         }
     }
 
-    /// Flushes the current transaction to disk and writes the commit
-    /// log to the log file
+    /// Writes the commit record and forces the log, the changed blocks are flushed whenever
+    /// the buffer pool needs them since redo can always bring them back
     pub fn commit(&mut self) -> std::io::Result<()> {
-        self.bm.safe_lock().flush_all(self.txnum)?;
+        if self.read_only {
+            return Ok(());
+        }
 
         let lsn = CommitLog::write_to_log(self.lm.clone(), self.txnum)?;
 
@@ -91,89 +158,339 @@ impl RecoveryManager {
         Ok(())
     }
 
-    /// Rolls back the data and flushes into the disk and flushes
-    /// the data into the disk
+    /// Undoes the changes of the transaction, logging a compensation record for each of
+    /// them, and forces the rollback record to the log
     pub fn rollback(&mut self) -> crate::error::DbResult<()> {
-        self.do_rollback()?;
+        if self.read_only {
+            return Ok(());
+        }
+
+        self.do_rollback(None)?;
 
-        self.bm.safe_lock().flush_all(self.txnum)?;
         let lsn = Rollback::write_to_log(self.lm.clone(), self.txnum)?;
 
         self.lm.safe_lock().flush(lsn)?;
         Ok(())
     }
 
-    /// This follows undo-only recovery i.e it does undo on
-    /// Transactions which is not commited/rollbacked before the database
-    /// crash or exited
-    pub fn recover(&mut self) -> crate::error::DbResult<()> {
-        self.do_recover()?;
+    /// Forces the prepare record to the log, once this returns the transaction can be
+    /// committed or rolled back even after a crash
+    pub fn prepare(&mut self, gid: &str) -> DbResult<()> {
+        if self.read_only {
+            return Ok(());
+        }
 
-        self.bm.safe_lock().flush_all(self.txnum)?;
-        let lsn = Checkpoint::write_to_log(self.lm.clone())?;
+        let lsn = PrepareLog::write_to_log(self.lm.clone(), self.txnum, gid.to_string())?;
 
         self.lm.safe_lock().flush(lsn)?;
         Ok(())
     }
 
-    /// Sets the log record for a set_int operation with old value
-    /// written to the log file for recovery
+    /// Writes a savepoint marker to the log, the transaction can later undo everything it did
+    /// after this point with `rollback_to`. A name which is already in use hides the older
+    /// savepoint till the newer one is released
+    pub fn savepoint(&mut self, name: &str) -> DbResult<()> {
+        let id = self.next_savepoint.fetch_add(1, Ordering::SeqCst);
+        if !self.read_only {
+            SavepointLog::write_to_log(self.lm.clone(), self.txnum, id, name.to_string())?;
+        }
+
+        self.savepoints.safe_lock().push((name.to_string(), id));
+        Ok(())
+    }
+
+    /// Undoes the changes made after the savepoint was created. The savepoint stays around
+    /// so it can be rolled back to again, the ones created after it are gone
+    pub fn rollback_to(&mut self, name: &str) -> DbResult<()> {
+        let id = {
+            let mut savepoints = self.savepoints.safe_lock();
+            let pos = Self::find_savepoint(&savepoints, name)?;
+
+            savepoints.truncate(pos + 1);
+            savepoints[pos].1
+        };
+
+        self.do_rollback(Some(id))
+    }
+
+    /// Forgets the savepoint and the ones created after it, the changes made since are kept
+    pub fn release(&mut self, name: &str) -> DbResult<()> {
+        let mut savepoints = self.savepoints.safe_lock();
+        let pos = Self::find_savepoint(&savepoints, name)?;
+
+        savepoints.truncate(pos);
+        Ok(())
+    }
+
+    fn find_savepoint(savepoints: &[(String, i32)], name: &str) -> DbResult<usize> {
+        savepoints
+            .iter()
+            .rposition(|(savepoint, _)| savepoint == name)
+            .ok_or_else(|| DbError::SavepointNotFound(name.to_string()))
+    }
+
+    /// Brings the database back to the state it was in before the crash or shutdown, then
+    /// undoes the transactions which were not committed/rollbacked. Returns the prepared
+    /// transactions which are still in doubt
+    ///
+    /// The checkpoint written at the end lists the transactions still in doubt, so the next
+    /// recovery finds their records again
+    ///
+    /// Only the checkpoints logged up to `last_checkpoint` are used if it's set. A backup is
+    /// restored from the checkpoint taken when it started, the blocks were copied later and
+    /// can miss changes a checkpoint logged meanwhile counts as written
+    pub fn recover(
+        &mut self,
+        last_checkpoint: Option<u64>,
+    ) -> crate::error::DbResult<Vec<InDoubt>> {
+        let in_doubt = self.do_recover(last_checkpoint)?;
+
+        // Nothing is left to redo before the checkpoint, only the prepared transactions
+        // are still running
+        self.bm.safe_lock().flush_dirty()?;
+        let active = in_doubt
+            .iter()
+            .map(|prepared| prepared.txnum as i64)
+            .collect::<Vec<_>>();
+        let redo_lsn = self.lm.safe_lock().latest_lsn() + 1;
+        let lsn = Checkpoint::write_to_log(self.lm.clone(), redo_lsn, &active)?;
+        self.lm.safe_lock().flush(lsn)?;
+        tracing::debug!(in_doubt = in_doubt.len(), "checkpoint written");
+
+        Ok(in_doubt)
+    }
+
+    /// Logs a checkpoint while the transactions keep running. Nothing gets flushed, the
+    /// record tells recovery where redo has to start and which transactions it has to follow
+    /// back past the checkpoint
+    pub fn checkpoint(
+        lm: Arc<Mutex<LogManager>>,
+        bm: &Mutex<BufferPoolManager>,
+        manager: &TxManager,
+    ) -> std::io::Result<u64> {
+        let redo_lsn = {
+            // No block changes while the pool is locked, whatever is logged after this
+            // goes to a block which is dirty already or becomes dirty with a later LSN
+            let bm = bm.safe_lock();
+            bm.oldest_rec_lsn()
+                .unwrap_or_else(|| lm.safe_lock().latest_lsn() + 1)
+        };
+
+        let lsn = {
+            let _gate = manager
+                .checkpoint_gate
+                .write()
+                .unwrap_or_else(PoisonError::into_inner);
+            let active = manager.running();
+            Checkpoint::write_to_log(lm.clone(), redo_lsn, &active)?
+        };
+        lm.safe_lock().flush(lsn)?;
+
+        tracing::debug!(lsn, redo_lsn, "checkpoint written");
+        Ok(lsn)
+    }
+
+    /// Logs a set_int operation with the old value for undo and the new one for redo
     pub fn set_int(
         &mut self,
         buff: &mut Buffer,
         offset: u32,
-        _new_val: i32,
-    ) -> std::io::Result<u32> {
+        new_val: i32,
+    ) -> std::io::Result<u64> {
         let oldval = buff.contents().get_int(offset as usize);
-        let block = buff
-            .blocks()
-            .ok_or(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                "Blocks not found",
-            ))?
-            .clone();
+        let block = Self::block_of(buff)?;
+
+        SetIntRecord::write_to_log(
+            self.lm.clone(),
+            self.txnum,
+            None,
+            block,
+            offset,
+            oldval,
+            new_val,
+        )
+    }
+
+    /// Logs a set_long operation with the old value for undo and the new one for redo
+    pub fn set_long(
+        &mut self,
+        buff: &mut Buffer,
+        offset: u32,
+        new_val: i64,
+    ) -> std::io::Result<u64> {
+        let oldval = buff.contents().get_long(offset as usize);
+        let block = Self::block_of(buff)?;
 
-        SetIntRecord::write_to_log(self.lm.clone(), self.txnum, block, offset, oldval)
+        SetLongRecord::write_to_log(
+            self.lm.clone(),
+            self.txnum,
+            None,
+            block,
+            offset,
+            oldval,
+            new_val,
+        )
     }
 
-    /// Sets the log record for a set_string operation with old value
-    /// written to the log file for recovery
+    /// Logs a set_string operation with the old value for undo and the new one for redo
     pub fn set_string(
         &mut self,
         buff: &mut Buffer,
         offset: u32,
-        _new_val: &str,
-    ) -> std::io::Result<u32> {
+        new_val: &str,
+    ) -> std::io::Result<u64> {
         let oldval = buff.contents().get_string(offset as usize);
-        let block = buff
+        let block = Self::block_of(buff)?;
+
+        SetStringRecord::write_to_log(
+            self.lm.clone(),
+            self.txnum,
+            None,
+            block,
+            offset,
+            oldval,
+            new_val.to_string(),
+        )
+    }
+
+    /// Logs the compensation record of an int put back while undoing the record at `undone`
+    /// of the transaction `txnum`, which isn't always the one undoing it during recovery
+    pub fn compensate_int(
+        &mut self,
+        buff: &mut Buffer,
+        offset: u32,
+        val: i32,
+        txnum: i64,
+        undone: u64,
+    ) -> std::io::Result<u64> {
+        let oldval = buff.contents().get_int(offset as usize);
+        let block = Self::block_of(buff)?;
+
+        SetIntRecord::write_to_log(
+            self.lm.clone(),
+            txnum,
+            Some(undone),
+            block,
+            offset,
+            oldval,
+            val,
+        )
+    }
+
+    /// Like `compensate_int` but for a long
+    pub fn compensate_long(
+        &mut self,
+        buff: &mut Buffer,
+        offset: u32,
+        val: i64,
+        txnum: i64,
+        undone: u64,
+    ) -> std::io::Result<u64> {
+        let oldval = buff.contents().get_long(offset as usize);
+        let block = Self::block_of(buff)?;
+
+        SetLongRecord::write_to_log(
+            self.lm.clone(),
+            txnum,
+            Some(undone),
+            block,
+            offset,
+            oldval,
+            val,
+        )
+    }
+
+    /// Like `compensate_int` but for a string
+    pub fn compensate_string(
+        &mut self,
+        buff: &mut Buffer,
+        offset: u32,
+        val: &str,
+        txnum: i64,
+        undone: u64,
+    ) -> std::io::Result<u64> {
+        let oldval = buff.contents().get_string(offset as usize);
+        let block = Self::block_of(buff)?;
+
+        SetStringRecord::write_to_log(
+            self.lm.clone(),
+            txnum,
+            Some(undone),
+            block,
+            offset,
+            oldval,
+            val.to_string(),
+        )
+    }
+
+    /// Logs a change of a single record with the record `RowChange::op` asks for
+    pub fn change_row(&mut self, change: &RowChange) -> std::io::Result<u64> {
+        RowRecord::write_to_log(self.lm.clone(), self.txnum, None, change)
+    }
+
+    /// Logs bytes written to a block of an overflow chain, there is nothing to undo
+    pub fn write_overflow(
+        &mut self,
+        block: &Block,
+        offset: u32,
+        bytes: &[u8],
+    ) -> std::io::Result<u64> {
+        OverflowRecord::write_to_log(self.lm.clone(), self.txnum, block, offset, bytes)
+    }
+
+    /// Like `compensate_int` but for a row change
+    pub fn compensate_row(
+        &mut self,
+        change: &RowChange,
+        txnum: i64,
+        undone: u64,
+    ) -> std::io::Result<u64> {
+        RowRecord::write_to_log(self.lm.clone(), txnum, Some(undone), change)
+    }
+
+    fn block_of(buff: &Buffer) -> std::io::Result<Block> {
+        Ok(buff
             .blocks()
             .ok_or(std::io::Error::new(
                 std::io::ErrorKind::NotFound,
                 "Blocks not found",
             ))?
-            .clone();
-
-        SetStringRecord::write_to_log(self.lm.clone(), self.txnum, block, offset, oldval)
+            .clone())
     }
 
     /// Iterates over the log file with pointer at the end and moving
     /// towards the beginning of the file. All the transactions are logged with
     /// `Start` marker so the iterator keeps iterating until the marker is found
     /// and undoes whatever that was done
-    fn do_rollback(&mut self) -> crate::error::DbResult<()> {
-        let iter = self.lm.safe_lock().iter()?;
-        let block_size = iter.block_size();
+    ///
+    /// When rolling back to a savepoint the iterator stops at its marker instead. Changes
+    /// already undone by an earlier rollback to a savepoint have a compensation record
+    /// further down the log and are skipped
+    #[tracing::instrument(level = "debug", skip(self), fields(txnum = self.txnum))]
+    fn do_rollback(&mut self, savepoint: Option<i32>) -> crate::error::DbResult<()> {
+        if self.read_only {
+            return Ok(());
+        }
 
-        for mut bytes in iter {
-            bytes.resize(block_size as usize, 0);
+        let iter = self.lm.safe_lock().iter()?;
+        let mut compensated = HashSet::<u64>::new();
 
-            let rec = create_log_record(bytes);
+        for (lsn, bytes) in iter.with_lsn() {
+            let rec = create_log_record(bytes)?;
 
-            if rec.tx_number() == self.txnum {
-                if rec.op().eq(&LogOperation::Start) {
-                    return Ok(());
-                }
+            if rec.tx_number() != self.txnum {
+                continue;
+            }
+            if rec.op().eq(&LogOperation::Start)
+                || (savepoint.is_some() && rec.savepoint() == savepoint)
+            {
+                return Ok(());
+            }
+            if let Some(undone) = rec.compensates() {
+                compensated.insert(undone);
+            } else if !compensated.contains(&lsn) {
                 rec.undo(
+                    lsn,
                     &mut self
                         .tx
                         .as_ref()
@@ -186,38 +503,121 @@ impl RecoveryManager {
         Ok(())
     }
 
-    /// Iterates over the log file with pointer at the end and moving
-    /// towards the beginning of the file. All the recovery operation is marked
-    /// with `Checkpoint` marker so once the checkpoint is found it comes out of the loop
+    /// Recovers in three passes over the records logged since the redo LSN of the last
+    /// `Checkpoint`, or since the start of the transactions it lists if that's earlier
     ///
-    /// If the Transactions are not committed or rolled back it reverses the change
-    fn do_recover(&mut self) -> crate::error::DbResult<()> {
-        let mut finished_txs = Vec::<i32>::new();
+    /// 1. Analysis goes backwards to find out which transactions finished, which are prepared
+    ///    and which are losers, i.e neither of those
+    /// 2. Redo goes forward and repeats every change whose block doesn't have it yet
+    /// 3. Undo goes backwards again and reverses the changes of the losers, logging a
+    ///    compensation record for each, then marks the losers as rolled back
+    #[tracing::instrument(level = "debug", name = "recovery", skip_all)]
+    fn do_recover(&mut self, last_checkpoint: Option<u64>) -> crate::error::DbResult<Vec<InDoubt>> {
+        let mut finished_txs = HashSet::<i64>::new();
+        let mut in_doubt = HashMap::<i64, InDoubt>::new();
+        let mut records = Vec::new();
+        // Set once the checkpoint is found along with the transactions which were running
+        // then and whose start wasn't found yet
+        let mut redo_lsn = None;
+        let mut unstarted = HashSet::<i64>::new();
         let iter = self.lm.safe_lock().iter()?;
-        let block_size = iter.block_size();
 
-        for mut byte in iter {
-            byte.resize(block_size as usize, 0);
+        for (lsn, byte) in iter.with_lsn() {
+            if redo_lsn.is_some_and(|redo_lsn| lsn < redo_lsn) && unstarted.is_empty() {
+                break;
+            }
 
-            let rec = create_log_record(byte);
+            let rec = create_log_record(byte)?;
 
             if rec.op().eq(&LogOperation::Checkpoint) {
-                return Ok(());
+                if redo_lsn.is_none() && last_checkpoint.is_none_or(|last| lsn <= last) {
+                    // Nothing before a quiescent checkpoint has to be redone
+                    redo_lsn = Some(rec.redo_lsn().unwrap_or(lsn));
+                    unstarted.extend(rec.active());
+                }
+                continue;
+            }
+            if rec.op().eq(&LogOperation::Start) {
+                unstarted.remove(&rec.tx_number());
             }
 
             if rec.op().eq(&LogOperation::Commit) || rec.op().eq(&LogOperation::Rollback) {
-                finished_txs.push(rec.tx_number());
-            } else if !finished_txs.contains(&rec.tx_number()) {
-                rec.undo(
-                    &mut self
-                        .tx
-                        .as_ref()
-                        .expect("Transactions was not initialized")
-                        .safe_lock(),
-                )?;
+                finished_txs.insert(rec.tx_number());
+            } else if finished_txs.contains(&rec.tx_number()) {
+                // Only redone
+            } else if let Some(gid) = rec.gid() {
+                // The prepare record comes after every change of the transaction, so it's
+                // found before them
+                in_doubt.insert(
+                    rec.tx_number(),
+                    InDoubt {
+                        txnum: rec.tx_number() as u64,
+                        gid,
+                        blocks: Vec::new(),
+                    },
+                );
+            } else if let Some(prepared) = in_doubt.get_mut(&rec.tx_number()) {
+                if let Some(block) = rec.block() {
+                    if !prepared.blocks.contains(&block) {
+                        prepared.blocks.push(block);
+                    }
+                }
             }
+            records.push((lsn, rec));
         }
 
-        Ok(())
+        let mut tx = self
+            .tx
+            .as_ref()
+            .expect("Transactions was not initialized")
+            .safe_lock();
+
+        let redo_span = tracing::debug_span!("redo").entered();
+        for (lsn, rec) in records.iter().rev() {
+            rec.redo(*lsn, &mut tx)?;
+        }
+        drop(redo_span);
+
+        let _undo_span = tracing::debug_span!("undo").entered();
+        let mut undone = 0;
+        let mut losers = Vec::<i64>::new();
+        let mut compensated = HashSet::<u64>::new();
+        for (lsn, rec) in &records {
+            let txnum = rec.tx_number();
+            if txnum == self.txnum || finished_txs.contains(&txnum) || in_doubt.contains_key(&txnum)
+            {
+                continue;
+            }
+
+            // The transaction never finished, so the locks it might still own would only
+            // block the undo
+            if !losers.contains(&txnum) {
+                tracing::debug!(txnum, "undoing unfinished transaction");
+                tx.forget_orphaned(txnum as u64);
+                losers.push(txnum);
+            }
+            if let Some(undone) = rec.compensates() {
+                compensated.insert(undone);
+            } else if !compensated.contains(lsn) {
+                rec.undo(*lsn, &mut tx)?;
+                undone += 1;
+            }
+        }
+
+        for loser in &losers {
+            Rollback::write_to_log(self.lm.clone(), *loser)?;
+        }
+
+        tracing::info!(
+            redone = records.len(),
+            undone,
+            transactions = losers.len(),
+            in_doubt = in_doubt.len(),
+            "undo finished"
+        );
+
+        let mut in_doubt = in_doubt.into_values().collect::<Vec<_>>();
+        in_doubt.sort_by_key(|prepared| prepared.txnum);
+        Ok(in_doubt)
     }
 }
@@ -1,27 +1,29 @@
 use crate::{
-    consts::INTEGER_BYTES, storage::disk::page::Page, storage::log::manager::LogManager,
-    storage::tx::Transactions, utils::safe_lock::SafeLock,
+    consts::{INTEGER_BYTES, LONG_BYTES},
+    storage::disk::page::Page,
+    storage::log::manager::LogManager,
+    storage::tx::Transactions,
+    utils::safe_lock::SafeLock,
 };
 
-use super::log_record::{LogOperation, RecordLog};
+use super::log_record::{LogOperation, RecordLog, RecordReader};
 
 use std::sync::{Arc, Mutex};
 
 pub struct Rollback {
-    txnum: i32,
+    txnum: i64,
 }
 
 impl Rollback {
-    pub fn new(mut page: Page) -> Self {
-        Self {
-            txnum: page.get_int(INTEGER_BYTES),
-        }
+    pub fn new(mut p: RecordReader, version: i32) -> crate::error::DbResult<Self> {
+        let (txnum, _) = p.txnum(version)?;
+        Ok(Self { txnum })
     }
 
-    pub fn write_to_log(lm: Arc<Mutex<LogManager>>, tx_num: i32) -> std::io::Result<u32> {
-        let mut page = Page::new(2 * INTEGER_BYTES as u64);
-        page.set_int(0, LogOperation::Rollback as i32);
-        page.set_int(INTEGER_BYTES, tx_num);
+    pub fn write_to_log(lm: Arc<Mutex<LogManager>>, tx_num: i64) -> std::io::Result<u64> {
+        let mut page = Page::new((INTEGER_BYTES + LONG_BYTES) as u64);
+        page.set_int(0, LogOperation::Rollback.header());
+        page.set_long(INTEGER_BYTES, tx_num);
 
         lm.safe_lock().append(page.contents())
     }
@@ -32,11 +34,11 @@ impl RecordLog for Rollback {
         LogOperation::Rollback
     }
 
-    fn tx_number(&self) -> i32 {
+    fn tx_number(&self) -> i64 {
         self.txnum
     }
 
-    fn undo(&self, _tx: &mut Transactions) -> crate::error::DbResult<()> {
+    fn undo(&self, _lsn: u64, _tx: &mut Transactions) -> crate::error::DbResult<()> {
         Ok(())
     }
 }
@@ -0,0 +1,222 @@
+use super::log_record::{LogOperation, RecordLog, RecordReader};
+use crate::{
+    common::slot::Slot,
+    consts::{INTEGER_BYTES, LONG_BYTES},
+    error::DbResult,
+    storage::disk::{block::Block, page::Page},
+    storage::log::manager::LogManager,
+    storage::record::rowid::RowId,
+    storage::tx::Transactions,
+    utils::safe_lock::SafeLock,
+};
+use std::sync::{Arc, Mutex};
+
+/// Kind of change a row level log record describes
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RowOp {
+    /// A slot was claimed for a new record or version, the record holds the whole slot
+    Insert,
+    /// The record was marked deleted
+    Delete,
+    /// Fields or the header of the record changed, only the span that differs is logged
+    Update,
+}
+
+impl RowOp {
+    fn log_operation(self) -> LogOperation {
+        match self {
+            Self::Insert => LogOperation::Insert,
+            Self::Delete => LogOperation::Delete,
+            Self::Update => LogOperation::Update,
+        }
+    }
+}
+
+/// A change of a single record. `new` is written at `offset` from the start of the slot and
+/// undoing it writes `old` back, which can be shorter, e.g only the flag of an inserted record.
+/// A `slot_size` of 0 marks a record of a slotted page, the offset is then from the start of
+/// its tuple wherever the directory of the block says it is
+#[derive(Clone, Debug)]
+pub struct RowChange {
+    pub op: RowOp,
+    /// File of the table the record belongs to
+    pub file: String,
+    pub rid: RowId,
+    pub slot_size: usize,
+    pub offset: usize,
+    pub old: Vec<u8>,
+    pub new: Vec<u8>,
+}
+
+impl RowChange {
+    pub fn is_slotted(&self) -> bool {
+        self.slot_size == 0
+    }
+
+    /// Position of the change in a block of fixed size slots
+    pub fn pos(&self) -> usize {
+        self.rid.slot().inner() * self.slot_size + self.offset
+    }
+
+    pub fn block(&self) -> Block {
+        Block::new(self.file.clone(), self.rid.blk_num())
+    }
+}
+
+/// Saves the log in the write-ahead-log in the following format
+///
+/// INSERT|DELETE|UPDATE [TRANSACTION NUM] [COMPENSATES] [FILE NAME] [BLOCK NUM] [SLOT]
+/// [SLOT SIZE] [OFFSET] [OLD] [NEW]
+///
+/// A whole row change is a single record keyed by the table and the RowId of the record, so
+/// the log tells which rows a transaction inserted, deleted or updated. Undoing one logs an
+/// `UPDATE` compensation record, which doesn't need the old bytes
+pub struct RowRecord {
+    txnum: i64,
+    compensates: Option<u64>,
+    change: RowChange,
+}
+
+impl RowRecord {
+    pub fn new(mut p: RecordReader, op: RowOp) -> DbResult<Self> {
+        let txnum_pos = INTEGER_BYTES;
+        let txnum = p.long(txnum_pos)?;
+        let compensates_pos = txnum_pos + LONG_BYTES;
+        let compensates = p.long(compensates_pos)?;
+        let file_pos = compensates_pos + LONG_BYTES;
+
+        let file = p.string(file_pos)?;
+        let blknum_pos = file_pos + Page::max_len(file.len());
+        let blk_num = p.int(blknum_pos)?;
+        let slot_pos = blknum_pos + INTEGER_BYTES;
+        let slot = p.int(slot_pos)?;
+        let slot_size_pos = slot_pos + INTEGER_BYTES;
+        let slot_size = p.int(slot_size_pos)?;
+        let offset_pos = slot_size_pos + INTEGER_BYTES;
+        let offset = p.int(offset_pos)?;
+
+        let old_pos = offset_pos + INTEGER_BYTES;
+        let old = p.bytes(old_pos)?;
+        let new_pos = old_pos + INTEGER_BYTES + old.len();
+        let new = p.bytes(new_pos)?;
+
+        Ok(Self {
+            txnum,
+            compensates: (compensates >= 0).then_some(compensates as u64),
+            change: RowChange {
+                op,
+                file,
+                rid: RowId::new(blk_num as u64, Slot::new(slot as usize)),
+                slot_size: slot_size as usize,
+                offset: offset as usize,
+                old,
+                new,
+            },
+        })
+    }
+
+    pub fn write_to_log(
+        lm: Arc<Mutex<LogManager>>,
+        tx_num: i64,
+        compensates: Option<u64>,
+        change: &RowChange,
+    ) -> std::io::Result<u64> {
+        let txnum_pos = INTEGER_BYTES;
+        let compensates_pos = txnum_pos + LONG_BYTES;
+        let file_pos = compensates_pos + LONG_BYTES;
+        let blknum_pos = file_pos + Page::max_len(change.file.len());
+        let slot_pos = blknum_pos + INTEGER_BYTES;
+        let slot_size_pos = slot_pos + INTEGER_BYTES;
+        let offset_pos = slot_size_pos + INTEGER_BYTES;
+        let old_pos = offset_pos + INTEGER_BYTES;
+        let new_pos = old_pos + INTEGER_BYTES + change.old.len();
+
+        let mut page = Page::new((new_pos + INTEGER_BYTES + change.new.len()) as u64);
+
+        page.set_int(0, change.op.log_operation().header());
+        page.set_long(txnum_pos, tx_num);
+        page.set_long(compensates_pos, compensates.map_or(-1, |lsn| lsn as i64));
+        page.set_string(file_pos, change.file.clone());
+        page.set_int(blknum_pos, change.rid.blk_num() as i32);
+        page.set_int(slot_pos, change.rid.slot().inner() as i32);
+        page.set_int(slot_size_pos, change.slot_size as i32);
+        page.set_int(offset_pos, change.offset as i32);
+        page.set_bytes(old_pos, &change.old);
+        page.set_bytes(new_pos, &change.new);
+
+        lm.safe_lock().append(page.contents())
+    }
+}
+
+impl RecordLog for RowRecord {
+    fn op(&self) -> LogOperation {
+        self.change.op.log_operation()
+    }
+
+    fn tx_number(&self) -> i64 {
+        self.txnum
+    }
+    fn block(&self) -> Option<Block> {
+        Some(self.change.block())
+    }
+    fn compensates(&self) -> Option<u64> {
+        self.compensates
+    }
+
+    fn undo(&self, lsn: u64, tx: &mut Transactions) -> DbResult<()> {
+        if self.compensates.is_some() {
+            return Ok(());
+        }
+
+        let block = self.change.block();
+        tx.pin(&block)?;
+        {
+            // The transaction still holds the locks of the records it changed
+            let _latch = tx.latch(&block);
+            let undo = RowChange {
+                op: RowOp::Update,
+                old: Vec::new(),
+                new: self.change.old.clone(),
+                ..self.change.clone()
+            };
+            tx.compensate_row_latched(&block, &undo, self.txnum, lsn)?;
+        }
+        tx.unpin(&block)?;
+        Ok(())
+    }
+
+    fn redo(&self, lsn: u64, tx: &mut Transactions) -> DbResult<()> {
+        let block = self.change.block();
+        tx.pin(&block)?;
+        {
+            let _latch = tx.latch(&block);
+            tx.redo_row_latched(&block, &self.change, lsn)?;
+        }
+        tx.unpin(&block)?;
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for RowRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let op = match self.change.op {
+            RowOp::Insert => "INSERT",
+            RowOp::Delete => "DELETE",
+            RowOp::Update => "UPDATE",
+        };
+        let change = &self.change;
+        let (blk, slot) = (change.rid.blk_num(), change.rid.slot().inner());
+        match self.compensates {
+            Some(lsn) => write!(
+                f,
+                "<CLR {op} {} {lsn} {} {blk} {slot} {} {:?} >",
+                self.txnum, change.file, change.offset, change.new
+            ),
+            None => write!(
+                f,
+                "<{op} {} {} {blk} {slot} {} {:?} {:?} >",
+                self.txnum, change.file, change.offset, change.old, change.new
+            ),
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use super::log_record::{LogOperation, RecordLog, RecordReader};
+use crate::{
+    consts::{INTEGER_BYTES, LONG_BYTES},
+    storage::disk::page::Page,
+    storage::log::manager::LogManager,
+    storage::tx::Transactions,
+    utils::safe_lock::SafeLock,
+};
+use std::sync::{Arc, Mutex};
+
+/// Marks the point of the log a transaction can roll back to without undoing everything
+///
+/// SAVEPOINT [TRANSACTION NUM] [ID] [NAME]
+///
+/// - Id: Number of the savepoint, unique within the transaction. Names can be reused so the
+///   id is what a rollback looks for
+/// - Name: Name the savepoint was created with
+pub struct SavepointLog {
+    txnum: i64,
+    id: i32,
+    name: String,
+}
+
+impl SavepointLog {
+    pub fn new(mut p: RecordReader) -> crate::error::DbResult<Self> {
+        let txnum_pos = INTEGER_BYTES;
+        let id_pos = txnum_pos + LONG_BYTES;
+        let name_pos = id_pos + INTEGER_BYTES;
+
+        Ok(Self {
+            txnum: p.long(txnum_pos)?,
+            id: p.int(id_pos)?,
+            name: p.string(name_pos)?,
+        })
+    }
+
+    pub fn write_to_log(
+        lm: Arc<Mutex<LogManager>>,
+        tx_num: i64,
+        id: i32,
+        name: String,
+    ) -> std::io::Result<u64> {
+        let txnum_pos = INTEGER_BYTES;
+        let id_pos = txnum_pos + LONG_BYTES;
+        let name_pos = id_pos + INTEGER_BYTES;
+        let reclen = name_pos + Page::max_len(name.len());
+
+        let mut page = Page::new(reclen as u64);
+        page.set_int(0, LogOperation::Savepoint.header());
+        page.set_long(txnum_pos, tx_num);
+        page.set_int(id_pos, id);
+        page.set_string(name_pos, name);
+
+        lm.safe_lock().append(page.contents())
+    }
+}
+
+impl RecordLog for SavepointLog {
+    fn op(&self) -> LogOperation {
+        LogOperation::Savepoint
+    }
+
+    fn tx_number(&self) -> i64 {
+        self.txnum
+    }
+
+    fn savepoint(&self) -> Option<i32> {
+        Some(self.id)
+    }
+
+    fn undo(&self, _lsn: u64, _tx: &mut Transactions) -> crate::error::DbResult<()> {
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for SavepointLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<SAVEPOINT {} {} {} >", self.txnum, self.id, self.name)
+    }
+}
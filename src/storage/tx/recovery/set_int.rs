@@ -1,6 +1,6 @@
-use super::log_record::{LogOperation, RecordLog};
+use super::log_record::{LogOperation, RecordLog, RecordReader};
 use crate::{
-    consts::INTEGER_BYTES,
+    consts::{INTEGER_BYTES, LONG_BYTES},
     storage::disk::{block::Block, page::Page},
     storage::log::manager::LogManager,
     storage::tx,
@@ -8,72 +8,119 @@ use crate::{
 };
 use std::sync::{Arc, Mutex};
 
+/// Like `SetStringRecord` but for the 4 byte integers
 pub struct SetIntRecord {
     offset: u32,
-    val: i32,
-    txnum: i32,
+    old: i32,
+    new: Option<i32>,
+    txnum: i64,
+    compensates: Option<u64>,
     block: Block,
 }
 
 impl SetIntRecord {
-    pub fn new(mut p: Page) -> Self {
-        let txnum_pos = INTEGER_BYTES;
-        let txnum = p.get_int(txnum_pos);
-        let file_name_pos = txnum_pos + INTEGER_BYTES;
+    pub fn new(mut p: RecordReader, version: i32) -> crate::error::DbResult<Self> {
+        let (txnum, compensates_pos) = p.txnum(version)?;
+        let (compensates, file_name_pos) = if version == 0 {
+            (-1, compensates_pos)
+        } else {
+            (p.long(compensates_pos)?, compensates_pos + LONG_BYTES)
+        };
 
-        let file_name = p.get_string(file_name_pos);
+        let file_name = p.string(file_name_pos)?;
         let blk_num_pos = file_name_pos + Page::max_len(file_name.len());
-        let blk_num = p.get_int(blk_num_pos);
+        let blk_num = p.int(blk_num_pos)?;
 
         let offset_pos = blk_num_pos + INTEGER_BYTES;
-        let offset = p.get_int(offset_pos);
-        let value_pos = offset_pos + INTEGER_BYTES;
-        let val = p.get_int(value_pos);
+        let offset = p.int(offset_pos)?;
+        let old_pos = offset_pos + INTEGER_BYTES;
+        let old = p.int(old_pos)?;
+        let new_pos = old_pos + INTEGER_BYTES;
+        let new = if version == 0 {
+            None
+        } else {
+            Some(p.int(new_pos)?)
+        };
 
-        Self {
+        Ok(Self {
             txnum,
+            compensates: (compensates >= 0).then_some(compensates as u64),
             block: Block::new(file_name, blk_num as u64),
-            val,
+            old,
+            new,
             offset: offset as u32,
-        }
+        })
     }
 
     pub fn write_to_log(
         lm: Arc<Mutex<LogManager>>,
-        tx_num: i32,
+        tx_num: i64,
+        compensates: Option<u64>,
         block: Block,
         offset: u32,
-        val: i32,
-    ) -> std::io::Result<u32> {
+        old: i32,
+        new: i32,
+    ) -> std::io::Result<u64> {
         let txnum_pos = INTEGER_BYTES;
-        let filename_pos = txnum_pos + INTEGER_BYTES;
+        let compensates_pos = txnum_pos + LONG_BYTES;
+        let filename_pos = compensates_pos + LONG_BYTES;
         let blknum_pos = filename_pos + Page::max_len(block.filename().len());
         let offset_pos = blknum_pos + INTEGER_BYTES;
-        let value_pos = offset_pos + INTEGER_BYTES;
+        let old_pos = offset_pos + INTEGER_BYTES;
+        let new_pos = old_pos + INTEGER_BYTES;
 
-        let mut page = Page::new((value_pos + INTEGER_BYTES) as u64);
+        let mut page = Page::new((new_pos + INTEGER_BYTES) as u64);
 
-        page.set_int(0, LogOperation::SetInt as i32);
-        page.set_int(txnum_pos, tx_num);
+        page.set_int(0, LogOperation::SetInt.header());
+        page.set_long(txnum_pos, tx_num);
+        page.set_long(compensates_pos, compensates.map_or(-1, |lsn| lsn as i64));
         page.set_string(filename_pos, block.filename().to_string());
         page.set_int(blknum_pos, block.num() as i32);
         page.set_int(offset_pos, offset as i32);
-        page.set_int(value_pos, val);
+        page.set_int(old_pos, old);
+        page.set_int(new_pos, new);
 
         lm.safe_lock().append(page.contents())
     }
 }
 
 impl RecordLog for SetIntRecord {
-    fn tx_number(&self) -> i32 {
+    fn tx_number(&self) -> i64 {
         self.txnum
     }
+    fn block(&self) -> Option<Block> {
+        Some(self.block.clone())
+    }
+    fn compensates(&self) -> Option<u64> {
+        self.compensates
+    }
     fn op(&self) -> LogOperation {
         LogOperation::SetInt
     }
-    fn undo(&self, tx: &mut tx::Transactions) -> crate::error::DbResult<()> {
+    fn undo(&self, lsn: u64, tx: &mut tx::Transactions) -> crate::error::DbResult<()> {
+        if self.compensates.is_some() {
+            return Ok(());
+        }
+
         tx.pin(&self.block)?;
-        tx.set_int(&self.block, self.offset, self.val, false)?;
+        {
+            // The transaction still holds the locks of the records it changed
+            let _latch = tx.latch(&self.block);
+            tx.compensate_int_latched(&self.block, self.offset, self.old, self.txnum, lsn)?;
+        }
+        tx.unpin(&self.block)?;
+        Ok(())
+    }
+    fn redo(&self, lsn: u64, tx: &mut tx::Transactions) -> crate::error::DbResult<()> {
+        // Changes of version 0 were flushed before their transaction committed
+        let Some(new) = self.new else {
+            return Ok(());
+        };
+        tx.pin(&self.block)?;
+        {
+            let _latch = tx.latch(&self.block);
+            tx.redo_int_latched(&self.block, self.offset, new, lsn)?;
+        }
         tx.unpin(&self.block)?;
         Ok(())
     }
@@ -81,10 +128,27 @@ impl RecordLog for SetIntRecord {
 
 impl std::fmt::Display for SetIntRecord {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "<SETINT {} {} {} {} >",
-            self.txnum, self.block, self.offset, self.val
-        )
+        match self.compensates {
+            Some(lsn) => write!(
+                f,
+                "<CLR SETINT {} {} {} {} {} >",
+                self.txnum,
+                lsn,
+                self.block,
+                self.offset,
+                self.new.unwrap_or_default()
+            ),
+            None => {
+                write!(
+                    f,
+                    "<SETINT {} {} {} {} ",
+                    self.txnum, self.block, self.offset, self.old
+                )?;
+                match self.new {
+                    Some(new) => write!(f, "{new} >"),
+                    None => write!(f, ">"),
+                }
+            }
+        }
     }
 }
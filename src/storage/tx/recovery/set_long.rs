@@ -0,0 +1,136 @@
+use super::log_record::{LogOperation, RecordLog, RecordReader};
+use crate::{
+    consts::{INTEGER_BYTES, LONG_BYTES},
+    storage::disk::{block::Block, page::Page},
+    storage::log::manager::LogManager,
+    storage::tx,
+    utils::safe_lock::SafeLock,
+};
+use std::sync::{Arc, Mutex};
+
+/// Like `SetIntRecord` but for the 8 byte values, e.g the transaction numbers stored in the
+/// headers of the records
+pub struct SetLongRecord {
+    offset: u32,
+    old: i64,
+    new: i64,
+    txnum: i64,
+    compensates: Option<u64>,
+    block: Block,
+}
+
+impl SetLongRecord {
+    pub fn new(mut p: RecordReader) -> crate::error::DbResult<Self> {
+        let txnum_pos = INTEGER_BYTES;
+        let txnum = p.long(txnum_pos)?;
+        let compensates_pos = txnum_pos + LONG_BYTES;
+        let compensates = p.long(compensates_pos)?;
+        let file_name_pos = compensates_pos + LONG_BYTES;
+
+        let file_name = p.string(file_name_pos)?;
+        let blk_num_pos = file_name_pos + Page::max_len(file_name.len());
+        let blk_num = p.int(blk_num_pos)?;
+
+        let offset_pos = blk_num_pos + INTEGER_BYTES;
+        let offset = p.int(offset_pos)?;
+        let old_pos = offset_pos + INTEGER_BYTES;
+        let old = p.long(old_pos)?;
+        let new_pos = old_pos + LONG_BYTES;
+        let new = p.long(new_pos)?;
+
+        Ok(Self {
+            txnum,
+            compensates: (compensates >= 0).then_some(compensates as u64),
+            block: Block::new(file_name, blk_num as u64),
+            old,
+            new,
+            offset: offset as u32,
+        })
+    }
+
+    pub fn write_to_log(
+        lm: Arc<Mutex<LogManager>>,
+        tx_num: i64,
+        compensates: Option<u64>,
+        block: Block,
+        offset: u32,
+        old: i64,
+        new: i64,
+    ) -> std::io::Result<u64> {
+        let txnum_pos = INTEGER_BYTES;
+        let compensates_pos = txnum_pos + LONG_BYTES;
+        let filename_pos = compensates_pos + LONG_BYTES;
+        let blknum_pos = filename_pos + Page::max_len(block.filename().len());
+        let offset_pos = blknum_pos + INTEGER_BYTES;
+        let old_pos = offset_pos + INTEGER_BYTES;
+        let new_pos = old_pos + LONG_BYTES;
+
+        let mut page = Page::new((new_pos + LONG_BYTES) as u64);
+
+        page.set_int(0, LogOperation::SetLong.header());
+        page.set_long(txnum_pos, tx_num);
+        page.set_long(compensates_pos, compensates.map_or(-1, |lsn| lsn as i64));
+        page.set_string(filename_pos, block.filename().to_string());
+        page.set_int(blknum_pos, block.num() as i32);
+        page.set_int(offset_pos, offset as i32);
+        page.set_long(old_pos, old);
+        page.set_long(new_pos, new);
+
+        lm.safe_lock().append(page.contents())
+    }
+}
+
+impl RecordLog for SetLongRecord {
+    fn tx_number(&self) -> i64 {
+        self.txnum
+    }
+    fn block(&self) -> Option<Block> {
+        Some(self.block.clone())
+    }
+    fn compensates(&self) -> Option<u64> {
+        self.compensates
+    }
+    fn op(&self) -> LogOperation {
+        LogOperation::SetLong
+    }
+    fn undo(&self, lsn: u64, tx: &mut tx::Transactions) -> crate::error::DbResult<()> {
+        if self.compensates.is_some() {
+            return Ok(());
+        }
+
+        tx.pin(&self.block)?;
+        {
+            // The transaction still holds the locks of the records it changed
+            let _latch = tx.latch(&self.block);
+            tx.compensate_long_latched(&self.block, self.offset, self.old, self.txnum, lsn)?;
+        }
+        tx.unpin(&self.block)?;
+        Ok(())
+    }
+    fn redo(&self, lsn: u64, tx: &mut tx::Transactions) -> crate::error::DbResult<()> {
+        tx.pin(&self.block)?;
+        {
+            let _latch = tx.latch(&self.block);
+            tx.redo_long_latched(&self.block, self.offset, self.new, lsn)?;
+        }
+        tx.unpin(&self.block)?;
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for SetLongRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.compensates {
+            Some(lsn) => write!(
+                f,
+                "<CLR SETLONG {} {} {} {} {} >",
+                self.txnum, lsn, self.block, self.offset, self.new
+            ),
+            None => write!(
+                f,
+                "<SETLONG {} {} {} {} {} >",
+                self.txnum, self.block, self.offset, self.old, self.new
+            ),
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use crate::{
-    consts::INTEGER_BYTES,
+    consts::{INTEGER_BYTES, LONG_BYTES},
     error::DbResult,
     storage::disk::{block::Block, page::Page},
     storage::log::manager::LogManager,
@@ -8,71 +8,95 @@ use crate::{
 };
 use std::sync::{Arc, Mutex};
 
-use super::log_record::{LogOperation, RecordLog};
+use super::log_record::{LogOperation, RecordLog, RecordReader};
 
 /// Saves the log in the write-ahead-log in the following format
 ///
-/// SETSTRING [TRANSACTION NUM] [FILE NAME] [BLOCK NUM] [OFFSET] [VALUE]
+/// SETSTRING [TRANSACTION NUM] [COMPENSATES] [FILE NAME] [BLOCK NUM] [OFFSET] [OLD] [NEW]
 ///
 /// - Operation: Operation that has been performed by the database like SetInt, SetString
 /// - Transaction number: A unique number given to the transaction
+/// - Compensates: LSN of the record this one undoes, or -1. Compensation records are written
+///   while rolling back, they are redone after a crash but never undone themselves
 /// - File name: Name of the file database just changed
 /// - Block number: If the file is divided into N equally sized blocks, the block database modified
 /// - Offset: Offset from the start of the block which database changed
-/// - Value: The value being written to log
+/// - Old: The value before the change, restored by undo
+/// - New: The value after the change, reapplied by redo
+///
+/// Records of version 0 have neither the compensated LSN nor the new value
 pub struct SetStringRecord {
-    txnum: i32,
-    val: String,
+    txnum: i64,
+    compensates: Option<u64>,
+    old: String,
+    new: Option<String>,
     block: Block,
     offset: u32,
 }
 
 impl SetStringRecord {
-    pub fn new(mut p: Page) -> Self {
-        let txnum_pos = INTEGER_BYTES;
-        let txnum = p.get_int(txnum_pos);
-        let filename_pos = txnum_pos + INTEGER_BYTES;
+    pub fn new(mut p: RecordReader, version: i32) -> DbResult<Self> {
+        let (txnum, compensates_pos) = p.txnum(version)?;
+        let (compensates, filename_pos) = if version == 0 {
+            (-1, compensates_pos)
+        } else {
+            (p.long(compensates_pos)?, compensates_pos + LONG_BYTES)
+        };
 
-        let file_name = p.get_string(filename_pos);
+        let file_name = p.string(filename_pos)?;
         let blknum_pos = filename_pos + Page::max_len(file_name.len());
-        let blk_num = p.get_int(blknum_pos);
+        let blk_num = p.int(blknum_pos)?;
 
         let offset_pos = blknum_pos + INTEGER_BYTES;
-        let offset = p.get_int(offset_pos);
-        let value_pos = offset_pos + INTEGER_BYTES;
-        let val = p.get_string(value_pos);
+        let offset = p.int(offset_pos)?;
+        let old_pos = offset_pos + INTEGER_BYTES;
+        let old = p.string(old_pos)?;
+        let new_pos = old_pos + Page::max_len(old.len());
+        let new = if version == 0 {
+            None
+        } else {
+            Some(p.string(new_pos)?)
+        };
 
-        Self {
+        Ok(Self {
             txnum,
+            compensates: (compensates >= 0).then_some(compensates as u64),
             block: Block::new(file_name, blk_num as u64),
-            val,
+            old,
+            new,
             offset: offset as u32,
-        }
+        })
     }
 
     pub fn write_to_log(
         lm: Arc<Mutex<LogManager>>,
-        tx_num: i32,
+        tx_num: i64,
+        compensates: Option<u64>,
         block: Block,
         offset: u32,
-        val: String,
-    ) -> std::io::Result<u32> {
+        old: String,
+        new: String,
+    ) -> std::io::Result<u64> {
         let txnum_pos = INTEGER_BYTES;
-        let filename_pos = txnum_pos + INTEGER_BYTES;
+        let compensates_pos = txnum_pos + LONG_BYTES;
+        let filename_pos = compensates_pos + LONG_BYTES;
         let blknum_pos = filename_pos + Page::max_len(block.filename().len());
         let offset_pos = blknum_pos + INTEGER_BYTES;
-        let value_pos = offset_pos + INTEGER_BYTES;
+        let old_pos = offset_pos + INTEGER_BYTES;
+        let new_pos = old_pos + Page::max_len(old.len());
 
-        let reclen = value_pos + Page::max_len(val.len());
+        let reclen = new_pos + Page::max_len(new.len());
 
         let mut page = Page::new(reclen as u64);
 
-        page.set_int(0, LogOperation::SetString as i32);
-        page.set_int(txnum_pos, tx_num);
+        page.set_int(0, LogOperation::SetString.header());
+        page.set_long(txnum_pos, tx_num);
+        page.set_long(compensates_pos, compensates.map_or(-1, |lsn| lsn as i64));
         page.set_string(filename_pos, block.filename().to_string());
         page.set_int(blknum_pos, block.num() as i32);
         page.set_int(offset_pos, offset as i32);
-        page.set_string(value_pos, val);
+        page.set_string(old_pos, old);
+        page.set_string(new_pos, new);
 
         lm.safe_lock().append(page.contents())
     }
@@ -83,13 +107,47 @@ impl RecordLog for SetStringRecord {
         LogOperation::SetString
     }
 
-    fn tx_number(&self) -> i32 {
+    fn tx_number(&self) -> i64 {
         self.txnum
     }
+    fn block(&self) -> Option<Block> {
+        Some(self.block.clone())
+    }
+    fn compensates(&self) -> Option<u64> {
+        self.compensates
+    }
+
+    fn undo(&self, lsn: u64, tx: &mut Transactions) -> DbResult<()> {
+        if self.compensates.is_some() {
+            return Ok(());
+        }
 
-    fn undo(&self, tx: &mut Transactions) -> DbResult<()> {
         tx.pin(&self.block)?;
-        tx.set_string(&self.block, self.offset, self.val.clone(), false)?;
+        {
+            // The transaction still holds the locks of the records it changed
+            let _latch = tx.latch(&self.block);
+            tx.compensate_string_latched(
+                &self.block,
+                self.offset,
+                self.old.clone(),
+                self.txnum,
+                lsn,
+            )?;
+        }
+        tx.unpin(&self.block)?;
+        Ok(())
+    }
+
+    fn redo(&self, lsn: u64, tx: &mut Transactions) -> DbResult<()> {
+        // Changes of version 0 were flushed before their transaction committed
+        let Some(new) = &self.new else {
+            return Ok(());
+        };
+        tx.pin(&self.block)?;
+        {
+            let _latch = tx.latch(&self.block);
+            tx.redo_string_latched(&self.block, self.offset, new.clone(), lsn)?;
+        }
         tx.unpin(&self.block)?;
         Ok(())
     }
@@ -97,10 +155,27 @@ impl RecordLog for SetStringRecord {
 
 impl std::fmt::Display for SetStringRecord {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "<SETSTRING {} {} {} {} >",
-            self.txnum, self.block, self.offset, self.val
-        )
+        match self.compensates {
+            Some(lsn) => write!(
+                f,
+                "<CLR SETSTRING {} {} {} {} {} >",
+                self.txnum,
+                lsn,
+                self.block,
+                self.offset,
+                self.new.as_deref().unwrap_or_default()
+            ),
+            None => {
+                write!(
+                    f,
+                    "<SETSTRING {} {} {} {} ",
+                    self.txnum, self.block, self.offset, self.old
+                )?;
+                match &self.new {
+                    Some(new) => write!(f, "{new} >"),
+                    None => write!(f, ">"),
+                }
+            }
+        }
     }
 }
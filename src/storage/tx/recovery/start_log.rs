@@ -1,41 +1,46 @@
-use super::log_record::{LogOperation, RecordLog};
+use super::log_record::{LogOperation, RecordLog, RecordReader};
 use crate::{
-    consts::INTEGER_BYTES, storage::disk::page::Page, storage::log::manager::LogManager,
-    storage::tx, utils::safe_lock::SafeLock,
+    consts::{INTEGER_BYTES, LONG_BYTES},
+    storage::disk::page::Page,
+    storage::log::manager::LogManager,
+    storage::tx,
+    utils::safe_lock::SafeLock,
 };
 use std::sync::{Arc, Mutex};
 
 pub struct StartLog {
-    txnum: i32,
+    txnum: i64,
 }
 
 impl StartLog {
-    pub fn new(mut page: Page) -> Self {
-        let txnum_pos = INTEGER_BYTES;
-        Self {
-            txnum: page.get_int(txnum_pos),
-        }
+    pub fn new(mut p: RecordReader, version: i32) -> crate::error::DbResult<Self> {
+        let (txnum, _) = p.txnum(version)?;
+        Ok(Self { txnum })
     }
 
-    pub fn write_to_log(lm: Arc<Mutex<LogManager>>, tx_num: i32) -> std::io::Result<u32> {
-        let mut page = Page::new(2 * INTEGER_BYTES as u64);
+    pub fn write_to_log(lm: Arc<Mutex<LogManager>>, tx_num: i64) -> std::io::Result<u64> {
+        let mut page = Page::new((INTEGER_BYTES + LONG_BYTES) as u64);
 
-        page.set_int(0, LogOperation::Start as i32);
-        page.set_int(INTEGER_BYTES, tx_num);
+        page.set_int(0, LogOperation::Start.header());
+        page.set_long(INTEGER_BYTES, tx_num);
 
-        lm.safe_lock().append(page.contents())
+        // Every record of the transaction follows its start, so the log keeps the numbering
+        // past it
+        let mut lm = lm.safe_lock();
+        lm.advance_txnum(tx_num as u64 + 1);
+        lm.append(page.contents())
     }
 }
 
 impl RecordLog for StartLog {
-    fn undo(&self, _tx: &mut tx::Transactions) -> crate::error::DbResult<()> {
+    fn undo(&self, _lsn: u64, _tx: &mut tx::Transactions) -> crate::error::DbResult<()> {
         Ok(())
     }
 
     fn op(&self) -> LogOperation {
         LogOperation::Start
     }
-    fn tx_number(&self) -> i32 {
+    fn tx_number(&self) -> i64 {
         self.txnum
     }
 }
@@ -1,14 +1,48 @@
 #[cfg(test)]
 mod tests {
     use std::{
+        collections::HashMap,
         fs,
         sync::{Arc, Mutex},
+        time::{Duration, SystemTime},
     };
 
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
     use crate::{
+        common::slot::Slot,
+        consts::{INTEGER_BYTES, LOG_FILE},
+        error::{DbError, DbResult},
         server::{DBServer, DBServerOptions},
         storage::bufferpool::pool::BufferPoolManager,
-        storage::disk::{block::Block, manager::Manager, page::Page},
+        storage::disk::{
+            block::Block,
+            fault::{CrashPoint, FaultInjector},
+            manager::Manager,
+            page::Page,
+        },
+        storage::log::manager::HEADER_BYTES,
+        storage::record::{
+            layout::Layout,
+            rowid::RowId,
+            scan::{Scan, TableScan, UpdateScan},
+            schema::Schema,
+        },
+        storage::tx::recovery::{
+            checkpoint::Checkpoint,
+            commit_log::CommitLog,
+            log_record::{create_log_record, LogOperation, LOG_VERSION},
+            overflow::OverflowRecord,
+            prepare::PrepareLog,
+            rollback::Rollback,
+            row::{RowChange, RowOp, RowRecord},
+            savepoint::SavepointLog,
+            set_int::SetIntRecord,
+            set_long::SetLongRecord,
+            set_string::SetStringRecord,
+            start_log::StartLog,
+        },
+        storage::tx::{LockMode, LockTarget},
     };
 
     #[test]
@@ -36,6 +70,819 @@ mod tests {
         fs::remove_dir_all("recoverytest").unwrap();
     }
 
+    #[test]
+    fn savepointtest() {
+        let db = DBServer::new_with_params(
+            DBServerOptions::default()
+                .block_size(400)
+                .directory("savepointtest".to_string())
+                .pool_size(8),
+        )
+        .unwrap();
+        let blk = Block::new("testfile".to_string(), 0);
+
+        let mut tx = db.new_tx().unwrap();
+        tx.pin(&blk).unwrap();
+        tx.set_int(&blk, 0, 1, true).unwrap();
+        tx.set_string(&blk, 30, "abc".to_string(), true).unwrap();
+
+        tx.savepoint("first").unwrap();
+        tx.set_int(&blk, 0, 2, true).unwrap();
+        tx.set_string(&blk, 30, "def".to_string(), true).unwrap();
+
+        tx.savepoint("second").unwrap();
+        tx.set_int(&blk, 0, 3, true).unwrap();
+
+        tx.rollback_to("second").unwrap();
+        assert_eq!(tx.get_int(&blk, 0).unwrap(), 2);
+        assert_eq!(tx.get_string(&blk, 30).unwrap(), "def");
+
+        // Rolling back to the same savepoint again only undoes what was done after it
+        tx.set_int(&blk, 0, 4, true).unwrap();
+        tx.rollback_to("second").unwrap();
+        assert_eq!(tx.get_int(&blk, 0).unwrap(), 2);
+
+        tx.rollback_to("first").unwrap();
+        assert_eq!(tx.get_int(&blk, 0).unwrap(), 1);
+        assert_eq!(tx.get_string(&blk, 30).unwrap(), "abc");
+        assert!(matches!(
+            tx.rollback_to("second"),
+            Err(DbError::SavepointNotFound(_))
+        ));
+        assert_eq!(
+            tx.lock_mode(&LockTarget::Block(blk.clone())),
+            Some(LockMode::Exclusive)
+        );
+
+        tx.set_int(&blk, 0, 5, true).unwrap();
+        tx.release("first").unwrap();
+        assert!(matches!(
+            tx.rollback_to("first"),
+            Err(DbError::SavepointNotFound(_))
+        ));
+        tx.commit().unwrap();
+
+        let mut tx = db.new_tx().unwrap();
+        tx.pin(&blk).unwrap();
+        assert_eq!(tx.get_int(&blk, 0).unwrap(), 5);
+        assert_eq!(tx.get_string(&blk, 30).unwrap(), "abc");
+
+        // A full rollback still undoes everything done after a savepoint was rolled back to
+        tx.savepoint("first").unwrap();
+        tx.set_int(&blk, 0, 6, true).unwrap();
+        tx.rollback_to("first").unwrap();
+        tx.set_int(&blk, 0, 7, true).unwrap();
+        tx.rollback().unwrap();
+
+        let mut tx = db.new_tx().unwrap();
+        tx.pin(&blk).unwrap();
+        assert_eq!(tx.get_int(&blk, 0).unwrap(), 5);
+        tx.commit().unwrap();
+
+        fs::remove_dir_all("savepointtest").unwrap();
+    }
+
+    #[test]
+    fn preparetest() {
+        let options = || {
+            DBServerOptions::default()
+                .block_size(400)
+                .directory("preparetest".to_string())
+                .pool_size(8)
+                .lock_timeout(Duration::from_millis(100))
+        };
+        let blk0 = Block::new("testfile".to_string(), 0);
+        let blk1 = Block::new("testfile".to_string(), 1);
+        let blk2 = Block::new("testfile".to_string(), 2);
+
+        let db = DBServer::new_with_params(options()).unwrap();
+        let mut tx = db.new_tx().unwrap();
+        for blk in [&blk0, &blk1, &blk2] {
+            tx.pin(blk).unwrap();
+            tx.set_int(blk, 0, 1, true).unwrap();
+        }
+        tx.commit().unwrap();
+
+        let mut tx1 = db.new_tx().unwrap();
+        tx1.pin(&blk0).unwrap();
+        tx1.set_int(&blk0, 0, 2, true).unwrap();
+        tx1.prepare("first").unwrap();
+        assert_eq!(tx1.gid(), Some("first"));
+        assert!(matches!(
+            tx1.set_int(&blk0, 0, 3, true),
+            Err(DbError::TransactionPrepared(_))
+        ));
+        assert!(matches!(
+            tx1.prepare("other"),
+            Err(DbError::TransactionPrepared(_))
+        ));
+        assert!(matches!(
+            db.abort_tx(tx1.txnum()),
+            Err(DbError::TransactionPrepared(_))
+        ));
+
+        let mut tx2 = db.new_tx().unwrap();
+        tx2.pin(&blk1).unwrap();
+        tx2.set_int(&blk1, 0, 2, true).unwrap();
+        assert!(matches!(
+            tx2.prepare("first"),
+            Err(DbError::DuplicateGid(_))
+        ));
+        tx2.prepare("second").unwrap();
+
+        // Not prepared, recovery undoes it
+        let mut tx3 = db.new_tx().unwrap();
+        tx3.pin(&blk2).unwrap();
+        tx3.set_int(&blk2, 0, 2, true).unwrap();
+        db.buffer_manager()
+            .lock()
+            .unwrap()
+            .flush_all(tx3.txnum() as i64)
+            .unwrap();
+        drop(db);
+
+        let db = DBServer::new_with_params(options()).unwrap();
+        let fm = db.file_manager();
+        db.new_tx().unwrap().recover().unwrap();
+        assert_eq!(db.prepared(), vec!["first", "second"]);
+        assert_eq!(read_int(&fm, &blk0), 2);
+        assert_eq!(read_int(&fm, &blk1), 2);
+        assert_eq!(read_int(&fm, &blk2), 1);
+
+        // The prepared transactions got their locks back
+        let infos = db.transactions();
+        assert_eq!(infos.len(), 2);
+        assert_eq!(infos[0].gid.as_deref(), Some("first"));
+        assert!(infos[0]
+            .locks
+            .contains(&(LockTarget::Block(blk0.clone()), LockMode::Exclusive)));
+
+        let mut tx = db.new_tx().unwrap();
+        tx.pin(&blk0).unwrap();
+        assert!(matches!(tx.get_int(&blk0, 0), Err(DbError::LockAborted)));
+        tx.rollback().unwrap();
+
+        db.commit_prepared("first").unwrap();
+        db.rollback_prepared("second").unwrap();
+        assert!(matches!(
+            db.commit_prepared("first"),
+            Err(DbError::PreparedTransactionNotFound(_))
+        ));
+        assert!(db.prepared().is_empty());
+        assert!(db.transactions().is_empty());
+
+        let mut tx = db.new_tx().unwrap();
+        tx.pin(&blk0).unwrap();
+        tx.pin(&blk1).unwrap();
+        assert_eq!(tx.get_int(&blk0, 0).unwrap(), 2);
+        assert_eq!(tx.get_int(&blk1, 0).unwrap(), 1);
+        tx.commit().unwrap();
+        drop(db);
+
+        // Both are resolved, the next recovery has nothing left in doubt
+        let db = DBServer::new_with_params(options()).unwrap();
+        db.new_tx().unwrap().recover().unwrap();
+        assert!(db.prepared().is_empty());
+        assert_eq!(read_int(&db.file_manager(), &blk0), 2);
+        assert_eq!(read_int(&db.file_manager(), &blk1), 1);
+
+        fs::remove_dir_all("preparetest").unwrap();
+    }
+
+    #[test]
+    fn redotest() {
+        let options = || {
+            DBServerOptions::default()
+                .block_size(400)
+                .directory("redotest".to_string())
+                .pool_size(8)
+        };
+        let blk0 = Block::new("testfile".to_string(), 0);
+        let blk1 = Block::new("testfile".to_string(), 1);
+
+        let db = DBServer::new_with_params(options()).unwrap();
+        let mut tx1 = db.new_tx().unwrap();
+        let mut tx2 = db.new_tx().unwrap();
+        tx1.pin(&blk0).unwrap();
+        // Pinned before the buffer of blk0 is free, which would otherwise get reused
+        tx2.pin(&blk1).unwrap();
+        tx1.set_int(&blk0, 0, 1, true).unwrap();
+        tx1.set_string(&blk0, 30, "abc".to_string(), true).unwrap();
+        tx1.commit().unwrap();
+
+        // A loser whose change reached the disk before the crash
+        tx2.set_int(&blk1, 0, 2, true).unwrap();
+        db.buffer_manager()
+            .lock()
+            .unwrap()
+            .flush_all(tx2.txnum() as i64)
+            .unwrap();
+
+        // Crash, nothing gets written back
+        std::mem::forget(tx1);
+        std::mem::forget(tx2);
+        std::mem::forget(db);
+
+        let db = DBServer::new_with_params(options()).unwrap();
+        let fm = db.file_manager();
+        assert_eq!(read_int(&fm, &blk0), 0);
+        assert_eq!(read_int(&fm, &blk1), 2);
+
+        db.new_tx().unwrap().recover().unwrap();
+        assert_eq!(read_int(&fm, &blk0), 1);
+        assert_eq!(read_int(&fm, &blk1), 0);
+
+        let mut page = Page::new(fm.blocksize());
+        fm.read(&blk0, &mut page).unwrap();
+        assert_eq!(page.get_string(30), "abc");
+
+        // The undo was logged with a compensation record, the loser is marked rolled back
+        let iter = db.log_manager().lock().unwrap().iter().unwrap();
+        let records = iter
+            .map(|bytes| create_log_record(bytes).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            records
+                .iter()
+                .filter(|rec| rec.compensates().is_some())
+                .count(),
+            1
+        );
+        assert!(records.iter().any(|rec| rec.op() == LogOperation::Rollback));
+        drop(db);
+
+        // Running recovery again changes nothing
+        let db = DBServer::new_with_params(options()).unwrap();
+        db.new_tx().unwrap().recover().unwrap();
+        assert_eq!(read_int(&db.file_manager(), &blk0), 1);
+        assert_eq!(read_int(&db.file_manager(), &blk1), 0);
+
+        fs::remove_dir_all("redotest").unwrap();
+    }
+
+    #[test]
+    fn checkpointtest() {
+        let options = || {
+            DBServerOptions::default()
+                .block_size(400)
+                .directory("checkpointtest".to_string())
+                .pool_size(8)
+        };
+        let blk0 = Block::new("testfile".to_string(), 0);
+        let blk1 = Block::new("testfile".to_string(), 1);
+        let blk2 = Block::new("testfile".to_string(), 2);
+
+        let db = DBServer::new_with_params(options()).unwrap();
+        let mut tx1 = db.new_tx().unwrap();
+        let mut tx2 = db.new_tx().unwrap();
+        let mut tx3 = db.new_tx().unwrap();
+        // Pinned before any buffer is free, which would otherwise get reused
+        tx1.pin(&blk0).unwrap();
+        tx2.pin(&blk1).unwrap();
+        tx3.pin(&blk2).unwrap();
+
+        // Running during the checkpoint, its change reached the disk before it
+        tx2.set_int(&blk1, 0, 2, true).unwrap();
+        db.buffer_manager()
+            .lock()
+            .unwrap()
+            .flush_all(tx2.txnum() as i64)
+            .unwrap();
+
+        // Committed before the checkpoint but never flushed
+        tx1.set_int(&blk0, 0, 1, true).unwrap();
+        tx1.commit().unwrap();
+
+        db.checkpoint().unwrap();
+
+        tx3.set_int(&blk2, 0, 3, true).unwrap();
+        tx3.commit().unwrap();
+
+        // Crash, nothing gets written back
+        std::mem::forget(tx2);
+        std::mem::forget(tx3);
+        std::mem::forget(db);
+
+        let db = DBServer::new_with_params(options()).unwrap();
+        let fm = db.file_manager();
+        db.new_tx().unwrap().recover().unwrap();
+        assert_eq!(read_int(&fm, &blk0), 1);
+        assert_eq!(read_int(&fm, &blk1), 0);
+        assert_eq!(read_int(&fm, &blk2), 3);
+        drop(db);
+
+        // The checkpoint thread keeps logging checkpoints
+        let db =
+            DBServer::new_with_params(options().checkpoint_interval(Duration::from_millis(10)))
+                .unwrap();
+        let checkpoints = || {
+            let iter = db.log_manager().lock().unwrap().iter().unwrap();
+            iter.filter(|bytes| {
+                create_log_record(bytes.clone()).unwrap().op() == LogOperation::Checkpoint
+            })
+            .count()
+        };
+        let before = checkpoints();
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(checkpoints() > before);
+        drop(db);
+
+        fs::remove_dir_all("checkpointtest").unwrap();
+    }
+
+    #[test]
+    fn restarttest() {
+        let options = || {
+            DBServerOptions::default()
+                .block_size(400)
+                .directory("restarttest".to_string())
+                .pool_size(8)
+        };
+        let blk = Block::new("testfile".to_string(), 0);
+
+        let run = |db: &DBServer| {
+            let mut tx = db.new_tx().unwrap();
+            tx.pin(&blk).unwrap();
+            tx.set_int(&blk, 0, tx.txnum() as i32, true).unwrap();
+            tx.commit().unwrap();
+            tx.txnum()
+        };
+        let restart = |db: DBServer| {
+            drop(db);
+            DBServer::new_with_params(options()).unwrap()
+        };
+
+        // The log has no checkpoint and spans several blocks, the numbering continues from
+        // the header of its last block
+        let db = DBServer::new_with_params(options()).unwrap();
+        let mut last = 0;
+        for _ in 0..20 {
+            last = run(&db);
+        }
+        let lm = db.log_manager();
+        let latest = lm.lock().unwrap().latest_lsn();
+        assert!(lm
+            .lock()
+            .unwrap()
+            .iter()
+            .unwrap()
+            .all(|bytes| { create_log_record(bytes).unwrap().op() != LogOperation::Checkpoint }));
+        drop(lm);
+        assert!(db.file_manager().size(LOG_FILE).unwrap() > 2);
+
+        let db = restart(db);
+        assert_eq!(db.log_manager().lock().unwrap().latest_lsn(), latest);
+        assert_eq!(run(&db), last + 1);
+        let latest = db.log_manager().lock().unwrap().latest_lsn();
+
+        // A block of the log appended right before a crash is never written
+        let db = restart(db);
+        db.file_manager().append(LOG_FILE).unwrap();
+        let db = restart(db);
+        assert_eq!(db.log_manager().lock().unwrap().latest_lsn(), latest);
+        assert_eq!(run(&db), last + 2);
+        drop(db);
+
+        fs::remove_dir_all("restarttest").unwrap();
+    }
+
+    #[test]
+    fn rowlogtest() {
+        let options = || {
+            DBServerOptions::default()
+                .block_size(400)
+                .directory("rowlogtest".to_string())
+                .pool_size(8)
+        };
+        let mut sch = Schema::new();
+        sch.add_int_field("A".to_string());
+        sch.add_string_field("B".to_string(), 9);
+        let layout = Layout::new(sch).unwrap();
+
+        let db = DBServer::new_with_params(options()).unwrap();
+        let ops = |db: &DBServer, txnum: u64| {
+            let iter = db.log_manager().lock().unwrap().iter().unwrap();
+            iter.map(|bytes| create_log_record(bytes).unwrap())
+                .filter(|rec| rec.tx_number() == txnum as i64)
+                .map(|rec| rec.op())
+                .collect::<Vec<_>>()
+        };
+
+        let mut tx1 = db.new_tx().unwrap();
+        let mut ts = TableScan::new(tx1.clone(), "T".to_string(), layout.clone()).unwrap();
+        ts.insert().unwrap();
+        ts.set_int("A", 7).unwrap();
+        ts.set_string("B", "rec7".to_string()).unwrap();
+        drop(ts);
+        tx1.commit().unwrap();
+
+        // One record claims the slot and one per setter with its null bit
+        let logged = ops(&db, tx1.txnum());
+        let count = |op: LogOperation| logged.iter().filter(|o| **o == op).count();
+        assert_eq!(count(LogOperation::Insert), 1);
+        assert_eq!(count(LogOperation::Update), 2);
+        assert_eq!(count(LogOperation::SetInt), 0);
+        assert_eq!(count(LogOperation::SetString), 0);
+
+        let mut tx2 = db.new_tx().unwrap();
+        let mut ts = TableScan::new(tx2.clone(), "T".to_string(), layout.clone()).unwrap();
+        assert!(ts.next().unwrap());
+        ts.delete().unwrap();
+        drop(ts);
+        assert_eq!(
+            ops(&db, tx2.txnum())
+                .iter()
+                .filter(|op| **op == LogOperation::Delete)
+                .count(),
+            1
+        );
+        tx2.rollback().unwrap();
+
+        let mut tx3 = db.new_tx().unwrap();
+        let mut ts = TableScan::new(tx3.clone(), "T".to_string(), layout.clone()).unwrap();
+        ts.insert().unwrap();
+        ts.set_int("A", 8).unwrap();
+        ts.set_string("B", "rec8".to_string()).unwrap();
+        drop(ts);
+        tx3.commit().unwrap();
+
+        // Crash, nothing gets written back
+        std::mem::forget(tx1);
+        std::mem::forget(tx2);
+        std::mem::forget(tx3);
+        std::mem::forget(db);
+
+        let db = DBServer::new_with_params(options()).unwrap();
+        db.new_tx().unwrap().recover().unwrap();
+
+        let mut tx = db.new_tx().unwrap();
+        let mut ts = TableScan::new(tx.clone(), "T".to_string(), layout).unwrap();
+        let mut rows = Vec::new();
+        while ts.next().unwrap() {
+            rows.push((ts.get_int("A").unwrap(), ts.get_string("B").unwrap()));
+        }
+        drop(ts);
+        tx.commit().unwrap();
+        assert_eq!(rows, vec![(7, "rec7".to_string()), (8, "rec8".to_string())]);
+        drop(db);
+
+        fs::remove_dir_all("rowlogtest").unwrap();
+    }
+
+    #[test]
+    fn crashtest() {
+        // A failing run is repeated by setting CRASHTEST_SEED to the seed it printed
+        let seed = std::env::var("CRASHTEST_SEED")
+            .map(|seed| seed.parse().expect("CRASHTEST_SEED is not a number"))
+            .unwrap_or_else(|_| rand::rng().random());
+        println!("crashtest seed {seed}");
+        let mut rng = StdRng::seed_from_u64(seed);
+        let blocks = (0..6)
+            .map(|num| Block::new("testfile".to_string(), num))
+            .collect::<Vec<_>>();
+
+        for _ in 0..20 {
+            let crash_point = if rng.random_bool(0.5) {
+                CrashPoint::BlockWrite(rng.random_range(1..=20))
+            } else {
+                CrashPoint::LogFlush(rng.random_range(1..=30))
+            };
+            let mut faults = FaultInjector::new(crash_point);
+            if rng.random_bool(0.5) {
+                faults = faults.torn();
+            }
+            let faults = Arc::new(faults);
+            let options = || {
+                DBServerOptions::default()
+                    .block_size(400)
+                    .directory("crashtest".to_string())
+                    .pool_size(3)
+            };
+
+            // Created up front, so the crash happens in the middle of the workload
+            drop(DBServer::new_with_params(options()).unwrap());
+
+            // Transactions run one after another until the crash, the values are unique so
+            // the ones of lost transactions can't show up by chance
+            let db = DBServer::new_with_params(options().fault_injector(faults.clone())).unwrap();
+            let mut committed = HashMap::new();
+            let mut next_val = 0;
+            let mut workload = || -> DbResult<()> {
+                for _ in 0..30 {
+                    let mut tx = db.new_tx()?;
+                    let mut written = Vec::new();
+                    for _ in 0..rng.random_range(1..=3) {
+                        let blk = &blocks[rng.random_range(0..blocks.len())];
+                        let offset = rng.random_range(0..10) * 4;
+                        next_val += 1;
+                        tx.pin(blk)?;
+                        tx.set_int(blk, offset, next_val, true)?;
+                        tx.unpin(blk)?;
+                        written.push(((blk.num(), offset), next_val));
+                    }
+
+                    if rng.random_bool(0.8) {
+                        tx.commit()?;
+                        committed.extend(written);
+                    } else {
+                        tx.rollback()?;
+                    }
+                }
+
+                // Still running when the database goes down
+                let mut tx = db.new_tx()?;
+                tx.pin(&blocks[0])?;
+                tx.set_int(&blocks[0], 0, -1, true)?;
+                std::mem::forget(tx);
+                Ok(())
+            };
+            if workload().is_err() {
+                assert!(
+                    faults.crashed(),
+                    "failed before {crash_point:?} with seed {seed}"
+                );
+            }
+            std::mem::forget(db);
+
+            let db = DBServer::new_with_params(options()).unwrap();
+            db.new_tx().unwrap().recover().unwrap();
+            let fm = db.file_manager();
+            for blk in &blocks {
+                let mut page = Page::new(fm.blocksize());
+                fm.read(blk, &mut page).unwrap();
+                for offset in (0..10).map(|i| i * 4) {
+                    let expected = committed.get(&(blk.num(), offset)).copied().unwrap_or(0);
+                    assert_eq!(
+                        page.get_int(offset as usize),
+                        expected,
+                        "{blk} at {offset} after {crash_point:?} with seed {seed}"
+                    );
+                }
+            }
+            drop(db);
+
+            fs::remove_dir_all("crashtest").unwrap();
+        }
+    }
+
+    #[test]
+    fn logrecordtest() {
+        let db = DBServer::new_with_params(
+            DBServerOptions::default()
+                .block_size(400)
+                .directory("logrecordtest".to_string())
+                .pool_size(8),
+        )
+        .unwrap();
+        let lm = db.log_manager();
+        let blk = Block::new("testfile".to_string(), 3);
+        let change = RowChange {
+            op: RowOp::Insert,
+            file: "T.tbl".to_string(),
+            rid: RowId::new(2, Slot::new(5)),
+            slot_size: 40,
+            offset: 4,
+            old: vec![1, 2],
+            new: vec![3, 4, 5],
+        };
+
+        StartLog::write_to_log(lm.clone(), 7).unwrap();
+        CommitLog::write_to_log(lm.clone(), 7).unwrap();
+        Rollback::write_to_log(lm.clone(), 7).unwrap();
+        Checkpoint::write_to_log(lm.clone(), 12, &[3, 7]).unwrap();
+        SavepointLog::write_to_log(lm.clone(), 7, 2, "sp".to_string()).unwrap();
+        PrepareLog::write_to_log(lm.clone(), 7, "gid".to_string()).unwrap();
+        SetIntRecord::write_to_log(lm.clone(), 7, None, blk.clone(), 8, 1, -2).unwrap();
+        SetIntRecord::write_to_log(lm.clone(), 7, Some(9), blk.clone(), 8, 0, 1).unwrap();
+        SetLongRecord::write_to_log(lm.clone(), 7, None, blk.clone(), 16, 1, 1 << 40).unwrap();
+        SetLongRecord::write_to_log(lm.clone(), 7, Some(9), blk.clone(), 16, 0, 1).unwrap();
+        SetStringRecord::write_to_log(
+            lm.clone(),
+            7,
+            None,
+            blk.clone(),
+            30,
+            "abc".to_string(),
+            "de".to_string(),
+        )
+        .unwrap();
+        SetStringRecord::write_to_log(
+            lm.clone(),
+            7,
+            Some(9),
+            blk.clone(),
+            30,
+            String::new(),
+            "abc".to_string(),
+        )
+        .unwrap();
+        RowRecord::write_to_log(lm.clone(), 7, None, &change).unwrap();
+        for op in [RowOp::Delete, RowOp::Update] {
+            let change = RowChange {
+                op,
+                ..change.clone()
+            };
+            RowRecord::write_to_log(lm.clone(), 7, None, &change).unwrap();
+        }
+        RowRecord::write_to_log(lm.clone(), 7, Some(9), &change).unwrap();
+        OverflowRecord::write_to_log(lm.clone(), 7, &blk, 4, &[1, 2, 3]).unwrap();
+
+        let iter = lm.lock().unwrap().iter().unwrap();
+        let block_size = iter.block_size() as usize;
+        let mut records = iter
+            .map(|bytes| create_log_record(bytes).unwrap())
+            .collect::<Vec<_>>();
+        records.reverse();
+
+        let expected = [
+            (LogOperation::Start, "<START 7 >"),
+            (LogOperation::Commit, "<COMMIT 7 >"),
+            (LogOperation::Rollback, "<ROLLBACK 7 >"),
+            (LogOperation::Checkpoint, "<CHECKPOINT 12 [3, 7]>"),
+            (LogOperation::Savepoint, "<SAVEPOINT 7 2 sp >"),
+            (LogOperation::Prepare, "<PREPARE 7 gid >"),
+            (
+                LogOperation::SetInt,
+                "<SETINT 7 [file testfile block 3] 8 1 -2 >",
+            ),
+            (
+                LogOperation::SetInt,
+                "<CLR SETINT 7 9 [file testfile block 3] 8 1 >",
+            ),
+            (
+                LogOperation::SetLong,
+                "<SETLONG 7 [file testfile block 3] 16 1 1099511627776 >",
+            ),
+            (
+                LogOperation::SetLong,
+                "<CLR SETLONG 7 9 [file testfile block 3] 16 1 >",
+            ),
+            (
+                LogOperation::SetString,
+                "<SETSTRING 7 [file testfile block 3] 30 abc de >",
+            ),
+            (
+                LogOperation::SetString,
+                "<CLR SETSTRING 7 9 [file testfile block 3] 30 abc >",
+            ),
+            (
+                LogOperation::Insert,
+                "<INSERT 7 T.tbl 2 5 4 [1, 2] [3, 4, 5] >",
+            ),
+            (
+                LogOperation::Delete,
+                "<DELETE 7 T.tbl 2 5 4 [1, 2] [3, 4, 5] >",
+            ),
+            (
+                LogOperation::Update,
+                "<UPDATE 7 T.tbl 2 5 4 [1, 2] [3, 4, 5] >",
+            ),
+            (
+                LogOperation::Insert,
+                "<CLR INSERT 7 9 T.tbl 2 5 4 [3, 4, 5] >",
+            ),
+            (
+                LogOperation::Overflow,
+                "<OVERFLOW 7 [file testfile block 3] 4 [1, 2, 3] >",
+            ),
+        ];
+        assert_eq!(records.len(), expected.len());
+        for (rec, (op, display)) in records.iter().zip(expected) {
+            assert_eq!(rec.op(), op);
+            assert_eq!(rec.to_string(), display);
+        }
+        assert_eq!(records[3].redo_lsn(), Some(12));
+        assert_eq!(records[3].active(), vec![3, 7]);
+        assert_eq!(records[4].savepoint(), Some(2));
+        assert_eq!(records[5].gid().as_deref(), Some("gid"));
+        assert_eq!(records[6].block(), Some(blk.clone()));
+        assert_eq!(records[7].compensates(), Some(9));
+        assert!(records[1]
+            .commit_time()
+            .is_some_and(|at| at <= SystemTime::now()));
+        assert_eq!(records[12].block(), Some(change.block()));
+        assert_eq!(records[16].block(), Some(blk.clone()));
+
+        // The bytes of an overflow record fill a block of the log
+        let most = OverflowRecord::max_bytes(block_size, &blk);
+        OverflowRecord::write_to_log(lm.clone(), 7, &blk, 4, &vec![1; most]).unwrap();
+        let mut iter = lm.lock().unwrap().iter().unwrap();
+        let bytes = iter.next().unwrap();
+        assert_eq!(bytes.len(), block_size - HEADER_BYTES - INTEGER_BYTES);
+        assert_eq!(
+            create_log_record(bytes).unwrap().op(),
+            LogOperation::Overflow
+        );
+
+        // Unknown operations and versions are reported instead of decoded
+        let record = |header: i32| create_log_record(header.to_be_bytes().to_vec());
+        assert!(matches!(
+            record((LOG_VERSION << 16) | 99),
+            Err(DbError::UnknownLogOperation(99))
+        ));
+        assert!(matches!(
+            record(((LOG_VERSION + 1) << 16) | LogOperation::Commit as i32),
+            Err(DbError::UnsupportedLogVersion(v)) if v == LOG_VERSION + 1
+        ));
+        assert!(matches!(
+            record(-1 << 16 | LogOperation::Commit as i32),
+            Err(DbError::UnsupportedLogVersion(-1))
+        ));
+        assert!(matches!(
+            create_log_record(vec![1]),
+            Err(DbError::CorruptLogRecord(1))
+        ));
+
+        // Commit records of version 1 have no time
+        let mut commit = ((1 << 16) | LogOperation::Commit as i32)
+            .to_be_bytes()
+            .to_vec();
+        commit.extend_from_slice(&7i64.to_be_bytes());
+        let commit = create_log_record(commit).unwrap();
+        assert_eq!(commit.to_string(), "<COMMIT 7 >");
+        assert_eq!(commit.commit_time(), None);
+
+        // Records written before the header had a version have the baseline layouts
+        let ints = |ints: &[i32]| {
+            ints.iter()
+                .flat_map(|i| i.to_be_bytes())
+                .collect::<Vec<_>>()
+        };
+        let string = |s: &str| [ints(&[s.len() as i32]), s.as_bytes().to_vec()].concat();
+        let file = string("testfile");
+        let baseline = [
+            (ints(&[LogOperation::Checkpoint as i32]), "<CHECKPOINT>"),
+            (ints(&[LogOperation::Start as i32, 7]), "<START 7 >"),
+            (ints(&[LogOperation::Commit as i32, 7]), "<COMMIT 7 >"),
+            (ints(&[LogOperation::Rollback as i32, 7]), "<ROLLBACK 7 >"),
+            (
+                [
+                    ints(&[LogOperation::SetInt as i32, 7]),
+                    file.clone(),
+                    ints(&[3, 8, 1]),
+                ]
+                .concat(),
+                "<SETINT 7 [file testfile block 3] 8 1 >",
+            ),
+            (
+                [
+                    ints(&[LogOperation::SetString as i32, 7]),
+                    file.clone(),
+                    ints(&[3, 30]),
+                    string("abc"),
+                ]
+                .concat(),
+                "<SETSTRING 7 [file testfile block 3] 30 abc >",
+            ),
+        ];
+        for (bytes, display) in baseline {
+            let rec = create_log_record(bytes).unwrap();
+            assert_eq!(rec.to_string(), display);
+            assert_eq!(rec.commit_time(), None);
+            assert_eq!(rec.compensates(), None);
+            assert_eq!(rec.redo_lsn(), None);
+        }
+        assert!(matches!(
+            create_log_record(ints(&[LogOperation::SetLong as i32, 7])),
+            Err(DbError::UnknownLogOperation(op)) if op == LogOperation::SetLong as i32
+        ));
+
+        // Fields running past the end of the record or strings which aren't UTF-8 make it
+        // corrupt
+        let header = |op: LogOperation| op.header().to_be_bytes().to_vec();
+        let mut truncated = header(LogOperation::Rollback);
+        truncated.extend_from_slice(&[0; 4]);
+        let mut long_string = header(LogOperation::Prepare);
+        long_string.extend_from_slice(&7i64.to_be_bytes());
+        long_string.extend_from_slice(&100i32.to_be_bytes());
+        let mut not_utf8 = header(LogOperation::Prepare);
+        not_utf8.extend_from_slice(&7i64.to_be_bytes());
+        not_utf8.extend_from_slice(&2i32.to_be_bytes());
+        not_utf8.extend_from_slice(&[0xff, 0xfe]);
+        let mut negative = header(LogOperation::Checkpoint);
+        negative.extend_from_slice(&12i64.to_be_bytes());
+        negative.extend_from_slice(&(-1i32).to_be_bytes());
+        let mut many = header(LogOperation::Checkpoint);
+        many.extend_from_slice(&12i64.to_be_bytes());
+        many.extend_from_slice(&i32::MAX.to_be_bytes());
+        for bytes in [truncated, long_string, not_utf8, negative, many] {
+            let len = bytes.len();
+            assert!(matches!(
+                create_log_record(bytes),
+                Err(DbError::CorruptLogRecord(l)) if l == len
+            ));
+        }
+        drop(db);
+
+        fs::remove_dir_all("logrecordtest").unwrap();
+    }
+
+    fn read_int(fm: &Manager, blk: &Block) -> i32 {
+        let mut page = Page::new(fm.blocksize());
+        fm.read(blk, &mut page).unwrap();
+        page.get_int(0)
+    }
+
     fn initialize(db: &DBServer, fm: Arc<Manager>, blk0: &Block, blk1: &Block) {
         let mut tx1 = db.new_tx().unwrap();
         let mut tx2 = db.new_tx().unwrap();
@@ -52,6 +899,8 @@ mod tests {
         tx2.set_string(blk1, 30, "def".to_string(), false).unwrap();
         tx1.commit().unwrap();
         tx2.commit().unwrap();
+        // Committing only forces the log, the blocks are written when the pool evicts them
+        db.buffer_manager().lock().unwrap().flush_dirty().unwrap();
 
         print_values(fm.clone(), "After initialization", blk0, blk1);
         assert_values(
@@ -83,8 +932,8 @@ mod tests {
         }
         tx3.set_string(blk0, 30, "uvw".to_string(), true).unwrap();
         tx4.set_string(blk1, 30, "xyz".to_string(), true).unwrap();
-        bm.lock().unwrap().flush_all(tx3.txnum() as i32).unwrap();
-        bm.lock().unwrap().flush_all(tx4.txnum() as i32).unwrap();
+        bm.lock().unwrap().flush_all(tx3.txnum() as i64).unwrap();
+        bm.lock().unwrap().flush_all(tx4.txnum() as i64).unwrap();
 
         print_values(fm.clone(), "After modification", blk0, blk1);
         tx3.rollback().unwrap();
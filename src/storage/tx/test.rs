@@ -1,11 +1,29 @@
 #[cfg(test)]
 mod tests {
-    use std::fs;
+    use std::{
+        fs,
+        sync::{Arc, Mutex},
+        thread,
+        time::Duration,
+    };
+
+    use tracing::{
+        field::{Field, Visit},
+        span::{Attributes, Id},
+        Event, Subscriber,
+    };
+    use tracing_subscriber::{layer::Context, prelude::*, Layer};
 
     use crate::{
-        server::{DBServer, DBServerOptions},
+        error::DbError,
+        server::{DBServer, DBServerOptions, TxOptions},
         storage::disk::block::Block,
-        storage::tx::Transactions,
+        storage::record::{
+            layout::Layout,
+            scan::{RowImpl, Scan, TableScan, UpdateScan},
+            schema::Schema,
+        },
+        storage::tx::{DeadlockPolicy, LockMode, LockTarget, Transactions, TxActivity},
     };
 
     #[test]
@@ -23,14 +41,28 @@ mod tests {
 
         let bm = db.buffer_manager();
 
-        let mut tx1 = Transactions::new(fm.clone(), bm.clone(), lm.clone()).unwrap();
+        let mut tx1 = Transactions::new(
+            fm.clone(),
+            bm.clone(),
+            lm.clone(),
+            db.tx_manager(),
+            DeadlockPolicy::default(),
+        )
+        .unwrap();
         let blk = Block::new("testfile".to_string(), 1);
         tx1.pin(&blk).unwrap();
         tx1.set_int(&blk, 80, 1, false).unwrap();
         tx1.set_string(&blk, 40, "one".to_string(), false).unwrap();
         tx1.commit().unwrap();
 
-        let mut tx2 = Transactions::new(fm.clone(), bm.clone(), lm.clone()).unwrap();
+        let mut tx2 = Transactions::new(
+            fm.clone(),
+            bm.clone(),
+            lm.clone(),
+            db.tx_manager(),
+            DeadlockPolicy::default(),
+        )
+        .unwrap();
         tx2.pin(&blk).unwrap();
         let ival = tx2.get_int(&blk, 80).unwrap();
         let sval = tx2.get_string(&blk, 40).unwrap();
@@ -41,7 +73,14 @@ mod tests {
         tx2.set_int(&blk, 80, newival, true).unwrap();
         tx2.set_string(&blk, 40, newsval, true).unwrap();
         tx2.commit().unwrap();
-        let mut tx3 = Transactions::new(fm.clone(), bm.clone(), lm.clone()).unwrap();
+        let mut tx3 = Transactions::new(
+            fm.clone(),
+            bm.clone(),
+            lm.clone(),
+            db.tx_manager(),
+            DeadlockPolicy::default(),
+        )
+        .unwrap();
         tx3.pin(&blk).unwrap();
         assert_eq!(2, tx3.get_int(&blk, 80).unwrap());
         assert_eq!("one!", tx3.get_string(&blk, 40).unwrap());
@@ -49,11 +88,403 @@ mod tests {
         assert_eq!(9999, tx3.get_int(&blk, 80).unwrap());
         tx3.rollback().unwrap();
 
-        let mut tx4 = Transactions::new(fm.clone(), bm.clone(), lm.clone()).unwrap();
+        let mut tx4 = Transactions::new(
+            fm.clone(),
+            bm.clone(),
+            lm.clone(),
+            db.tx_manager(),
+            DeadlockPolicy::default(),
+        )
+        .unwrap();
         tx4.pin(&blk).unwrap();
         assert_eq!(2, tx4.get_int(&blk, 80).unwrap());
         tx4.commit().unwrap();
 
         fs::remove_dir_all("txtest").unwrap();
     }
+
+    #[test]
+    fn readonlytest() {
+        let db = DBServer::new_with_params(
+            DBServerOptions::default()
+                .block_size(400)
+                .directory("readonlytest".to_string())
+                .pool_size(8)
+                .lock_timeout(Duration::from_millis(100)),
+        )
+        .unwrap();
+        let lm = db.log_manager();
+
+        let mut sch = Schema::new();
+        sch.add_int_field("A".to_string());
+        let layout = Layout::new(sch).unwrap();
+
+        let mut tx = db.new_tx().unwrap();
+        let mut ts = TableScan::new(tx.clone(), "readonly".to_string(), layout.clone()).unwrap();
+        ts.insert().unwrap();
+        ts.set_int("A", 1).unwrap();
+        let rid = ts.get_row_id();
+        ts.close().unwrap();
+        tx.commit().unwrap();
+
+        // The writer keeps the record locked, the read only transaction reads around it
+        let mut writer = db.new_tx().unwrap();
+        let mut ws =
+            TableScan::new(writer.clone(), "readonly".to_string(), layout.clone()).unwrap();
+        ws.move_to_row_id(rid.clone()).unwrap();
+        ws.set_int("A", 2).unwrap();
+
+        let records = lm.lock().unwrap().iter().unwrap().count();
+
+        let mut reader = db.new_tx_with(TxOptions::default().read_only()).unwrap();
+        assert!(reader.is_read_only());
+        let mut rs =
+            TableScan::new(reader.clone(), "readonly".to_string(), layout.clone()).unwrap();
+        rs.move_to_row_id(rid.clone()).unwrap();
+        assert_eq!(rs.get_int("A").unwrap(), 1);
+        assert!(matches!(
+            rs.set_int("A", 3),
+            Err(DbError::ReadOnlyTransaction(_))
+        ));
+        assert!(matches!(rs.delete(), Err(DbError::ReadOnlyTransaction(_))));
+        assert!(matches!(rs.insert(), Err(DbError::ReadOnlyTransaction(_))));
+        assert!(matches!(
+            reader.lock(
+                &LockTarget::File("readonly.tbl".to_string()),
+                LockMode::Exclusive
+            ),
+            Err(DbError::ReadOnlyTransaction(_))
+        ));
+        assert_eq!(
+            reader.lock_mode(&LockTarget::Record("readonly.tbl".to_string(), rid.clone())),
+            None
+        );
+        rs.close().unwrap();
+        reader.commit().unwrap();
+
+        // Nothing was logged for the read only transaction
+        assert_eq!(lm.lock().unwrap().iter().unwrap().count(), records);
+
+        ws.close().unwrap();
+        writer.commit().unwrap();
+
+        fs::remove_dir_all("readonlytest").unwrap();
+    }
+
+    #[test]
+    fn txidtest() {
+        let options = || {
+            DBServerOptions::default()
+                .block_size(400)
+                .directory("txidtest".to_string())
+                .pool_size(8)
+        };
+        let blk = Block::new("testfile".to_string(), 0);
+
+        let db = DBServer::new_with_params(options()).unwrap();
+        let mut last = 0;
+        for _ in 0..3 {
+            let mut tx = db.new_tx().unwrap();
+            tx.pin(&blk).unwrap();
+            tx.set_int(&blk, 0, tx.txnum() as i32, true).unwrap();
+            last = tx.txnum();
+            tx.commit().unwrap();
+        }
+        drop(db);
+
+        // After a restart the numbers continue after the ones found in the log
+        let db = DBServer::new_with_params(options()).unwrap();
+        let mut tx = db.new_tx().unwrap();
+        assert!(tx.txnum() > last);
+
+        tx.pin(&blk).unwrap();
+        tx.set_int(&blk, 0, -1, true).unwrap();
+        tx.rollback().unwrap();
+
+        let mut tx = db.new_tx().unwrap();
+        tx.pin(&blk).unwrap();
+        assert_eq!(tx.get_int(&blk, 0).unwrap(), last as i32);
+        tx.commit().unwrap();
+
+        fs::remove_dir_all("txidtest").unwrap();
+    }
+
+    #[test]
+    fn registrytest() {
+        let db = DBServer::new_with_params(
+            DBServerOptions::default()
+                .block_size(400)
+                .directory("registrytest".to_string())
+                .pool_size(8),
+        )
+        .unwrap();
+        let blk = Block::new("testfile".to_string(), 0);
+
+        let mut tx = db.new_tx().unwrap();
+        tx.pin(&blk).unwrap();
+        tx.set_int(&blk, 0, 1, true).unwrap();
+        tx.commit().unwrap();
+        assert!(db.transactions().is_empty());
+
+        let mut tx1 = db.new_tx().unwrap();
+        tx1.pin(&blk).unwrap();
+        tx1.set_int(&blk, 0, 2, true).unwrap();
+
+        // The reader waits for the block tx1 has written
+        let mut tx2 = db.new_tx().unwrap();
+        let txnum2 = tx2.txnum();
+        let block = blk.clone();
+        let reader = thread::spawn(move || {
+            tx2.pin(&block).unwrap();
+            let val = tx2.get_int(&block, 0);
+            tx2.commit().unwrap();
+            val
+        });
+
+        let waiting = |db: &DBServer, txnum: u64| {
+            while !db
+                .transactions()
+                .iter()
+                .any(|info| info.txnum == txnum && info.activity == TxActivity::WaitingForLock)
+            {
+                thread::sleep(Duration::from_millis(10));
+            }
+        };
+        waiting(&db, txnum2);
+
+        let infos = db.transactions();
+        assert_eq!(infos.len(), 2);
+        let info = &infos[0];
+        assert_eq!(info.txnum, tx1.txnum());
+        assert_eq!(info.activity, TxActivity::Running);
+        assert!(info
+            .locks
+            .contains(&(LockTarget::Block(blk.clone()), LockMode::Exclusive)));
+        assert_eq!(info.pinned, vec![blk.clone()]);
+
+        // Aborting tx1 undoes its change and lets the reader through
+        db.abort_tx(tx1.txnum()).unwrap();
+        assert_eq!(reader.join().unwrap().unwrap(), 1);
+
+        assert!(matches!(
+            tx1.set_int(&blk, 0, 3, true),
+            Err(DbError::TransactionAborted(_))
+        ));
+        assert!(matches!(tx1.commit(), Err(DbError::TransactionAborted(_))));
+        tx1.rollback().unwrap();
+
+        // A transaction waiting for a lock is woken up by the abort
+        let mut tx3 = db.new_tx().unwrap();
+        tx3.pin(&blk).unwrap();
+        tx3.set_int(&blk, 0, 3, true).unwrap();
+
+        let mut tx4 = db.new_tx().unwrap();
+        let txnum4 = tx4.txnum();
+        let block = blk.clone();
+        let writer = thread::spawn(move || {
+            tx4.pin(&block).unwrap();
+            let result = tx4.set_int(&block, 0, 4, true);
+            tx4.rollback().unwrap();
+            result
+        });
+        waiting(&db, txnum4);
+
+        db.abort_tx(txnum4).unwrap();
+        assert!(matches!(
+            writer.join().unwrap(),
+            Err(DbError::TransactionAborted(_))
+        ));
+        tx3.commit().unwrap();
+
+        assert!(matches!(
+            db.abort_tx(txnum4),
+            Err(DbError::TransactionNotFound(_))
+        ));
+
+        // Scans of an aborted transaction fail instead of panicking
+        let mut sch = Schema::new();
+        sch.add_int_field("A".to_string());
+        let tx5 = db.new_tx().unwrap();
+        let mut ts =
+            TableScan::new(tx5.clone(), "T".to_string(), Layout::new(sch).unwrap()).unwrap();
+        db.abort_tx(tx5.txnum()).unwrap();
+        assert!(matches!(
+            ts.before_first(),
+            Err(DbError::TransactionAborted(_))
+        ));
+        assert!(db.transactions().is_empty());
+
+        fs::remove_dir_all("registrytest").unwrap();
+    }
+
+    #[test]
+    fn guardtest() {
+        let db = DBServer::new_with_params(
+            DBServerOptions::default()
+                .block_size(400)
+                .directory("guardtest".to_string())
+                .pool_size(8)
+                .lock_timeout(Duration::from_millis(100))
+                .transaction_retries(2),
+        )
+        .unwrap();
+        let blk = Block::new("testfile".to_string(), 0);
+        let read = |db: &DBServer| {
+            let mut tx = db.begin().unwrap();
+            tx.pin(&blk).unwrap();
+            let val = tx.get_int(&blk, 0).unwrap();
+            tx.commit().unwrap();
+            val
+        };
+
+        let mut tx = db.begin().unwrap();
+        tx.pin(&blk).unwrap();
+        tx.set_int(&blk, 0, 1, true).unwrap();
+        tx.commit().unwrap();
+
+        // Dropped on the error path, the change is rolled back and the locks are released
+        let write = |db: &DBServer| -> Result<(), DbError> {
+            let mut tx = db.begin()?;
+            tx.pin(&blk)?;
+            tx.set_int(&blk, 0, 2, true)?;
+            tx.rollback_to("missing")?;
+            tx.commit()
+        };
+        assert!(matches!(write(&db), Err(DbError::SavepointNotFound(_))));
+        assert!(db.transactions().is_empty());
+        assert_eq!(read(&db), 1);
+
+        // Retried after losing the lock to another transaction
+        let mut blocker = db.new_tx().unwrap();
+        blocker.pin(&blk).unwrap();
+        blocker.set_int(&blk, 0, 5, true).unwrap();
+
+        let mut attempts = 0;
+        let val = db
+            .transaction(|tx| {
+                attempts += 1;
+                tx.pin(&blk)?;
+                let val = tx.get_int(&blk, 0);
+                if attempts == 1 {
+                    blocker.rollback()?;
+                }
+                let val = val?;
+                tx.set_int(&blk, 0, val + 1, true)?;
+                Ok(val)
+            })
+            .unwrap();
+        assert_eq!(attempts, 2);
+        assert_eq!(val, 1);
+        assert_eq!(read(&db), 2);
+
+        // Other errors are not retried
+        let mut attempts = 0;
+        let result = db.transaction(|tx| {
+            attempts += 1;
+            tx.pin(&blk)?;
+            tx.set_int(&blk, 0, 3, true)?;
+            tx.rollback_to("missing")
+        });
+        assert!(matches!(result, Err(DbError::SavepointNotFound(_))));
+        assert_eq!(attempts, 1);
+
+        // Gives up once the retries are used up
+        let mut blocker = db.new_tx().unwrap();
+        blocker.pin(&blk).unwrap();
+        blocker.set_int(&blk, 0, 5, true).unwrap();
+
+        let mut attempts = 0;
+        let result = db.transaction(|tx| {
+            attempts += 1;
+            tx.pin(&blk)?;
+            tx.get_int(&blk, 0)
+        });
+        assert!(matches!(result, Err(DbError::LockAborted)));
+        assert_eq!(attempts, 3);
+        blocker.rollback().unwrap();
+
+        assert_eq!(read(&db), 2);
+        assert!(db.transactions().is_empty());
+
+        fs::remove_dir_all("guardtest").unwrap();
+    }
+
+    /// Records the names of the spans and the messages of the events
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl<S: Subscriber> Layer<S> for Recorder {
+        fn on_new_span(&self, attrs: &Attributes<'_>, _id: &Id, _ctx: Context<'_, S>) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(attrs.metadata().name().to_string());
+        }
+
+        fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+            struct Message<'a>(&'a mut Vec<String>);
+            impl Visit for Message<'_> {
+                fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                    if field.name() == "message" {
+                        self.0.push(format!("{value:?}"));
+                    }
+                }
+            }
+            event.record(&mut Message(&mut self.0.lock().unwrap()));
+        }
+    }
+
+    #[test]
+    fn tracingtest() {
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+        let _subscriber = tracing_subscriber::registry()
+            .with(Recorder(recorded.clone()))
+            .set_default();
+
+        let db = DBServer::new_with_params(
+            DBServerOptions::default()
+                .block_size(400)
+                .directory("tracingtest".to_string())
+                .pool_size(8)
+                .lock_timeout(Duration::from_millis(100)),
+        )
+        .unwrap();
+        let blk = Block::new("testfile".to_string(), 0);
+
+        let mut tx1 = db.new_tx().unwrap();
+        tx1.pin(&blk).unwrap();
+        tx1.set_int(&blk, 0, 1, true).unwrap();
+
+        let mut tx2 = db.new_tx().unwrap();
+        tx2.pin(&blk).unwrap();
+        assert!(matches!(tx2.get_int(&blk, 0), Err(DbError::LockAborted)));
+        tx2.rollback().unwrap();
+        tx1.commit().unwrap();
+
+        db.new_tx().unwrap().recover().unwrap();
+
+        let recorded = recorded.lock().unwrap();
+        for name in [
+            "transaction",
+            "pin",
+            "lock_wait",
+            "log_flush",
+            "commit",
+            "rollback",
+            "recover",
+            "undo",
+        ] {
+            assert!(recorded.iter().any(|r| r == name), "no {name} span");
+        }
+        for message in [
+            "transaction started",
+            "lock request failed",
+            "transaction committed",
+            "transaction rolled back",
+            "undo finished",
+        ] {
+            assert!(recorded.iter().any(|r| r == message), "no {message} event");
+        }
+
+        fs::remove_dir_all("tracingtest").unwrap();
+    }
 }
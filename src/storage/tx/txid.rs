@@ -0,0 +1,31 @@
+use crate::{storage::log::manager::LogManager, utils::safe_lock::SafeLock};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
+
+/// Hands out the transaction numbers of a server
+///
+/// Numbers are never reused, not even across restarts. On startup the generator continues
+/// after the highest number logged, which the log keeps in the header of its blocks. Otherwise
+/// new transactions could be mistaken for the ones already logged by recovery and rollback.
+pub struct TxIdGenerator {
+    next: AtomicU64,
+}
+
+impl TxIdGenerator {
+    pub fn new(first: u64) -> Self {
+        Self {
+            next: AtomicU64::new(first),
+        }
+    }
+
+    /// Creates a generator continuing after the transactions found in the log
+    pub fn recover(lm: Arc<Mutex<LogManager>>) -> Self {
+        Self::new(lm.safe_lock().next_txnum())
+    }
+
+    pub fn next(&self) -> u64 {
+        self.next.fetch_add(1, Ordering::SeqCst)
+    }
+}
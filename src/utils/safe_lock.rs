@@ -10,7 +10,7 @@ impl<T> SafeLock<T> for Mutex<T> {
         match self.lock() {
             Ok(r) => r,
             Err(e) => {
-                eprintln!("Mutex lock poisoned recovering ..");
+                tracing::warn!("mutex lock poisoned, recovering");
 
                 let val = e.into_inner();
                 self.clear_poison();